@@ -121,6 +121,7 @@ export function DetailedMatrix({ matrix }: ConformanceMatrixProps) {
     tier2_interactive: [],
     tier3_rich_output: [],
     tier4_advanced: [],
+    benchmark: [],
   };
 
   // Build category mapping from first report that has each test
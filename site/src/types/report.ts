@@ -36,7 +36,8 @@ export type TestCategory =
   | 'tier1_basic'
   | 'tier2_interactive'
   | 'tier3_rich_output'
-  | 'tier4_advanced';
+  | 'tier4_advanced'
+  | 'benchmark';
 
 /** Human-readable tier descriptions */
 export const TIER_DESCRIPTIONS: Record<TestCategory, string> = {
@@ -44,6 +45,7 @@ export const TIER_DESCRIPTIONS: Record<TestCategory, string> = {
   tier2_interactive: 'Interactive Features',
   tier3_rich_output: 'Rich Output',
   tier4_advanced: 'Advanced Features',
+  benchmark: 'Benchmarks',
 };
 
 /** Tier numbers for sorting */
@@ -52,6 +54,7 @@ export const TIER_NUMBERS: Record<TestCategory, number> = {
   tier2_interactive: 2,
   tier3_rich_output: 3,
   tier4_advanced: 4,
+  benchmark: 5,
 };
 
 /** Status of a test result */
@@ -95,6 +98,14 @@ export interface TestRecord {
   result: TestResult;
   /** How long the test took in milliseconds */
   duration: number;
+  /** Whether this test came from a user-supplied spec */
+  custom?: boolean;
+  /** Measurements recorded by benchmark tests (e.g., "p95_ms") */
+  metrics?: Record<string, number>;
+  /** Why a benchmark that still passed was flagged as slow */
+  flagged?: string;
+  /** A shell reply without parent_header was accepted during this test */
+  missing_parent_header?: boolean;
 }
 
 /** Report for a single kernel's conformance test run */
@@ -109,19 +120,35 @@ export interface KernelReport {
   protocol_version: string;
   /** Individual test results */
   results: TestRecord[];
-  /** When the test run started (ISO 8601) */
+  /** Identifier shared by every report produced in one invocation */
+  run_id?: string;
+  /** When the test run started (UTC RFC 3339) */
   timestamp: string;
   /** Total duration of test run in milliseconds */
   total_duration: number;
   /** Error that prevented tests from running (e.g., kernel startup failed) */
   startup_error?: string;
+  /** Per-tier requirements this report was judged against (e.g., "tier1=100") */
+  requirements?: string[];
+  /** State-leakage diagnostics, present when --check-isolation was used */
+  isolation?: IsolationReport;
+}
+
+/** Which tests changed kernel state between runs (diagnostic only) */
+export interface IsolationReport {
+  /** Tests after which the state fingerprint changed, in run order */
+  state_mutating_tests: string[];
+  /** Why checking stopped early or never started */
+  error?: string;
 }
 
 /** Matrix of conformance results across multiple kernels */
 export interface ConformanceMatrix {
   /** Reports from each kernel tested */
   reports: KernelReport[];
-  /** When the matrix was generated (ISO 8601) */
+  /** Identifier of the run that produced this matrix */
+  run_id?: string;
+  /** When the matrix was generated (UTC RFC 3339) */
   generated_at: string;
   /** Git commit SHA (added by CI) */
   commit_sha?: string;
@@ -0,0 +1,114 @@
+//! End-of-run summary of files written and time spent per kernel.
+//!
+//! Anything that writes a file during a run records it here, so the summary
+//! printed at the end accounts for every artifact.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A file written during the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// What the file is (e.g. "report", "step summary")
+    pub kind: String,
+    pub path: PathBuf,
+    /// Size on disk when recorded
+    pub bytes: u64,
+}
+
+/// Files written and wall-clock time per kernel, collected as the run goes.
+#[derive(Debug, Clone, Default)]
+pub struct ArtifactsSummary {
+    pub files: Vec<Artifact>,
+    /// (kernel name, wall-clock time) in run order
+    pub kernels: Vec<(String, Duration)>,
+}
+
+impl ArtifactsSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a file that has just been written.
+    pub fn record_file(&mut self, kind: &str, path: &Path) {
+        let bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        self.files.push(Artifact {
+            kind: kind.to_string(),
+            path: path.to_path_buf(),
+            bytes,
+        });
+    }
+
+    /// Record the total time spent testing a kernel.
+    pub fn record_kernel(&mut self, kernel_name: &str, duration: Duration) {
+        self.kernels.push((kernel_name.to_string(), duration));
+    }
+
+    /// Render the summary as a plain-text block.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str("Run summary\n");
+        output.push_str(&format!("{}\n", "-".repeat(50)));
+
+        if self.files.is_empty() {
+            output.push_str("  Files written: none\n");
+        } else {
+            output.push_str("  Files written:\n");
+            for file in &self.files {
+                output.push_str(&format!(
+                    "    {} ({}, {} bytes)\n",
+                    file.path.display(),
+                    file.kind,
+                    file.bytes
+                ));
+            }
+        }
+
+        output.push_str("  Wall-clock per kernel:\n");
+        for (kernel_name, duration) in &self.kernels {
+            output.push_str(&format!(
+                "    {}: {:.1}s\n",
+                kernel_name,
+                duration.as_secs_f64()
+            ));
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lists_recorded_files() {
+        let dir =
+            std::env::temp_dir().join(format!("kernel-testbed-artifacts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let report = dir.join("report.json");
+        let summary_file = dir.join("summary.md");
+        std::fs::write(&report, "{}").unwrap();
+        std::fs::write(&summary_file, "# Summary\n").unwrap();
+
+        let mut summary = ArtifactsSummary::new();
+        summary.record_file("report", &report);
+        summary.record_file("step summary", &summary_file);
+        summary.record_kernel("python3", Duration::from_millis(12_340));
+
+        let paths: Vec<&Path> = summary.files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, vec![report.as_path(), summary_file.as_path()]);
+        assert_eq!(summary.files[0].bytes, 2);
+
+        let rendered = summary.render();
+        assert!(rendered.contains(&format!("{} (report, 2 bytes)", report.display())));
+        assert!(rendered.contains("python3: 12.3s"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_empty_summary() {
+        let rendered = ArtifactsSummary::new().render();
+        assert!(rendered.contains("Files written: none"));
+    }
+}
@@ -0,0 +1,549 @@
+//! The channels the harness talks to a kernel over.
+//!
+//! Tests reach shell, control and stdin through [`MessageChannel`] and the
+//! heartbeat through [`HeartbeatChannel`], so the same test runs whether its
+//! messages travel over the kernel's own ZeroMQ sockets or through a relay
+//! task, such as the one replaying a recorded transcript.
+
+use crate::iopub::{Delivery, IoPubCollector};
+use crate::transcript::{message_from_value, Recorder};
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use jupyter_protocol::connection_info::ConnectionInfo;
+use jupyter_protocol::JupyterMessage;
+use ring::hmac;
+use runtimelib::{ClientHeartbeatConnection, ClientShellConnection};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use zeromq::{Socket, SocketRecv, SocketSend, ZmqMessage};
+
+/// Frame separating a ZeroMQ message's routing identities from its signed parts
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Future a channel operation resolves to its result with.
+pub type ChannelFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// One of a kernel's channels, as named by the messaging spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Shell,
+    Control,
+    Stdin,
+    IoPub,
+    Heartbeat,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Channel::Shell => "shell",
+            Channel::Control => "control",
+            Channel::Stdin => "stdin",
+            Channel::IoPub => "iopub",
+            Channel::Heartbeat => "heartbeat",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A request channel to the kernel: shell, control or stdin.
+pub trait MessageChannel: Send + Sync {
+    /// Send `message` to the kernel.
+    fn send(&mut self, message: JupyterMessage) -> ChannelFuture<'_, ()>;
+
+    /// Wait for the next message from the kernel.
+    fn read(&mut self) -> ChannelFuture<'_, JupyterMessage>;
+}
+
+/// The kernel's heartbeat channel.
+pub trait HeartbeatChannel: Send + Sync {
+    /// Send one heartbeat and wait for the kernel to echo it.
+    fn beat(&mut self) -> ChannelFuture<'_, ()>;
+}
+
+// Shell, control and stdin connections are all the same DEALER socket type
+impl MessageChannel for ClientShellConnection {
+    fn send(&mut self, message: JupyterMessage) -> ChannelFuture<'_, ()> {
+        Box::pin(ClientShellConnection::send(self, message))
+    }
+
+    fn read(&mut self) -> ChannelFuture<'_, JupyterMessage> {
+        Box::pin(ClientShellConnection::read(self))
+    }
+}
+
+impl HeartbeatChannel for ClientHeartbeatConnection {
+    fn beat(&mut self) -> ChannelFuture<'_, ()> {
+        Box::pin(self.single_heartbeat())
+    }
+}
+
+/// A channel that adds everything passing through it to a [`Recorder`].
+struct Recorded<C: ?Sized> {
+    channel: Channel,
+    recorder: Recorder,
+    inner: Box<C>,
+}
+
+impl<C: ?Sized> Recorded<C> {
+    fn new(channel: Channel, recorder: Recorder, inner: Box<C>) -> Self {
+        Self {
+            channel,
+            recorder,
+            inner,
+        }
+    }
+}
+
+/// Box `inner` as `channel`, recording into `recorder`.
+pub(crate) fn recorded(
+    channel: Channel,
+    recorder: &Recorder,
+    inner: impl MessageChannel + 'static,
+) -> Box<dyn MessageChannel> {
+    let inner: Box<dyn MessageChannel> = Box::new(inner);
+    Box::new(Recorded::new(channel, recorder.clone(), inner))
+}
+
+/// Box the heartbeat `inner`, recording into `recorder`.
+pub(crate) fn recorded_heartbeat(
+    recorder: &Recorder,
+    inner: impl HeartbeatChannel + 'static,
+) -> Box<dyn HeartbeatChannel> {
+    let inner: Box<dyn HeartbeatChannel> = Box::new(inner);
+    Box::new(Recorded::new(Channel::Heartbeat, recorder.clone(), inner))
+}
+
+impl MessageChannel for Recorded<dyn MessageChannel> {
+    fn send(&mut self, message: JupyterMessage) -> ChannelFuture<'_, ()> {
+        // Recorded before it goes out, so no reply can be recorded first
+        self.recorder.sent(self.channel, Some(&message));
+        self.inner.send(message)
+    }
+
+    fn read(&mut self) -> ChannelFuture<'_, JupyterMessage> {
+        Box::pin(async move {
+            let message = self.inner.read().await?;
+            self.recorder.received(self.channel, Some(&message));
+            Ok(message)
+        })
+    }
+}
+
+impl HeartbeatChannel for Recorded<dyn HeartbeatChannel> {
+    fn beat(&mut self) -> ChannelFuture<'_, ()> {
+        Box::pin(async move {
+            self.recorder.sent(Channel::Heartbeat, None);
+            self.inner.beat().await?;
+            self.recorder.received(Channel::Heartbeat, None);
+            Ok(())
+        })
+    }
+}
+
+/// A shell connection that builds, signs and reads its own ZeroMQ frames.
+///
+/// jupyter-protocol's Header has a fixed set of fields, so a request whose
+/// header needs another one, such as a JEP 91 `subshell_id`, can't go out
+/// through runtimelib's connections. Every message sent here has
+/// `header_fields` added to its header. Replies can also be read as the
+/// JSON the kernel sent, for fields typed decoding would default or reject.
+pub(crate) struct RawShell {
+    socket: zeromq::DealerSocket,
+    /// None when the connection file's key is empty and nothing is signed
+    key: Option<hmac::Key>,
+    session_id: String,
+    header_fields: Map<String, Value>,
+}
+
+impl RawShell {
+    /// Connect to the shell socket in `connection_info` as `session_id`.
+    pub(crate) async fn connect(
+        connection_info: &ConnectionInfo,
+        session_id: &str,
+        header_fields: Map<String, Value>,
+    ) -> anyhow::Result<Self> {
+        let mut socket = zeromq::DealerSocket::new();
+        socket.connect(&connection_info.shell_url()).await?;
+        Ok(Self {
+            socket,
+            key: signing_key(connection_info),
+            session_id: session_id.to_string(),
+            header_fields,
+        })
+    }
+
+    /// Wait for the next message from the kernel, as the JSON it sent.
+    pub(crate) async fn read_value(&mut self) -> anyhow::Result<Value> {
+        let frames = self.socket.recv().await?;
+        Ok(decode_frames(frames.into_vec(), self.key.as_ref())?.0)
+    }
+}
+
+impl MessageChannel for RawShell {
+    fn send(&mut self, message: JupyterMessage) -> ChannelFuture<'_, ()> {
+        Box::pin(async move {
+            let message = message.with_session(&self.session_id);
+            let frames = to_frames(&message, &self.header_fields, self.key.as_ref())?;
+            self.socket.send(frames).await?;
+            Ok(())
+        })
+    }
+
+    fn read(&mut self) -> ChannelFuture<'_, JupyterMessage> {
+        Box::pin(async move {
+            let frames = self.socket.recv().await?;
+            from_frames(frames.into_vec(), self.key.as_ref())
+        })
+    }
+}
+
+/// An IOPub subscription that hands over messages as the JSON the kernel
+/// sent, before any typed decoding could default or reject a field.
+pub(crate) struct RawIoPub {
+    socket: zeromq::SubSocket,
+    key: Option<hmac::Key>,
+}
+
+impl RawIoPub {
+    /// Subscribe to everything on the IOPub socket in `connection_info`.
+    pub(crate) async fn connect(connection_info: &ConnectionInfo) -> anyhow::Result<Self> {
+        let mut socket = zeromq::SubSocket::new();
+        socket.subscribe("").await?;
+        socket.connect(&connection_info.iopub_url()).await?;
+        Ok(Self {
+            socket,
+            key: signing_key(connection_info),
+        })
+    }
+
+    /// Wait for the next published message, as the JSON the kernel sent.
+    pub(crate) async fn read_value(&mut self) -> anyhow::Result<Value> {
+        let frames = self.socket.recv().await?;
+        Ok(decode_frames(frames.into_vec(), self.key.as_ref())?.0)
+    }
+}
+
+/// Key the connection file's messages are signed with; None when its key is
+/// empty and nothing is signed.
+fn signing_key(connection_info: &ConnectionInfo) -> Option<hmac::Key> {
+    (!connection_info.key.is_empty())
+        .then(|| hmac::Key::new(hmac::HMAC_SHA256, connection_info.key.as_bytes()))
+}
+
+/// Frames for `message` with `header_fields` added to its header, signed
+/// with `key`.
+fn to_frames(
+    message: &JupyterMessage,
+    header_fields: &Map<String, Value>,
+    key: Option<&hmac::Key>,
+) -> anyhow::Result<ZmqMessage> {
+    let mut header = serde_json::to_value(&message.header)?;
+    if let Value::Object(header) = &mut header {
+        header.extend(header_fields.clone());
+    }
+    let parent_header = match &message.parent_header {
+        Some(parent) => serde_json::to_vec(parent)?,
+        None => b"{}".to_vec(),
+    };
+    let parts = [
+        serde_json::to_vec(&header)?,
+        parent_header,
+        serde_json::to_vec(&message.metadata)?,
+        serde_json::to_vec(&message.content)?,
+    ];
+
+    let mut frames = ZmqMessage::from(DELIMITER.to_vec());
+    frames.push_back(signature(key, &parts).into());
+    for part in parts {
+        frames.push_back(part.into());
+    }
+    for buffer in &message.buffers {
+        frames.push_back(buffer.clone());
+    }
+    Ok(frames)
+}
+
+/// Decode a message from the kernel, checking its signature against `key`.
+fn from_frames(frames: Vec<Bytes>, key: Option<&hmac::Key>) -> anyhow::Result<JupyterMessage> {
+    let (value, buffers) = decode_frames(frames, key)?;
+    let mut message = message_from_value(value)?;
+    message.buffers = buffers;
+    Ok(message)
+}
+
+/// Check the signature on a message from the kernel and return its header,
+/// parent_header, metadata and content as one JSON object, plus its buffers.
+fn decode_frames(
+    frames: Vec<Bytes>,
+    key: Option<&hmac::Key>,
+) -> anyhow::Result<(Value, Vec<Bytes>)> {
+    let delimiter = frames
+        .iter()
+        .position(|frame| frame == DELIMITER)
+        .ok_or_else(|| anyhow!("message has no delimiter frame"))?;
+    let signed = &frames[delimiter + 1..];
+    if signed.len() < 5 {
+        bail!("message has {} frames after the delimiter", signed.len());
+    }
+    let parts: Vec<&[u8]> = signed[1..5].iter().map(|part| &part[..]).collect();
+    if signature(key, &parts) != signed[0] {
+        bail!("message signature doesn't match");
+    }
+
+    let part = |index: usize| serde_json::from_slice::<Value>(parts[index]);
+    let value = serde_json::json!({
+        "header": part(0)?,
+        "parent_header": part(1)?,
+        "metadata": part(2)?,
+        "content": part(3)?,
+    });
+    Ok((value, signed[5..].to_vec()))
+}
+
+/// Hex HMAC-SHA256 of `parts`, or empty when there is no key.
+fn signature(key: Option<&hmac::Key>, parts: &[impl AsRef<[u8]>]) -> Vec<u8> {
+    let Some(key) = key else {
+        return Vec::new();
+    };
+    let mut context = hmac::Context::with_key(key);
+    for part in parts {
+        context.update(part.as_ref());
+    }
+    context
+        .sign()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// What a relayed channel hands its relay task.
+#[derive(Debug)]
+pub(crate) enum Outgoing {
+    /// A message to send to the kernel on a channel
+    Message(Channel, Box<JupyterMessage>),
+    /// A heartbeat; the relay answers on the sender once it is echoed
+    Heartbeat(oneshot::Sender<()>),
+}
+
+/// A shell, control or stdin channel whose messages a relay task carries.
+pub(crate) struct RelayedChannel {
+    channel: Channel,
+    session_id: String,
+    outgoing: mpsc::UnboundedSender<(Instant, Outgoing)>,
+    incoming: mpsc::UnboundedReceiver<JupyterMessage>,
+}
+
+impl MessageChannel for RelayedChannel {
+    fn send(&mut self, message: JupyterMessage) -> ChannelFuture<'_, ()> {
+        // Stamped with our session, as the ZeroMQ connections do
+        let message = message.with_session(&self.session_id);
+        let sent = self.outgoing.send((
+            Instant::now(),
+            Outgoing::Message(self.channel, Box::new(message)),
+        ));
+        let channel = self.channel;
+        Box::pin(async move { sent.map_err(|_| anyhow!("{} channel is closed", channel)) })
+    }
+
+    fn read(&mut self) -> ChannelFuture<'_, JupyterMessage> {
+        Box::pin(async move {
+            self.incoming
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("{} channel is closed", self.channel))
+        })
+    }
+}
+
+/// A heartbeat channel whose beats a relay task answers.
+pub(crate) struct RelayedHeartbeat {
+    outgoing: mpsc::UnboundedSender<(Instant, Outgoing)>,
+}
+
+impl HeartbeatChannel for RelayedHeartbeat {
+    fn beat(&mut self) -> ChannelFuture<'_, ()> {
+        let (echo, echoed) = oneshot::channel();
+        let sent = self
+            .outgoing
+            .send((Instant::now(), Outgoing::Heartbeat(echo)));
+        Box::pin(async move {
+            sent.map_err(|_| anyhow!("heartbeat channel is closed"))?;
+            echoed
+                .await
+                .map_err(|_| anyhow!("heartbeat channel is closed"))
+        })
+    }
+}
+
+/// The relay task's end of a set of relayed channels.
+///
+/// Dropping it closes every channel, so the harness's reads fail at once
+/// rather than wait out their timeouts.
+pub(crate) struct Relay {
+    /// Messages and heartbeats the harness sent, stamped with when it did
+    pub(crate) outgoing: mpsc::UnboundedReceiver<(Instant, Outgoing)>,
+    shell: mpsc::UnboundedSender<JupyterMessage>,
+    control: mpsc::UnboundedSender<JupyterMessage>,
+    stdin: mpsc::UnboundedSender<JupyterMessage>,
+    iopub: mpsc::UnboundedSender<Delivery>,
+    recorder: Recorder,
+}
+
+impl Relay {
+    /// Hand `message` from the kernel to whoever reads `channel`.
+    ///
+    /// Returns false once the harness has gone away.
+    pub(crate) fn deliver(&self, channel: Channel, message: JupyterMessage) -> bool {
+        match channel {
+            Channel::Shell => self.shell.send(message).is_ok(),
+            Channel::Control => self.control.send(message).is_ok(),
+            Channel::Stdin => self.stdin.send(message).is_ok(),
+            Channel::IoPub => {
+                self.recorder.received(Channel::IoPub, Some(&message));
+                self.iopub.send(Ok((Instant::now(), message))).is_ok()
+            }
+            // Heartbeats are answered through Outgoing::Heartbeat
+            Channel::Heartbeat => true,
+        }
+    }
+}
+
+/// Channels to a kernel that a relay task carries, from [`relay`].
+pub(crate) struct RelayedChannels {
+    pub(crate) shell: RelayedChannel,
+    pub(crate) control: RelayedChannel,
+    pub(crate) stdin: RelayedChannel,
+    pub(crate) heartbeat: RelayedHeartbeat,
+    /// Reads what the relay delivers on IOPub; dropping it stops the relay
+    pub(crate) iopub: IoPubCollector,
+}
+
+/// Create channels for the session `session_id` and hand their other end
+/// to the task `start` spawns.
+///
+/// IOPub messages the relay delivers are added to `recorder` as they
+/// arrive; the other channels record what is read from them once they are
+/// wrapped with [`recorded`].
+pub(crate) fn relay(
+    session_id: &str,
+    recorder: Recorder,
+    start: impl FnOnce(Relay) -> JoinHandle<()>,
+) -> RelayedChannels {
+    let (outgoing_sender, outgoing) = mpsc::unbounded_channel();
+    let (shell_sender, shell) = mpsc::unbounded_channel();
+    let (control_sender, control) = mpsc::unbounded_channel();
+    let (stdin_sender, stdin) = mpsc::unbounded_channel();
+    let (iopub_sender, iopub) = mpsc::unbounded_channel();
+
+    let task = start(Relay {
+        outgoing,
+        shell: shell_sender,
+        control: control_sender,
+        stdin: stdin_sender,
+        iopub: iopub_sender,
+        recorder,
+    });
+    let channel = |channel, incoming| RelayedChannel {
+        channel,
+        session_id: session_id.to_string(),
+        outgoing: outgoing_sender.clone(),
+        incoming,
+    };
+    RelayedChannels {
+        shell: channel(Channel::Shell, shell),
+        control: channel(Channel::Control, control),
+        stdin: channel(Channel::Stdin, stdin),
+        heartbeat: RelayedHeartbeat {
+            outgoing: outgoing_sender.clone(),
+        },
+        iopub: IoPubCollector::new(iopub, task),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jupyter_protocol::{ExecuteReply, ExecuteRequest, KernelInfoRequest};
+
+    #[tokio::test]
+    async fn test_relay_carries_messages_both_ways() {
+        let channels = relay("session-1", Recorder::default(), |mut relay| {
+            tokio::spawn(async move {
+                while let Some((_, outgoing)) = relay.outgoing.recv().await {
+                    match outgoing {
+                        Outgoing::Message(channel, request) => {
+                            assert_eq!(request.header.session, "session-1");
+                            let reply =
+                                JupyterMessage::new(ExecuteReply::default(), Some(&*request));
+                            relay.deliver(channel, reply);
+                        }
+                        Outgoing::Heartbeat(echo) => {
+                            let _ = echo.send(());
+                        }
+                    }
+                }
+            })
+        });
+        let RelayedChannels {
+            mut control,
+            mut heartbeat,
+            ..
+        } = channels;
+
+        let request: JupyterMessage = KernelInfoRequest {}.into();
+        let msg_id = request.header.msg_id.clone();
+        control.send(request).await.unwrap();
+        let reply = control.read().await.unwrap();
+        assert_eq!(reply.parent_header.unwrap().msg_id, msg_id);
+        heartbeat.beat().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_relay_closes_channels() {
+        let channels = relay("session-1", Recorder::default(), |relay| {
+            tokio::spawn(async move { drop(relay) })
+        });
+        let mut shell = channels.shell;
+        let err = shell.read().await.unwrap_err();
+        assert!(
+            err.to_string().contains("shell channel is closed"),
+            "{}",
+            err
+        );
+
+        let request: JupyterMessage = ExecuteRequest::new("1".to_string()).into();
+        assert!(shell.send(request).await.is_err());
+    }
+
+    #[test]
+    fn test_raw_frames_carry_header_fields_and_are_signed() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let request: JupyterMessage = ExecuteRequest::new("1".to_string()).into();
+        let mut fields = Map::new();
+        fields.insert("subshell_id".to_string(), "sub-1".into());
+
+        let frames = to_frames(&request, &fields, Some(&key)).unwrap().into_vec();
+        assert_eq!(frames[0], DELIMITER);
+        let header: Value = serde_json::from_slice(&frames[2]).unwrap();
+        assert_eq!(header["subshell_id"], "sub-1");
+        assert_eq!(header["msg_type"], "execute_request");
+
+        let decoded = from_frames(frames.clone(), Some(&key)).unwrap();
+        assert_eq!(decoded.header.msg_id, request.header.msg_id);
+        assert!(decoded.parent_header.is_none());
+
+        let wrong = hmac::Key::new(hmac::HMAC_SHA256, b"other");
+        let err = from_frames(frames, Some(&wrong)).unwrap_err();
+        assert!(err.to_string().contains("signature"), "{}", err);
+    }
+}
@@ -0,0 +1,166 @@
+//! Suite configuration loaded from a JSON file.
+//!
+//! Every field is optional; anything omitted keeps its built-in default.
+//!
+//! ```json
+//! {
+//!   "benchmarks": {
+//!     "completion_p95_pass_ms": 300,
+//!     "completion_p95_partial_ms": 1000
+//!   },
+//!   "kernels": {
+//!     "python3": { "require": ["tier1=100", "tier2=80"] }
+//!   }
+//! }
+//! ```
+
+use crate::extra_tests::without_location;
+use crate::types::TierRequirement;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Error raised while loading a config file.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+/// Top-level suite configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SuiteConfig {
+    /// Thresholds for opt-in benchmark tests
+    pub benchmarks: BenchmarkConfig,
+    /// Per-kernel settings, keyed by kernel name
+    pub kernels: BTreeMap<String, KernelConfig>,
+}
+
+impl SuiteConfig {
+    /// Tier requirements for a kernel, with `overrides` replacing any
+    /// configured requirement for the same tier.
+    pub fn requirements_for(
+        &self,
+        kernel_name: &str,
+        overrides: &[TierRequirement],
+    ) -> Vec<TierRequirement> {
+        let mut requirements: Vec<TierRequirement> = self
+            .kernels
+            .get(kernel_name)
+            .map(|k| k.require.clone())
+            .unwrap_or_default();
+        for requirement in overrides {
+            requirements.retain(|r| r.tier != requirement.tier);
+            requirements.push(requirement.clone());
+        }
+        requirements.sort_by_key(|r| r.tier.tier_number());
+        requirements
+    }
+}
+
+/// Settings that apply to a single kernel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KernelConfig {
+    /// Minimum tier scores, e.g. `["tier1=100", "tier2=80"]`
+    pub require: Vec<TierRequirement>,
+}
+
+/// Soft thresholds used to grade benchmark tests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BenchmarkConfig {
+    /// completion_request p95 latency at or below this passes outright
+    pub completion_p95_pass_ms: u64,
+    /// completion_request p95 latency at or below this is a partial pass;
+    /// anything slower still passes but is flagged
+    pub completion_p95_partial_ms: u64,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            completion_p95_pass_ms: 300,
+            completion_p95_partial_ms: 1000,
+        }
+    }
+}
+
+/// Load a suite configuration file.
+pub fn load_config(path: &Path) -> Result<SuiteConfig, ConfigError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    serde_json::from_str(&content).map_err(|e| ConfigError::Invalid {
+        path: path.to_path_buf(),
+        line: e.line(),
+        column: e.column(),
+        message: without_location(&e.to_string()).to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config: SuiteConfig =
+            serde_json::from_str(r#"{ "benchmarks": { "completion_p95_pass_ms": 150 } }"#).unwrap();
+        assert_eq!(config.benchmarks.completion_p95_pass_ms, 150);
+        assert_eq!(config.benchmarks.completion_p95_partial_ms, 1000);
+    }
+
+    #[test]
+    fn test_cli_requirements_override_config() {
+        let config: SuiteConfig = serde_json::from_str(
+            r#"{ "kernels": { "python3": { "require": ["tier1=100", "tier2=80"] } } }"#,
+        )
+        .unwrap();
+        let overrides = vec!["tier2=50".parse().unwrap()];
+
+        let requirements = config.requirements_for("python3", &overrides);
+        let rendered: Vec<String> = requirements.iter().map(|r| r.to_string()).collect();
+        assert_eq!(rendered, vec!["tier1=100", "tier2=50"]);
+        assert_eq!(config.requirements_for("ir", &[]), vec![]);
+    }
+
+    #[test]
+    fn test_unknown_field_rejected() {
+        let result: Result<SuiteConfig, _> = serde_json::from_str(r#"{ "benchmark": {} }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_config_reports_location_once() {
+        let path = std::env::temp_dir().join(format!(
+            "kernel-testbed-config-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, "{\n  \"benchmark\": {}\n}").unwrap();
+
+        let err = load_config(&path).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::Invalid { line: 2, .. }),
+            "{}",
+            err
+        );
+        assert!(!err.to_string().contains("at line"), "{}", err);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,158 @@
+//! Reference documentation generated from the test registry.
+//!
+//! Everything here is derived from [`ConformanceTest`], [`SuiteFinalizer`]
+//! and [`ExtraTest`] metadata, so the rendered document can't drift from
+//! the tests that actually run.
+
+use crate::extra_tests::ExtraTest;
+use crate::harness::{ConformanceTest, SuiteFinalizer};
+use crate::types::TestCategory;
+
+const MESSAGING_SPEC: &str = "https://jupyter-client.readthedocs.io/en/latest/messaging.html";
+
+/// Link to the section of the messaging spec that defines a message type.
+pub fn spec_link(message_type: &str) -> Option<String> {
+    let anchor = match message_type {
+        "heartbeat" => "heartbeat-for-kernels",
+        "iopub_welcome" => {
+            return Some("https://github.com/jupyter/enhancement-proposals/pull/65".to_string())
+        }
+        "kernel_info_request" => "kernel-info",
+        "execute_request" | "execute_reply" => "execute",
+        "execute_input" => "code-inputs",
+        "execute_result" => "execution-results",
+        "stream" => "streams-stdout-stderr-etc",
+        "status" => "kernel-status",
+        "parent_header" => "parent-header",
+        "display_data" => "display-data",
+        "update_display_data" => "update-display-data",
+        "complete_request" => "completion",
+        "inspect_request" => "introspection",
+        "is_complete_request" => "code-completeness",
+        "history_request" => "history",
+        "comm_info_request" => "comm-info",
+        "comm_open" => "custom-messages",
+        "error" => "execution-errors",
+        "input_request" => "messages-on-the-stdin-router-dealer-channel",
+        "interrupt_request" => "kernel-interrupt",
+        "shutdown_request" => "kernel-shutdown",
+        _ => return None,
+    };
+    Some(format!("{}#{}", MESSAGING_SPEC, anchor))
+}
+
+/// Render the suite definition as a markdown reference, grouped by tier.
+pub fn render_suite_docs(
+    tests: &[ConformanceTest],
+    finalizers: &[SuiteFinalizer],
+    extra_tests: &[ExtraTest],
+) -> String {
+    let mut output = String::new();
+    output.push_str("# Kernel Conformance Tests\n\n");
+    output.push_str(
+        "Generated from the test registry by `jupyter-kernel-test docs`. Do not edit by hand.\n",
+    );
+
+    for category in [
+        TestCategory::Tier1Basic,
+        TestCategory::Tier2Interactive,
+        TestCategory::Tier3RichOutput,
+        TestCategory::Tier4Advanced,
+        TestCategory::Benchmark,
+    ] {
+        let tier_tests: Vec<_> = tests.iter().filter(|t| t.category == category).collect();
+        let tier_finalizers: Vec<_> = finalizers
+            .iter()
+            .filter(|f| f.category == category)
+            .collect();
+        let tier_extra: Vec<_> = extra_tests
+            .iter()
+            .filter(|t| t.category == category)
+            .collect();
+        if tier_tests.is_empty() && tier_finalizers.is_empty() && tier_extra.is_empty() {
+            continue;
+        }
+
+        if category == TestCategory::Benchmark {
+            output.push_str("\n## Benchmarks\n\nOpt-in with `--benchmarks`.\n");
+        } else {
+            output.push_str(&format!(
+                "\n## Tier {}: {}\n",
+                category.tier_number(),
+                category.description()
+            ));
+        }
+
+        for test in tier_tests {
+            render_entry(
+                &mut output,
+                &test.name,
+                &test.message_type,
+                &test.description,
+                &snippet_list(test.snippets),
+                None,
+            );
+        }
+        for finalizer in tier_finalizers {
+            render_entry(
+                &mut output,
+                finalizer.name,
+                finalizer.message_type,
+                finalizer.description,
+                "none",
+                Some("Suite-wide check over every message observed during the run"),
+            );
+        }
+        for test in tier_extra {
+            let assertions = test
+                .assertions
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+                .join("; ");
+            render_entry(
+                &mut output,
+                &test.name,
+                &test.message_type,
+                &assertions,
+                "none (runs its own code)",
+                Some(&format!("Custom test: {}", test.description)),
+            );
+        }
+    }
+
+    output
+}
+
+fn snippet_list(snippets: &[&str]) -> String {
+    if snippets.is_empty() {
+        "none".to_string()
+    } else {
+        snippets
+            .iter()
+            .map(|s| format!("`{}`", s))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn render_entry(
+    output: &mut String,
+    name: &str,
+    message_type: &str,
+    pass_criteria: &str,
+    snippets: &str,
+    note: Option<&str>,
+) {
+    output.push_str(&format!("\n### `{}`\n\n", name));
+    if let Some(note) = note {
+        output.push_str(&format!("{}\n\n", note));
+    }
+    output.push_str(&format!("- **Message type**: `{}`\n", message_type));
+    output.push_str(&format!("- **Passes when**: {}\n", pass_criteria));
+    match spec_link(message_type) {
+        Some(link) => output.push_str(&format!("- **Spec**: <{}>\n", link)),
+        None => output.push_str("- **Spec**: none\n"),
+    }
+    output.push_str(&format!("- **Snippets**: {}\n", snippets));
+}
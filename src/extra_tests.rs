@@ -0,0 +1,472 @@
+//! User-supplied conformance checks loaded from a directory of spec files.
+//!
+//! Each `*.toml`, `*.yaml` (or `*.yml`) and `*.json` file in the directory
+//! describes one test: the code to execute, the tier it belongs to, and a
+//! list of assertions drawn from a fixed vocabulary. This lets teams keep
+//! kernel-specific regression checks next to their kernel without writing
+//! Rust.
+//!
+//! ```toml
+//! name = "sql_magic_display"
+//! tier = 3
+//! message_type = "display_data"
+//! description = "%%sql cells render a table"
+//! code = """
+//! %%sql
+//! SELECT 1"""
+//! assertions = [
+//!     { iopub_has_message_type = "display_data" },
+//!     { reply_status = "ok" },
+//! ]
+//! ```
+//!
+//! The same spec in YAML, or as JSON:
+//!
+//! ```yaml
+//! tier: 3
+//! message_type: display_data
+//! code: "%%sql\nSELECT 1"
+//! assertions:
+//!   - iopub_has_message_type: display_data
+//!   - reply_status: ok
+//! ```
+//!
+//! ```json
+//! {
+//!   "name": "sql_magic_display",
+//!   "tier": 3,
+//!   "message_type": "display_data",
+//!   "description": "%%sql cells render a table",
+//!   "code": "%%sql\nSELECT 1",
+//!   "assertions": [
+//!     { "iopub_has_message_type": "display_data" },
+//!     { "reply_status": "ok" }
+//!   ]
+//! }
+//! ```
+
+use crate::harness::KernelUnderTest;
+use crate::types::{FailureKind, TestCategory, TestResult};
+use jupyter_protocol::messaging::{
+    JupyterMessageContent, MediaType, ReplyStatus, Stdio, StreamContent,
+};
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Error raised while loading extra test specs.
+#[derive(Error, Debug)]
+pub enum ExtraTestError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+/// A single assertion from the spec vocabulary.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Assertion {
+    /// Some stdout stream message contains this text
+    StdoutContains(String),
+    /// Some stderr stream message contains this text
+    StderrContains(String),
+    /// An iopub message of this type was broadcast for the request
+    IopubHasMessageType(String),
+    /// The execute_result text/plain representation contains this text
+    ExecuteResultTextContains(String),
+    /// The execute_reply status matches
+    ReplyStatus(ReplyStatus),
+}
+
+impl std::fmt::Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::StdoutContains(text) => write!(f, "stdout contains {:?}", text),
+            Assertion::StderrContains(text) => write!(f, "stderr contains {:?}", text),
+            Assertion::IopubHasMessageType(msg_type) => {
+                write!(f, "iopub has a {} message", msg_type)
+            }
+            Assertion::ExecuteResultTextContains(text) => {
+                write!(f, "execute_result text/plain contains {:?}", text)
+            }
+            Assertion::ReplyStatus(status) => write!(f, "execute_reply status is {:?}", status),
+        }
+    }
+}
+
+/// A declarative test loaded from a spec file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraTest {
+    /// Test name; defaults to the spec file stem
+    #[serde(default)]
+    pub name: String,
+    /// Tier the test belongs to (1-4)
+    #[serde(rename = "tier", deserialize_with = "deserialize_tier")]
+    pub category: TestCategory,
+    /// The primary protocol message type being tested
+    pub message_type: String,
+    /// Human-readable description of what this test validates
+    #[serde(default)]
+    pub description: String,
+    /// Code to execute
+    pub code: String,
+    /// Assertions evaluated against the execution
+    pub assertions: Vec<Assertion>,
+}
+
+fn deserialize_tier<'de, D>(deserializer: D) -> Result<TestCategory, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let n = u8::deserialize(deserializer)?;
+    TestCategory::from_tier_number(n)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid tier {}, expected 1-4", n)))
+}
+
+/// Spec file formats, told apart by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpecFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl SpecFormat {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Parse `content`, returning the 1-based line and column of any error
+    /// along with its message.
+    fn parse(self, content: &str) -> Result<ExtraTest, (usize, usize, String)> {
+        match self {
+            Self::Toml => toml::from_str(content).map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map_or((1, 1), |span| line_and_column(content, span.start));
+                (line, column, e.message().to_string())
+            }),
+            // Assertions are written as one-key maps, like in the other
+            // formats, rather than as YAML tags
+            Self::Yaml => serde_yaml::with::singleton_map_recursive::deserialize(
+                serde_yaml::Deserializer::from_str(content),
+            )
+            .map_err(|e| {
+                let (line, column) = e
+                    .location()
+                    .map_or((1, 1), |location| (location.line(), location.column()));
+                (line, column, without_location(&e.to_string()).to_string())
+            }),
+            Self::Json => serde_json::from_str(content).map_err(|e| {
+                let message = without_location(&e.to_string()).to_string();
+                (e.line(), e.column(), message)
+            }),
+        }
+    }
+}
+
+/// 1-based line and column of byte `offset` in `content`.
+fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = content.get(..offset).unwrap_or(content);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// A parser's error message without the " at line L column C" it appends,
+/// for errors that report the location separately.
+pub(crate) fn without_location(message: &str) -> &str {
+    let Some(at) = message.rfind(" at line ") else {
+        return message;
+    };
+    let location = &message[at + " at line ".len()..];
+    let is_location = location
+        .split_once(" column ")
+        .is_some_and(|(line, column)| {
+            line.parse::<usize>().is_ok() && column.parse::<usize>().is_ok()
+        });
+    if is_location {
+        &message[..at]
+    } else {
+        message
+    }
+}
+
+/// Load every spec in `dir`, sorted by file name.
+///
+/// All files are validated up front so problems are reported before any
+/// kernel is launched.
+pub fn load_extra_tests(dir: &Path) -> Result<Vec<ExtraTest>, ExtraTestError> {
+    let io_err = |source| ExtraTestError::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(io_err)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| SpecFormat::of(path).is_some())
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| load_spec(path)).collect()
+}
+
+fn load_spec(path: &Path) -> Result<ExtraTest, ExtraTestError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ExtraTestError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let format = SpecFormat::of(path).unwrap_or(SpecFormat::Json);
+    let mut spec =
+        format
+            .parse(&content)
+            .map_err(|(line, column, message)| ExtraTestError::Invalid {
+                path: path.to_path_buf(),
+                line,
+                column,
+                message,
+            })?;
+
+    if spec.name.is_empty() {
+        spec.name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    if spec.assertions.is_empty() {
+        return Err(ExtraTestError::Invalid {
+            path: path.to_path_buf(),
+            line: 1,
+            column: 1,
+            message: "spec has no assertions".to_string(),
+        });
+    }
+
+    Ok(spec)
+}
+
+impl ExtraTest {
+    /// Execute the spec's code and evaluate its assertions.
+    pub async fn run(&self, kernel: &mut KernelUnderTest) -> TestResult {
+        let (reply, iopub) = match kernel.execute_and_collect(&self.code).await {
+            Ok(collected) => collected,
+            Err(e) => return TestResult::fail(e.to_string(), FailureKind::HarnessError),
+        };
+
+        let stream_contains = |stdout: bool, needle: &str| {
+            iopub.iter().any(|msg| match &msg.content {
+                JupyterMessageContent::StreamContent(StreamContent { name, text }) => {
+                    matches!(
+                        (name, stdout),
+                        (Stdio::Stdout, true) | (Stdio::Stderr, false)
+                    ) && text.contains(needle)
+                }
+                _ => false,
+            })
+        };
+
+        let mut failures = Vec::new();
+        for assertion in &self.assertions {
+            let ok = match assertion {
+                Assertion::StdoutContains(needle) => stream_contains(true, needle),
+                Assertion::StderrContains(needle) => stream_contains(false, needle),
+                Assertion::IopubHasMessageType(msg_type) => iopub
+                    .iter()
+                    .any(|msg| msg.content.message_type() == msg_type),
+                Assertion::ExecuteResultTextContains(needle) => iopub.iter().any(|msg| {
+                    if let JupyterMessageContent::ExecuteResult(result) = &msg.content {
+                        result.data.content.iter().any(|media| {
+                            matches!(media, MediaType::Plain(text) if text.contains(needle.as_str()))
+                        })
+                    } else {
+                        false
+                    }
+                }),
+                Assertion::ReplyStatus(expected) => matches!(
+                    &reply.content,
+                    JupyterMessageContent::ExecuteReply(er) if er.status == *expected
+                ),
+            };
+            if !ok {
+                failures.push(assertion.to_string());
+            }
+        }
+
+        if failures.is_empty() {
+            TestResult::Pass
+        } else {
+            TestResult::fail(
+                format!("Failed assertions: {}", failures.join(", ")),
+                FailureKind::UnexpectedContent,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_spec(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn temp_dir(label: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kernel-testbed-{}-{}", label, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_load_valid_spec() {
+        let dir = temp_dir("extra-valid");
+        write_spec(
+            &dir,
+            "magic_display.json",
+            r#"{
+                "tier": 3,
+                "message_type": "display_data",
+                "code": "%%html\n<b>hi</b>",
+                "assertions": [
+                    { "iopub_has_message_type": "display_data" },
+                    { "reply_status": "ok" }
+                ]
+            }"#,
+        );
+
+        let specs = load_extra_tests(&dir).unwrap();
+        assert_eq!(specs.len(), 1);
+        assert_eq!(specs[0].name, "magic_display");
+        assert_eq!(specs[0].category, TestCategory::Tier3RichOutput);
+        assert_eq!(specs[0].assertions.len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_assertion_reports_location() {
+        let dir = temp_dir("extra-invalid");
+        write_spec(
+            &dir,
+            "bad.json",
+            "{\n  \"tier\": 1,\n  \"message_type\": \"stream\",\n  \"code\": \"1\",\n  \"assertions\": [{ \"stdout_matches\": \"x\" }]\n}",
+        );
+
+        let err = load_extra_tests(&dir).unwrap_err();
+        assert!(!err.to_string().contains("at line"), "{}", err);
+        match err {
+            ExtraTestError::Invalid { path, line, .. } => {
+                assert!(path.ends_with("bad.json"));
+                assert_eq!(line, 5);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_toml_and_yaml_specs() {
+        let dir = temp_dir("extra-formats");
+        write_spec(
+            &dir,
+            "a_magic.toml",
+            "tier = 3\nmessage_type = \"display_data\"\ncode = \"%%html\"\n\
+             assertions = [{ iopub_has_message_type = \"display_data\" }, { reply_status = \"ok\" }]\n",
+        );
+        write_spec(
+            &dir,
+            "b_stream.yml",
+            "tier: 1\nmessage_type: stream\ncode: print('hi')\nassertions:\n  - stdout_contains: hi\n",
+        );
+        write_spec(&dir, "notes.txt", "not a spec");
+
+        let specs = load_extra_tests(&dir).unwrap();
+        let names: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["a_magic", "b_stream"]);
+        assert_eq!(specs[0].category, TestCategory::Tier3RichOutput);
+        assert!(matches!(
+            specs[0].assertions[1],
+            Assertion::ReplyStatus(ReplyStatus::Ok)
+        ));
+        assert_eq!(specs[1].assertions[0].to_string(), "stdout contains \"hi\"");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_toml_and_yaml_errors_report_location() {
+        let dir = temp_dir("extra-format-errors");
+        write_spec(
+            &dir,
+            "bad.toml",
+            "tier = 1\nmessage_type = \"stream\"\ncode = \"1\"\nassertions = [{ stdout_matches = \"x\" }]\n",
+        );
+        let err = load_extra_tests(&dir).unwrap_err();
+        assert!(
+            matches!(err, ExtraTestError::Invalid { line: 4, .. }),
+            "{}",
+            err
+        );
+        std::fs::remove_file(dir.join("bad.toml")).unwrap();
+
+        write_spec(
+            &dir,
+            "bad.yaml",
+            "tier: 1\nmessage_type: stream\ncode: '1'\nassertions:\n  - stdout_matches: x\n",
+        );
+        let err = load_extra_tests(&dir).unwrap_err();
+        assert!(
+            matches!(err, ExtraTestError::Invalid { line: 5, .. }),
+            "{}",
+            err
+        );
+        assert_eq!(err.to_string().matches("line").count(), 0, "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_without_location() {
+        assert_eq!(
+            without_location("unknown variant `x` at line 5 column 20"),
+            "unknown variant `x`"
+        );
+        assert_eq!(
+            without_location("expected 'at line 2'"),
+            "expected 'at line 2'"
+        );
+    }
+
+    #[test]
+    fn test_invalid_tier_rejected() {
+        let dir = temp_dir("extra-tier");
+        write_spec(
+            &dir,
+            "tier.json",
+            r#"{ "tier": 7, "message_type": "stream", "code": "1", "assertions": [{ "reply_status": "ok" }] }"#,
+        );
+
+        let err = load_extra_tests(&dir).unwrap_err();
+        assert!(err.to_string().contains("invalid tier 7"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,43 +1,98 @@
 //! Test harness for launching kernels and running conformance tests.
 
+use crate::channels::{
+    self, Channel, HeartbeatChannel, MessageChannel, RawIoPub, RawShell, RelayedChannels,
+};
+use crate::config::BenchmarkConfig;
+use crate::extra_tests::ExtraTest;
+use crate::iopub::{IoPubCollector, TimedMessage};
+use crate::kernel_log::{KernelLog, KERNEL_LOG_EXCERPT_LINES};
+use crate::launch::{
+    check_transport, ipc_socket_paths, KernelLaunchOptions, KernelUnderTestBuilder, LaunchInfo,
+    ScratchDir,
+};
+use crate::resources::{ResourceMonitor, RESOURCES_SUPPORTED};
+use crate::server::{self, JupyterServer, ServerKernel};
 use crate::snippets::LanguageSnippets;
-use crate::types::{KernelReport, TestCategory, TestRecord, TestResult};
+use crate::transcript::{
+    transcript_path, Exchange, MessageSource, Recorder, ReplayKernel, ReplaySession, Transcript,
+};
+use crate::types::{
+    FailureKind, IsolationMode, IsolationReport, KernelCrash, KernelExitStatus, KernelReport,
+    TestCategory, TestRecord, TestResult,
+};
 use chrono::Utc;
 use jupyter_protocol::connection_info::{ConnectionInfo, Transport};
 use jupyter_protocol::messaging::{
-    CommClose, CommOpen, ExecuteRequest, ExecutionState, InputReply, JupyterMessage,
-    JupyterMessageContent, KernelInfoReply, KernelInfoRequest, ReplyStatus, ShutdownRequest,
-    Status,
+    CommClose, CommOpen, DebugRequest, ExecuteRequest, ExecutionState, InputReply, InputRequest,
+    JupyterMessage, JupyterMessageContent, KernelInfoReply, KernelInfoRequest, ReplyStatus,
+    ShutdownRequest, Status, StreamContent, UnknownMessage,
 };
 use runtimelib::{
     create_client_control_connection, create_client_heartbeat_connection,
     create_client_iopub_connection, create_client_shell_connection_with_identity,
-    create_client_stdin_connection_with_identity, peer_identity_for_session, peek_ports,
-    wait_for_iopub_welcome, ClientControlConnection, ClientHeartbeatConnection,
-    ClientIoPubConnection, ClientShellConnection, ClientStdinConnection, KernelspecDir,
+    create_client_stdin_connection_with_identity, peer_identity_for_session,
+    wait_for_iopub_welcome, ClientIoPubConnection, KernelspecDir,
 };
-use std::net::{IpAddr, Ipv4Addr};
-use std::path::PathBuf;
-use std::process::Stdio;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::process::Child;
+use tokio::sync::watch;
 use tokio::time::timeout;
 
-/// Timeout for waiting for iopub_welcome message (JEP 65)
-/// Kernels using XPUB sockets will send this immediately, others will timeout gracefully
-const IOPUB_WELCOME_TIMEOUT: Duration = Duration::from_millis(500);
+/// First wait for a kernel_info_reply while probing a starting kernel
+const STARTUP_PROBE_INITIAL: Duration = Duration::from_millis(100);
+
+/// Longest wait for a kernel_info_reply between startup probes
+const STARTUP_PROBE_MAX: Duration = Duration::from_secs(4);
+
+/// Held from picking TCP ports until the kernel has bound them, so concurrent
+/// launches never race for the same ports
+static PORT_ALLOCATION: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Launches tried, each on fresh ports, when a TCP kernel can't come up on
+/// the ports it was given (something else may have bound them first)
+const LAUNCH_ATTEMPTS: u32 = 3;
+
+/// How long to keep waiting for a properly parented shell reply once an
+/// un-parented reply of the expected type has arrived
+const UNPARENTED_REPLY_GRACE: Duration = Duration::from_millis(500);
 
 #[derive(Error, Debug)]
 pub enum HarnessError {
     #[error("Kernel launch failed: {0}")]
     LaunchFailed(String),
+    /// The kernel process exited before it answered kernel_info
+    #[error("Kernel launch failed: {0}")]
+    ExitedDuringStartup(String),
+    #[error("{0} transport is not supported on this platform")]
+    UnsupportedTransport(String),
+    #[error("Kernelspec argv has unknown placeholders: {}", .0.join(", "))]
+    UnknownPlaceholders(Vec<String>),
+    #[error("Invalid kernel command: {0}")]
+    InvalidCommand(String),
+    #[error("Can't read connection file {0}")]
+    InvalidConnectionFile(String),
+    #[error("Invalid Jupyter Server URL {0}")]
+    InvalidServerUrl(String),
     #[error("Connection failed: {0}")]
     ConnectionFailed(String),
     #[error("Timeout waiting for {0}")]
     Timeout(String),
     #[error("Protocol error: {0}")]
     ProtocolError(String),
+    #[error("No kernel process to {0}: attached to a kernel the harness didn't start")]
+    NotOwned(&'static str),
+    /// The test needs something the harness's link to the kernel can't
+    /// provide, such as a second connection while replaying a transcript
+    #[error("{0}")]
+    NotAvailable(String),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("Runtime error: {0}")]
@@ -49,102 +104,351 @@ pub type Result<T> = std::result::Result<T, HarnessError>;
 /// A kernel under test with all its connections.
 #[allow(dead_code)]
 pub struct KernelUnderTest {
-    /// The kernel process
-    process: Child,
-    /// Connection info
-    connection_info: ConnectionInfo,
-    /// Path to connection file
-    connection_path: PathBuf,
+    /// The kernel process; None when attached to a kernel we didn't start
+    process: Option<Child>,
+    /// Connection info; None when the channels don't go over ZeroMQ
+    connection_info: Option<ConnectionInfo>,
+    /// Path to the connection file we wrote, if we launched the kernel
+    connection_path: Option<PathBuf>,
+    /// Working directory the process was started in; None if attached
+    cwd: Option<PathBuf>,
+    /// Scratch working directory, removed along with the kernel
+    scratch_dir: Option<ScratchDir>,
     /// Session ID
     session_id: String,
+    /// What carries the channels below
+    link: KernelLink,
     /// Shell channel
-    shell: ClientShellConnection,
-    /// IOPub channel
-    iopub: ClientIoPubConnection,
+    shell: Box<dyn MessageChannel>,
+    /// IOPub channel, read by a background task
+    iopub: IoPubCollector,
     /// Control channel
-    control: ClientControlConnection,
+    control: Box<dyn MessageChannel>,
     /// Stdin channel
-    stdin: ClientStdinConnection,
+    stdin: Box<dyn MessageChannel>,
     /// Heartbeat channel
-    heartbeat: ClientHeartbeatConnection,
+    heartbeat: Box<dyn HeartbeatChannel>,
+    /// Records channel traffic while a test runs, for the transcript
+    recorder: Recorder,
+    /// Channel traffic of each test's last attempt, for the transcript
+    exchanges: Vec<Exchange>,
+    /// The attempt being recorded, its events still in `recorder`
+    recording: Option<Exchange>,
+    /// What the running test needed that the link couldn't provide
+    unavailable: OnceLock<String>,
     /// Kernel info (populated after startup)
     kernel_info: Option<KernelInfoReply>,
     /// Language snippets for this kernel
     snippets: LanguageSnippets,
+    /// Snippet fields replaced from the builder's snippet overrides
+    overridden_snippets: Vec<String>,
     /// Per-test timeout
     test_timeout: Duration,
     /// Whether iopub_welcome was received (JEP 65 support)
     iopub_welcome_received: bool,
+    /// Thresholds for benchmark tests
+    benchmark_config: BenchmarkConfig,
+    /// Metrics recorded by the currently running test
+    metrics: BTreeMap<String, f64>,
+    /// Slow-benchmark flag raised by the currently running test
+    flagged: Option<String>,
+    /// Whether the currently running test accepted a reply without parent_header
+    missing_parent_header: bool,
+    /// Every IOPub message and shell/control reply read during the run, for suite finalizers
+    observed: Vec<JupyterMessage>,
+    /// How the kernel process was started
+    launch_info: LaunchInfo,
+    /// Target and options this kernel was launched or attached with
+    builder: KernelUnderTestBuilder,
+    /// How long the process gets to exit on its own after shutdown_request
+    shutdown_grace: Duration,
+    /// Whether a shutdown_request has been sent on control
+    shutdown_requested: bool,
+    /// Whether the harness killed the process rather than it exiting
+    force_killed: bool,
+    /// Sequence number of the last DAP request sent in a debug_request
+    debug_seq: u64,
+    /// How long to wait for iopub_welcome on new subscriptions
+    iopub_welcome_timeout: Duration,
+    /// Time from spawning the process to its first kernel_info_reply
+    startup_duration: Duration,
+    /// Captured stdout/stderr of the kernel process
+    kernel_log: KernelLog,
+    /// Whether shutdown tests may shut down an attached kernel
+    allow_shutdown: bool,
+    /// How many times the suite re-runs a failing test
+    retries: u32,
 }
 
 impl KernelUnderTest {
+    /// Start building a kernel launch with non-default options.
+    pub fn builder(kernelspec: KernelspecDir) -> KernelUnderTestBuilder {
+        KernelUnderTestBuilder::new(kernelspec)
+    }
+
     /// Launch a kernel and establish all connections.
+    ///
+    /// Equivalent to `KernelUnderTest::builder(kernelspec).timeout(test_timeout).launch()`.
     pub async fn launch(
         kernelspec: KernelspecDir,
         test_timeout: Duration,
     ) -> Result<Self> {
-        let session_id = uuid::Uuid::new_v4().to_string();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-
-        // Find available ports
-        let ports = peek_ports(ip, 5).await?;
-
-        let connection_info = ConnectionInfo {
-            transport: Transport::TCP,
-            ip: ip.to_string(),
-            stdin_port: ports[0],
-            control_port: ports[1],
-            hb_port: ports[2],
-            shell_port: ports[3],
-            iopub_port: ports[4],
-            signature_scheme: "hmac-sha256".to_string(),
-            key: uuid::Uuid::new_v4().to_string(),
-            kernel_name: Some(kernelspec.kernel_name.clone()),
-        };
+        Self::builder(kernelspec).timeout(test_timeout).launch().await
+    }
 
-        // Write connection file
+    /// Launch a kernel with explicit options and establish all connections.
+    ///
+    /// A TCP kernel that exits during startup, or refuses connections, may
+    /// have lost a port to another process between allocation and bind, so
+    /// it is relaunched on fresh ports, up to `LAUNCH_ATTEMPTS` launches in all.
+    pub async fn launch_with_options(
+        kernelspec: KernelspecDir,
+        options: KernelLaunchOptions,
+    ) -> Result<Self> {
+        check_transport(&options.transport)?;
+        let mut tried_ports = HashSet::new();
+        let mut attempt = 1;
+        loop {
+            let launched =
+                Self::launch_once(kernelspec.clone(), options.clone(), &mut tried_ports).await;
+            match launched {
+                Err(e)
+                    if attempt < LAUNCH_ATTEMPTS
+                        && options.transport == Transport::TCP
+                        && is_port_conflict(&e) =>
+                {
+                    eprintln!(
+                        "Launch attempt {}/{} of {} failed ({}); retrying on fresh ports",
+                        attempt, LAUNCH_ATTEMPTS, kernelspec.kernel_name, e
+                    );
+                    attempt += 1;
+                }
+                launched => return launched,
+            }
+        }
+    }
+
+    /// Launch once on newly allocated ports, adding them to `tried_ports`.
+    async fn launch_once(
+        kernelspec: KernelspecDir,
+        options: KernelLaunchOptions,
+        tried_ports: &mut HashSet<u16>,
+    ) -> Result<Self> {
+        let session_id = uuid::Uuid::new_v4().to_string();
         let runtime_dir = runtimelib::dirs::runtime_dir();
         tokio::fs::create_dir_all(&runtime_dir).await?;
+
+        // peek_ports only finds ports that are free right now, so hold them
+        // until this kernel has bound them; another launch running
+        // concurrently could otherwise be handed the same ones
+        let port_guard = match options.transport {
+            Transport::TCP => Some(PORT_ALLOCATION.lock().await),
+            Transport::IPC => None,
+        };
+
+        // Find available ports (IPC uses them as socket suffixes)
+        let ports = match options.transport {
+            Transport::TCP => options.allocate_ports(tried_ports).await?,
+            Transport::IPC => (1..=5).collect(),
+        };
+        tried_ports.extend(&ports);
+
+        let key = options
+            .signature_key
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let connection_info = options.connection_info(
+            &kernelspec.kernel_name,
+            &session_id,
+            &ports,
+            &runtime_dir,
+            key,
+        );
+
         let connection_path = runtime_dir.join(format!("kernel-test-{}.json", session_id));
+
+        // Resolve argv placeholders before writing anything to disk
+        let launch_info = LaunchInfo::resolve(&kernelspec, &connection_path)?;
+        let builder = KernelUnderTestBuilder::new(kernelspec.clone()).with_options(options.clone());
+        let mut kernelspec = kernelspec;
+        kernelspec.kernelspec.argv = launch_info.argv.clone();
+
+        // Every launch, relaunches included, starts from an empty directory
+        let scratch_dir = if options.temp_cwd {
+            Some(ScratchDir::create(&session_id)?)
+        } else {
+            None
+        };
+        let cwd = match &scratch_dir {
+            Some(dir) => Some(dir.path().to_path_buf()),
+            None => options.cwd.clone().or_else(|| std::env::current_dir().ok()),
+        };
+
+        // Write connection file
         let content = serde_json::to_string(&connection_info)
             .map_err(|e| HarnessError::LaunchFailed(e.to_string()))?;
         tokio::fs::write(&connection_path, content).await?;
 
-        // Launch kernel process (capture stderr for diagnostics)
-        let mut process = kernelspec
-            .command(&connection_path, Some(Stdio::null()), Some(Stdio::piped()))?
-            .spawn()
-            .map_err(|e| HarnessError::LaunchFailed(e.to_string()))?;
-
-        // Give kernel time to start
-        tokio::time::sleep(Duration::from_millis(2000)).await;
-
-        // Check if kernel process has already exited (crashed during startup)
-        match process.try_wait() {
-            Ok(Some(exit_status)) => {
-                // Process has already exited - read stderr for diagnostics
-                let mut stderr_output = String::new();
-                if let Some(stderr) = process.stderr.take() {
-                    use tokio::io::AsyncReadExt;
-                    let mut reader = tokio::io::BufReader::new(stderr);
-                    let _ = reader.read_to_string(&mut stderr_output).await;
+        let spawned_at = Instant::now();
+        let (mut process, mut kernel_log) = Self::spawn_process(
+            kernelspec,
+            &connection_path,
+            &options,
+            scratch_dir.as_ref().map(ScratchDir::path),
+        )
+        .await?;
+
+        // Connecting retries refused connections indefinitely, so a kernel
+        // that dies before binding its ports would otherwise hang the launch
+        let deadline = spawned_at + options.startup_timeout;
+        let connecting = Self::connect(builder, connection_info.clone(), session_id, deadline);
+        let connected = tokio::select! {
+            connected = connecting => connected,
+            exited = process.wait() => match exited {
+                Ok(exit_status) => {
+                    kernel_log.settle().await;
+                    let stderr = kernel_log.stderr_tail(usize::MAX);
+                    Err(exited_during_startup(exit_status, stderr))
                 }
-                let msg = if stderr_output.is_empty() {
-                    format!("Kernel process exited with {} before connections could be established", exit_status)
-                } else {
-                    format!("Kernel process exited with {} before connections could be established. Stderr:\n{}", exit_status, stderr_output)
-                };
-                eprintln!("{}", msg);
-                return Err(HarnessError::LaunchFailed(msg));
-            }
-            Ok(None) => {
-                // Process still running - good
-            }
+                Err(e) => Err(e.into()),
+            },
+        };
+        let mut kernel = match connected {
+            Ok(kernel) => kernel,
             Err(e) => {
-                eprintln!("Warning: could not check kernel process status: {}", e);
+                let _ = process.kill().await;
+                remove_launch_files(&connection_path, Some(&connection_info));
+                return Err(e);
+            }
+        };
+        // Every channel connected, so the kernel has bound all its ports and
+        // other launches may pick theirs while this one probes for readiness
+        drop(port_guard);
+        kernel.process = Some(process);
+        kernel.connection_path = Some(connection_path.clone());
+        kernel.cwd = cwd;
+        kernel.scratch_dir = scratch_dir;
+        kernel.launch_info = launch_info;
+        kernel.kernel_log = kernel_log;
+
+        // If it never becomes ready, dropping `kernel` kills the process
+        kernel.start(spawned_at, options).await?;
+
+        Ok(kernel)
+    }
+
+    /// Start the process for `kernelspec`, whose argv has its placeholders
+    /// resolved, capturing its output for diagnostics if `options` asks to.
+    async fn spawn_process(
+        kernelspec: KernelspecDir,
+        connection_path: &Path,
+        options: &KernelLaunchOptions,
+        scratch_dir: Option<&Path>,
+    ) -> Result<(Child, KernelLog)> {
+        let kernel_name = kernelspec.kernel_name.clone();
+        let output = || {
+            if options.capture_output {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
             }
+        };
+        let mut command = kernelspec.command(connection_path, Some(output()), Some(output()))?;
+        options.apply_to_command(&mut command);
+        if let Some(dir) = scratch_dir {
+            command.current_dir(dir);
+        }
+        // Until the process is owned by a KernelUnderTest (whose Drop cleans
+        // up), a cancelled launch must not leave it running
+        command.kill_on_drop(true);
+        let mut process = command
+            .spawn()
+            .map_err(|e| HarnessError::LaunchFailed(e.to_string()))?;
+        let kernel_log = if options.capture_output {
+            KernelLog::capture(&mut process, options.log_dir.as_deref(), &kernel_name).await?
+        } else {
+            KernelLog::default()
+        };
+        Ok((process, kernel_log))
+    }
+
+    /// Start building a launch of `command`, a kernel command line such as
+    /// `path/to/kernel --connection {connection_file}`, without a kernelspec.
+    ///
+    /// The kernel is reported as `kernel_name`, or the executable's file name
+    /// if None. Its language comes from kernel_info as usual.
+    pub fn command_builder(
+        command: &str,
+        kernel_name: Option<&str>,
+    ) -> Result<KernelUnderTestBuilder> {
+        KernelUnderTestBuilder::command(command, kernel_name)
+    }
+
+    /// Start building an attachment to a kernel that is already running.
+    pub fn attach_builder(connection_info: ConnectionInfo) -> KernelUnderTestBuilder {
+        KernelUnderTestBuilder::attach_to(connection_info)
+    }
+
+    /// Start building an attachment to the running kernel described by the
+    /// connection file at `path`, e.g. one from `jupyter console --existing`.
+    pub fn connection_file_builder(path: &Path) -> Result<KernelUnderTestBuilder> {
+        KernelUnderTestBuilder::connection_file(path)
+    }
+
+    /// Start building a kernel that the Jupyter Server `server` starts from
+    /// its kernelspec `kernel_name`.
+    ///
+    /// The harness talks to it over the server's websocket, which carries
+    /// every channel but the heartbeat, and deletes it through the REST API
+    /// on shutdown.
+    pub fn server_builder(server: JupyterServer, kernel_name: &str) -> KernelUnderTestBuilder {
+        KernelUnderTestBuilder::server(server, kernel_name)
+    }
+
+    /// Connect to a kernel someone else started, e.g. from the connection
+    /// file of `jupyter console --existing`.
+    ///
+    /// Nothing is spawned and no connection file is written. The harness
+    /// never kills the process, and shutdown tests only run with
+    /// [`KernelLaunchOptions::allow_shutdown`].
+    pub async fn attach(
+        connection_info: ConnectionInfo,
+        options: KernelLaunchOptions,
+    ) -> Result<Self> {
+        check_transport(&connection_info.transport)?;
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let started_at = Instant::now();
+        let builder = KernelUnderTestBuilder::attach_to(connection_info.clone())
+            .with_options(options.clone());
+        let deadline = started_at + options.startup_timeout;
+        let mut kernel = Self::connect(builder, connection_info, session_id, deadline).await?;
+        kernel.start(started_at, options).await?;
+        Ok(kernel)
+    }
+
+    /// Open all channels to the kernel described by `connection_info`,
+    /// configured from `builder`'s options, failing with a startup timeout
+    /// if they aren't all open by `deadline`.
+    ///
+    /// The result owns no process; launching fills that in afterwards.
+    async fn connect(
+        builder: KernelUnderTestBuilder,
+        connection_info: ConnectionInfo,
+        session_id: String,
+        deadline: Instant,
+    ) -> Result<Self> {
+        let opening = Self::open_channels(builder, connection_info, session_id);
+        match tokio::time::timeout_at(deadline.into(), opening).await {
+            Ok(opened) => opened,
+            Err(_) => Err(HarnessError::Timeout("kernel startup".to_string())),
         }
+    }
 
+    async fn open_channels(
+        builder: KernelUnderTestBuilder,
+        connection_info: ConnectionInfo,
+        session_id: String,
+    ) -> Result<Self> {
         // Create peer identity for shell/stdin (must share identity)
         let identity = peer_identity_for_session(&session_id)?;
 
@@ -157,9 +461,11 @@ impl KernelUnderTest {
         .await
         .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
 
-        let mut iopub = create_client_iopub_connection(&connection_info, "", &session_id)
+        let iopub = create_client_iopub_connection(&connection_info, "", &session_id)
             .await
             .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+        let recorder = Recorder::default();
+        let iopub = IoPubCollector::start(iopub, recorder.clone());
 
         let control = create_client_control_connection(&connection_info, &session_id)
             .await
@@ -174,85 +480,245 @@ impl KernelUnderTest {
             .await
             .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
 
-        // Wait for iopub_welcome (JEP 65) or timeout gracefully for legacy kernels
-        let iopub_welcome_received =
-            match wait_for_iopub_welcome(&mut iopub, IOPUB_WELCOME_TIMEOUT).await {
-                Ok(Some(_subscription)) => true,
-                Ok(None) => false, // Timeout or non-welcome message - kernel doesn't support XPUB
-                Err(_) => false,   // Error during wait - proceed anyway
-            };
+        let channels = KernelChannels {
+            shell: channels::recorded(Channel::Shell, &recorder, shell),
+            control: channels::recorded(Channel::Control, &recorder, control),
+            stdin: channels::recorded(Channel::Stdin, &recorder, stdin),
+            heartbeat: channels::recorded_heartbeat(&recorder, heartbeat),
+            iopub,
+        };
+        Ok(Self::with_channels(
+            builder,
+            Some(connection_info),
+            session_id,
+            KernelLink::Zmq,
+            channels,
+            recorder,
+        ))
+    }
+
+    /// Start a kernel through `server` and connect to its channels websocket.
+    ///
+    /// The server picks the connection file and signature key, so a builder
+    /// asking for a particular key can't be honored.
+    pub(crate) async fn start_on_server(
+        server: JupyterServer,
+        kernel_name: &str,
+        options: KernelLaunchOptions,
+    ) -> Result<Self> {
+        if options.signature_key.is_some() {
+            return Err(HarnessError::NotAvailable(format!(
+                "a chosen signature key is not available {}",
+                KernelLink::SERVER
+            )));
+        }
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let started_at = Instant::now();
+        let builder = KernelUnderTestBuilder::server(server.clone(), kernel_name)
+            .with_options(options.clone());
+
+        // Deleted again on drop if anything below fails
+        let server_kernel = server.start_kernel(kernel_name).await?;
+        let deadline = started_at + options.startup_timeout;
+        let socket = tokio::time::timeout_at(deadline.into(), server_kernel.connect(&session_id))
+            .await
+            .map_err(|_| HarnessError::Timeout("kernel startup".to_string()))??;
+
+        let recorder = Recorder::default();
+        let relayed = channels::relay(&session_id, recorder.clone(), |relay| {
+            server::relay_websocket(socket, relay)
+        });
+        let channels = KernelChannels {
+            shell: channels::recorded(Channel::Shell, &recorder, relayed.shell),
+            control: channels::recorded(Channel::Control, &recorder, relayed.control),
+            stdin: channels::recorded(Channel::Stdin, &recorder, relayed.stdin),
+            heartbeat: Box::new(relayed.heartbeat),
+            iopub: relayed.iopub,
+        };
+        let mut kernel = Self::with_channels(
+            builder,
+            None,
+            session_id,
+            KernelLink::Server(server_kernel),
+            channels,
+            recorder,
+        );
+        kernel.start(started_at, options).await?;
+        Ok(kernel)
+    }
 
+    /// A kernel that re-runs tests against the exchanges recorded in
+    /// `transcript` instead of talking to a process.
+    ///
+    /// Each test is served its own exchange by
+    /// [`begin_exchange`](Self::begin_exchange). Anything an exchange can't
+    /// stand in for, such as a second connection or a relaunch, makes the
+    /// test report as skipped.
+    pub(crate) fn replaying(transcript: Arc<Transcript>) -> Self {
+        let builder = KernelUnderTestBuilder::replay(transcript.clone());
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let mut replay = ReplaySession::new(transcript.clone());
+        let relayed = replay.serve(None, &session_id);
+        let recorder = Recorder::default();
+        let mut kernel = Self::with_channels(
+            builder,
+            None,
+            session_id,
+            KernelLink::Replay(replay),
+            KernelChannels::from(relayed),
+            recorder,
+        );
+        kernel.snippets = transcript
+            .snippets
+            .clone()
+            .unwrap_or_else(|| LanguageSnippets::for_language(&transcript.language));
+        kernel.kernel_info = transcript.kernel_info.clone();
+        kernel.test_timeout = transcript.test_timeout;
+        kernel.iopub_welcome_received = transcript.iopub_welcome_received;
+        // What was recorded is all there is: retrying can't change it, and
+        // shutting down ends nothing
+        kernel.allow_shutdown = true;
+        kernel.retries = 0;
+        kernel
+    }
+
+    /// A kernel on `channels`, set up from `builder`'s options, with no
+    /// process and nothing learned from kernel_info yet.
+    fn with_channels(
+        builder: KernelUnderTestBuilder,
+        connection_info: Option<ConnectionInfo>,
+        session_id: String,
+        link: KernelLink,
+        channels: KernelChannels,
+        recorder: Recorder,
+    ) -> Self {
+        let options = builder.options().clone();
         // Default snippets (will be updated after kernel_info)
         let snippets = LanguageSnippets::for_language("python");
 
-        let mut kernel = Self {
-            process,
+        Self {
+            process: None,
             connection_info,
-            connection_path,
+            connection_path: None,
+            cwd: None,
+            scratch_dir: None,
             session_id,
-            shell,
-            iopub,
-            control,
-            stdin,
-            heartbeat,
+            link,
+            shell: channels.shell,
+            iopub: channels.iopub,
+            control: channels.control,
+            stdin: channels.stdin,
+            heartbeat: channels.heartbeat,
+            recorder,
+            exchanges: Vec::new(),
+            recording: None,
+            unavailable: OnceLock::new(),
             kernel_info: None,
             snippets,
-            test_timeout,
-            iopub_welcome_received,
-        };
+            overridden_snippets: Vec::new(),
+            test_timeout: options.test_timeout,
+            iopub_welcome_received: false,
+            benchmark_config: options.benchmark_config.clone(),
+            metrics: BTreeMap::new(),
+            flagged: None,
+            missing_parent_header: false,
+            observed: Vec::new(),
+            launch_info: LaunchInfo::default(),
+            builder,
+            shutdown_grace: options.shutdown_grace,
+            shutdown_requested: false,
+            force_killed: false,
+            debug_seq: 0,
+            iopub_welcome_timeout: options.iopub_welcome_timeout,
+            startup_duration: Duration::ZERO,
+            kernel_log: KernelLog::default(),
+            allow_shutdown: options.allow_shutdown,
+            retries: options.retries,
+        }
+    }
 
-        // Get kernel info to determine language
-        kernel.fetch_kernel_info().await?;
+    /// Wait for the kernel to answer kernel_info, then finish setting up
+    /// from its reply and `options`.
+    async fn start(&mut self, started_at: Instant, options: KernelLaunchOptions) -> Result<()> {
+        // Probe until the kernel answers kernel_info, which also tells us the
+        // language
+        self.fetch_kernel_info(started_at, options.startup_timeout)
+            .await?;
+        self.startup_duration = started_at.elapsed();
+
+        // Wait for iopub_welcome (JEP 65) now that the kernel is listening;
+        // kernels without XPUB sockets time out gracefully
+        let deadline = Instant::now() + options.iopub_welcome_timeout;
+        let is_welcome = |msg: &JupyterMessage| msg.content.message_type() == "iopub_welcome";
+        self.iopub_welcome_received = matches!(
+            self.next_iopub(deadline, is_welcome).await,
+            Ok(Some(_welcome))
+        );
+
+        if let Some(snippets) = options.snippets {
+            self.snippets = snippets;
+        }
+        let kernel_name = self.builder.kernel_name();
+        self.overridden_snippets = options
+            .snippet_overrides
+            .apply(&kernel_name, &mut self.snippets);
 
-        Ok(kernel)
+        Ok(())
     }
 
-    /// Fetch kernel_info and update snippets.
+    /// Probe a freshly spawned kernel with kernel_info_request until it
+    /// replies, then update snippets from its language_info.
     ///
-    /// Retries the kernel_info_request up to 3 times to handle slow-starting
-    /// kernels (e.g., xeus-octave) where the initial request may be sent before
-    /// the kernel has bound to its ports.
-    async fn fetch_kernel_info(&mut self) -> Result<()> {
-        let max_attempts = 3;
+    /// Each probe waits twice as long as the last for a reply (up to
+    /// `STARTUP_PROBE_MAX`), so fast kernels are picked up within ~100ms
+    /// while slow ones (e.g. JVM-based kernels) get the whole `budget`,
+    /// measured from `spawned_at`. A process that exits meanwhile fails
+    /// immediately with its stderr; one that never accepted a connection
+    /// fails as refused rather than timed out.
+    async fn fetch_kernel_info(&mut self, spawned_at: Instant, budget: Duration) -> Result<()> {
+        let mut wait = STARTUP_PROBE_INITIAL;
         let mut last_error = None;
 
-        for attempt in 0..max_attempts {
-            if attempt > 0 {
-                // Wait longer between retries to give the kernel time to start
-                tokio::time::sleep(Duration::from_secs(2)).await;
+        loop {
+            if let Some(Ok(Some(exit_status))) = self.process.as_mut().map(|p| p.try_wait()) {
+                let stderr = self.try_read_stderr().await;
+                return Err(exited_during_startup(exit_status, stderr));
             }
 
-            let request: JupyterMessage = KernelInfoRequest {}.into();
-            if let Err(e) = self.shell.send(request).await {
-                last_error = Some(HarnessError::ProtocolError(e.to_string()));
-                continue;
+            let remaining = budget.saturating_sub(spawned_at.elapsed());
+            if remaining.is_zero() {
+                break;
             }
 
-            match timeout(self.test_timeout, self.shell.read()).await {
-                Ok(Ok(reply)) => {
-                    if let JupyterMessageContent::KernelInfoReply(info) = reply.content {
-                        self.snippets =
-                            LanguageSnippets::for_language(&info.language_info.name);
-                        self.kernel_info = Some(*info);
-                        return Ok(());
-                    } else {
-                        return Err(HarnessError::ProtocolError(format!(
-                            "Expected kernel_info_reply, got {:?}",
-                            reply.content.message_type()
-                        )));
+            let request: JupyterMessage = KernelInfoRequest {}.into();
+            if let Err(e) = self.shell.send(request).await {
+                last_error = Some(e.to_string());
+            } else {
+                // Replies to earlier probes are just as good as this one's
+                match timeout(wait.min(remaining), self.shell.read()).await {
+                    Ok(Ok(reply)) => {
+                        if let JupyterMessageContent::KernelInfoReply(info) = reply.content {
+                            self.snippets =
+                                LanguageSnippets::for_language(&info.language_info.name);
+                            self.kernel_info = Some(*info);
+                            return Ok(());
+                        } else {
+                            return Err(HarnessError::ProtocolError(format!(
+                                "Expected kernel_info_reply, got {:?}",
+                                reply.content.message_type()
+                            )));
+                        }
                     }
-                }
-                Ok(Err(e)) => {
-                    eprintln!("  kernel_info attempt {}: protocol error: {}", attempt + 1, e);
-                    last_error = Some(HarnessError::ProtocolError(e.to_string()));
-                }
-                Err(_) => {
-                    eprintln!("  kernel_info attempt {}: timeout", attempt + 1);
-                    last_error = Some(HarnessError::Timeout("kernel_info_reply".to_string()));
+                    Ok(Err(e)) => last_error = Some(e.to_string()),
+                    Err(_) => {}
                 }
             }
+            wait = (wait * 2).min(STARTUP_PROBE_MAX);
         }
 
+        if let Some(e) = &last_error {
+            eprintln!("  last kernel_info probe error: {}", e);
+        }
         // Try to capture kernel stderr for diagnostics
         if let Some(stderr) = self.try_read_stderr().await {
             if !stderr.is_empty() {
@@ -260,7 +726,10 @@ impl KernelUnderTest {
             }
         }
 
-        Err(last_error.unwrap_or_else(|| HarnessError::Timeout("kernel_info_reply".to_string())))
+        match last_error {
+            Some(e) if is_connection_refused(&e) => Err(HarnessError::ConnectionFailed(e)),
+            _ => Err(HarnessError::Timeout("kernel startup".to_string())),
+        }
     }
 
     /// Get kernel info.
@@ -268,172 +737,791 @@ impl KernelUnderTest {
         self.kernel_info.as_ref()
     }
 
+    /// Time from spawning the kernel process to its first kernel_info_reply.
+    pub fn startup_duration(&self) -> Duration {
+        self.startup_duration
+    }
+
+    /// Get the resolved command line the kernel was started with.
+    pub fn launch_info(&self) -> &LaunchInfo {
+        &self.launch_info
+    }
+
+    /// Connection file the harness wrote for this kernel; None when attached.
+    pub fn connection_path(&self) -> Option<&Path> {
+        self.connection_path.as_deref()
+    }
+
+    /// Working directory the kernel process was started in; None when attached.
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// Session id the harness uses for its own messages.
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// A builder for launching another instance of this kernel with the same
+    /// kernelspec and options, or None when attached (there is no kernelspec)
+    /// or replaying a transcript.
+    pub fn relaunch_builder(&self) -> Option<KernelUnderTestBuilder> {
+        if let KernelLink::Replay(_) = self.link {
+            self.unavailable("another instance of the kernel");
+            return None;
+        }
+        (!self.builder.is_attach()).then(|| self.builder.clone())
+    }
+
+    /// Options this kernel was launched or attached with.
+    pub fn options(&self) -> &KernelLaunchOptions {
+        self.builder.options()
+    }
+
+    /// Whether this is a kernel someone else started, which we only attached to.
+    ///
+    /// A replayed kernel is attached if the recorded one was.
+    pub fn is_attached(&self) -> bool {
+        match &self.link {
+            KernelLink::Replay(replay) => replay.transcript().attached,
+            KernelLink::Zmq => self.process.is_none(),
+            KernelLink::Server(_) => false,
+        }
+    }
+
+    /// OS process id of the kernel, if the harness started it.
+    pub fn pid(&self) -> Option<u32> {
+        self.process.as_ref().and_then(Child::id)
+    }
+
+    /// How the kernel process ended, if the harness started it and it has
+    /// exited.
+    pub fn exit_status(&mut self) -> Option<ExitStatus> {
+        self.process.as_mut()?.try_wait().ok().flatten()
+    }
+
+    /// Whether shutdown tests may shut this kernel down.
+    pub fn may_shut_down(&self) -> bool {
+        !self.is_attached() || self.allow_shutdown
+    }
+
     /// Get language snippets.
     pub fn snippets(&self) -> &LanguageSnippets {
         &self.snippets
     }
 
+    /// Names of the snippet fields replaced by snippet overrides.
+    pub fn overridden_snippets(&self) -> &[String] {
+        &self.overridden_snippets
+    }
+
     /// Check if iopub_welcome was received (JEP 65 support).
     pub fn iopub_welcome_received(&self) -> bool {
         self.iopub_welcome_received
     }
 
-    /// Send a request on shell and wait for reply.
-    pub async fn shell_request(
+    /// Get the per-test timeout.
+    pub fn test_timeout(&self) -> Duration {
+        self.test_timeout
+    }
+
+    /// Change the per-test timeout, e.g. for a test that needs longer.
+    pub fn set_test_timeout(&mut self, test_timeout: Duration) {
+        self.test_timeout = test_timeout;
+    }
+
+    /// Get benchmark thresholds.
+    pub fn benchmark_config(&self) -> &BenchmarkConfig {
+        &self.benchmark_config
+    }
+
+    /// Record a metric for the currently running test.
+    pub fn record_metric(&mut self, name: &str, value: f64) {
+        self.metrics.insert(name.to_string(), value);
+    }
+
+    /// Flag the currently running benchmark as slow without failing it.
+    pub fn flag_slow(&mut self, note: impl Into<String>) {
+        self.flagged = Some(note.into());
+    }
+
+    /// All IOPub messages and shell/control replies read so far, across every test.
+    pub fn observed_messages(&self) -> &[JupyterMessage] {
+        &self.observed
+    }
+
+    fn observe(&mut self, msg: &JupyterMessage) {
+        self.observed.push(msg.clone());
+    }
+
+    /// Claim the next IOPub message matching `wanted`, waiting until
+    /// `deadline` for one; None if none arrived in time.
+    async fn next_iopub(
         &mut self,
-        content: impl Into<JupyterMessageContent>,
-    ) -> Result<JupyterMessage> {
-        let request: JupyterMessage = JupyterMessage::new(content, None);
-        self.shell
-            .send(request)
+        deadline: Instant,
+        wanted: impl Fn(&JupyterMessage) -> bool,
+    ) -> Result<Option<TimedMessage>> {
+        self.iopub
+            .next_matching(&mut self.observed, deadline, wanted)
             .await
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+    }
 
-        timeout(self.test_timeout, self.shell.read())
-            .await
-            .map_err(|_| HarnessError::Timeout("shell reply".to_string()))?
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))
+    /// Claim every IOPub message until `deadline`, including any that
+    /// arrived before the call and are still unclaimed.
+    async fn collect_iopub(&mut self, deadline: Instant) -> Result<Vec<JupyterMessage>> {
+        let mut iopub_messages = Vec::new();
+        while let Some((_, msg)) = self.next_iopub(deadline, |_| true).await? {
+            iopub_messages.push(msg);
+        }
+        Ok(iopub_messages)
     }
 
-    /// Send a request on shell and wait for reply, also collecting IOPub messages.
-    pub async fn shell_request_with_iopub(
+    /// Claim the IOPub messages parented to `msg_id` up to and including its
+    /// idle status, waiting until `deadline`. The flag is false if idle
+    /// hadn't arrived by then.
+    async fn collect_until_idle(
         &mut self,
-        content: impl Into<JupyterMessageContent>,
-    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
-        let request: JupyterMessage = JupyterMessage::new(content, None);
-        let msg_id = request.header.msg_id.clone();
+        msg_id: &str,
+        deadline: Instant,
+    ) -> Result<(Vec<TimedMessage>, bool)> {
+        let mut iopub_messages = Vec::new();
+        let ours = |msg: &JupyterMessage| is_child_of(msg, msg_id);
+        while let Some((at, msg)) = self.next_iopub(deadline, ours).await? {
+            let idle = is_idle(&msg);
+            iopub_messages.push((at, msg));
+            if idle {
+                return Ok((iopub_messages, true));
+            }
+        }
+        Ok((iopub_messages, false))
+    }
 
-        self.shell
-            .send(request)
+    /// Drop IOPub messages no query has claimed, e.g. late output of an
+    /// earlier test. They have still been observed.
+    pub(crate) fn discard_iopub_backlog(&mut self) {
+        self.iopub.discard_backlog(&mut self.observed);
+    }
+
+    /// The error for a test that needs `what`, which this kernel's link
+    /// can't provide; the test is reported as skipped rather than failed.
+    fn unavailable(&self, what: &str) -> HarnessError {
+        let reason = format!("{} is not available {}", what, self.link.describe());
+        let _ = self.unavailable.set(reason.clone());
+        HarnessError::NotAvailable(reason)
+    }
+
+    /// Get ready to run an attempt at `test`: record its channel traffic if
+    /// the run keeps a transcript, or serve its recorded exchange if this
+    /// kernel replays one.
+    fn begin_exchange(&mut self, test: &str) {
+        match &mut self.link {
+            KernelLink::Zmq | KernelLink::Server(_) => {
+                if self.builder.options().transcript_dir.is_some() {
+                    self.recording = Some(Exchange {
+                        test: test.to_string(),
+                        session_id: self.session_id.clone(),
+                        debug_seq: self.debug_seq,
+                        events: Vec::new(),
+                    });
+                    self.recorder.start();
+                }
+            }
+            KernelLink::Replay(replay) => {
+                let exchange = replay.transcript().exchange(test).cloned();
+                if let Some(exchange) = &exchange {
+                    self.session_id = exchange.session_id.clone();
+                    self.debug_seq = exchange.debug_seq;
+                }
+                let relayed = replay.serve(exchange.as_ref(), &self.session_id);
+                let channels = KernelChannels::from(relayed);
+                self.shell = channels.shell;
+                self.control = channels.control;
+                self.stdin = channels.stdin;
+                self.heartbeat = channels.heartbeat;
+                self.iopub = channels.iopub;
+            }
+        }
+    }
+
+    /// Finish the attempt at `test` that ended with `result`, keeping its
+    /// channel traffic for the transcript.
+    ///
+    /// A test that needed something the link couldn't provide, or whose
+    /// replayed exchange stopped matching what it sent, is skipped: its
+    /// verdict says nothing about the kernel.
+    fn end_exchange(&mut self, test: &str, result: TestResult) -> TestResult {
+        if let Some(mut exchange) = self.recording.take() {
+            exchange.events = self.recorder.finish();
+            self.exchanges.retain(|recorded| recorded.test != test);
+            self.exchanges.push(exchange);
+        }
+        let diverged = match &self.link {
+            KernelLink::Replay(replay) => replay.divergence(),
+            KernelLink::Zmq | KernelLink::Server(_) => None,
+        };
+        match self.unavailable.take().or(diverged) {
+            Some(reason) => TestResult::Skipped { reason },
+            None => result,
+        }
+    }
+
+    /// Take the metrics and flag recorded since the last call.
+    fn take_metrics(&mut self) -> (BTreeMap<String, f64>, Option<String>) {
+        (std::mem::take(&mut self.metrics), self.flagged.take())
+    }
+
+    /// Whether a reply without parent_header has been accepted during the
+    /// current test.
+    pub fn missing_parent_header(&self) -> bool {
+        self.missing_parent_header
+    }
+
+    /// Read shell messages until the reply to `matcher`'s request arrives.
+    async fn read_shell_reply(
+        &mut self,
+        matcher: ReplyMatcher,
+        what: &str,
+    ) -> Result<JupyterMessage> {
+        self.read_reply(Channel::Shell, matcher, what, self.test_timeout)
             .await
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+    }
 
-        // Collect IOPub messages until idle
-        let mut iopub_messages = Vec::new();
-        let start = Instant::now();
+    /// Read messages from `channel` (shell or control) until the reply to
+    /// `matcher`'s request arrives, waiting up to `wait`.
+    ///
+    /// Replies to other requests (e.g. left over from a timed-out test) are
+    /// skipped. If the kernel only sends an un-parented reply of the expected
+    /// type, it is accepted after a short grace period and the current test
+    /// is marked as having seen a missing parent_header.
+    async fn read_reply(
+        &mut self,
+        channel: Channel,
+        mut matcher: ReplyMatcher,
+        what: &str,
+        wait: Duration,
+    ) -> Result<JupyterMessage> {
+        let deadline = Instant::now() + wait;
+        let mut grace_deadline: Option<Instant> = None;
 
         loop {
-            if start.elapsed() > self.test_timeout {
-                return Err(HarnessError::Timeout("iopub idle".to_string()));
+            let until = grace_deadline.map_or(deadline, |grace| grace.min(deadline));
+            let wait = until.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                break;
             }
 
-            match timeout(Duration::from_millis(100), self.iopub.read()).await {
+            let replies = match channel {
+                Channel::Control => &mut self.control,
+                _ => &mut self.shell,
+            };
+            match timeout(wait, replies.read()).await {
                 Ok(Ok(msg)) => {
-                    if msg.parent_header.as_ref().map(|h| &h.msg_id) == Some(&msg_id) {
-                        let is_idle = matches!(
-                            &msg.content,
-                            JupyterMessageContent::Status(Status { execution_state })
-                            if *execution_state == ExecutionState::Idle
-                        );
-                        iopub_messages.push(msg);
-                        if is_idle {
-                            break;
-                        }
+                    self.observe(&msg);
+                    if let Some(reply) = matcher.offer(msg) {
+                        return Ok(reply);
+                    }
+                    if matcher.has_fallback() && grace_deadline.is_none() {
+                        grace_deadline = Some(Instant::now() + UNPARENTED_REPLY_GRACE);
                     }
                 }
-                Ok(Err(e)) => {
-                    return Err(HarnessError::ProtocolError(e.to_string()));
-                }
-                Err(_) => {
-                    // Timeout on this read, continue
-                }
+                Ok(Err(e)) => return Err(HarnessError::ProtocolError(e.to_string())),
+                Err(_) => break,
             }
         }
 
-        // Read shell reply
-        let reply = timeout(self.test_timeout, self.shell.read())
-            .await
-            .map_err(|_| HarnessError::Timeout("shell reply".to_string()))?
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
-
-        Ok((reply, iopub_messages))
+        match matcher.take_fallback() {
+            Some(reply) => {
+                self.missing_parent_header = true;
+                Ok(reply)
+            }
+            None => Err(HarnessError::Timeout(what.to_string())),
+        }
     }
 
-    /// Send a request on control and wait for reply.
-    pub async fn control_request(
+    /// Send a request on shell and wait for reply.
+    pub async fn shell_request(
         &mut self,
         content: impl Into<JupyterMessageContent>,
     ) -> Result<JupyterMessage> {
         let request: JupyterMessage = JupyterMessage::new(content, None);
-        self.control
+        let matcher = ReplyMatcher::for_request(&request);
+        self.shell
             .send(request)
             .await
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
 
-        timeout(self.test_timeout, self.control.read())
+        self.read_shell_reply(matcher, "shell reply").await
+    }
+
+    /// Send a hand-built message on shell without waiting for a reply.
+    ///
+    /// For robustness tests that need message types or content the typed
+    /// request helpers can't express.
+    pub async fn send_shell_message(&mut self, msg: JupyterMessage) -> Result<()> {
+        self.shell
+            .send(msg)
             .await
-            .map_err(|_| HarnessError::Timeout("control reply".to_string()))?
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))
     }
 
-    /// Execute code and collect all IOPub messages until idle.
-    pub async fn execute_and_collect(
+    /// Send an execute_request signed with the wrong HMAC key, then watch
+    /// IOPub for `watch`.
+    ///
+    /// The request goes out on a separate shell connection whose key differs
+    /// from the one in the connection file, so a conforming kernel drops it.
+    /// Returns the forged request's msg_id and every IOPub message seen
+    /// while watching.
+    pub async fn execute_with_forged_signature(
         &mut self,
         code: &str,
-    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
-        let request = ExecuteRequest::new(code.to_string());
-        let msg: JupyterMessage = request.into();
-        let msg_id = msg.header.msg_id.clone();
+        watch: Duration,
+    ) -> Result<(String, Vec<JupyterMessage>)> {
+        let mut forged_info = match &self.connection_info {
+            Some(connection_info) => connection_info.clone(),
+            None => return Err(self.unavailable("a second shell connection")),
+        };
+        forged_info.key = uuid::Uuid::new_v4().to_string();
+
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let identity = peer_identity_for_session(&session_id)?;
+        let mut forged_shell =
+            create_client_shell_connection_with_identity(&forged_info, &session_id, identity)
+                .await
+                .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+
+        let msg: JupyterMessage = ExecuteRequest::new(code.to_string()).into();
+        let msg_id = msg.header.msg_id.clone();
+        forged_shell
+            .send(msg)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        // forged_shell stays open while watching so the message isn't
+        // discarded on our side
+        let iopub_messages = self.collect_iopub(Instant::now() + watch).await?;
+
+        Ok((msg_id, iopub_messages))
+    }
+
+    /// Send a request on shell and wait for reply, also collecting IOPub messages.
+    pub async fn shell_request_with_iopub(
+        &mut self,
+        content: impl Into<JupyterMessageContent>,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let request: JupyterMessage = JupyterMessage::new(content, None);
+        let msg_id = request.header.msg_id.clone();
+        let matcher = ReplyMatcher::for_request(&request);
+
+        self.shell
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        // Collect IOPub messages until idle
+        let deadline = Instant::now() + self.test_timeout;
+        let (iopub_messages, idle) = self.collect_until_idle(&msg_id, deadline).await?;
+        if !idle {
+            return Err(HarnessError::Timeout("iopub idle".to_string()));
+        }
+
+        // Read shell reply
+        let reply = self.read_shell_reply(matcher, "shell reply").await?;
+
+        let iopub_messages = iopub_messages.into_iter().map(|(_, msg)| msg).collect();
+        Ok((reply, iopub_messages))
+    }
+
+    /// Send a request on control and wait for reply.
+    pub async fn control_request(
+        &mut self,
+        content: impl Into<JupyterMessageContent>,
+    ) -> Result<JupyterMessage> {
+        self.control_request_within(content, self.test_timeout)
+            .await
+    }
+
+    /// Like [`control_request`](Self::control_request), but waiting only
+    /// `wait` for the reply, for optional features a kernel may ignore.
+    pub async fn control_request_within(
+        &mut self,
+        content: impl Into<JupyterMessageContent>,
+        wait: Duration,
+    ) -> Result<JupyterMessage> {
+        let request: JupyterMessage = JupyterMessage::new(content, None);
+        if matches!(request.content, JupyterMessageContent::ShutdownRequest(_)) {
+            self.shutdown_requested = true;
+        }
+        let matcher = ReplyMatcher::for_request(&request);
+        self.control
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        self.read_reply(Channel::Control, matcher, "control reply", wait)
+            .await
+    }
+
+    /// Send a request on control, drain IOPub for `drain`, then read the reply.
+    ///
+    /// Returns the request msg_id and every IOPub message seen while draining
+    /// (not only those parented to the request), since control-channel status
+    /// broadcasts are optional and tests need to tell "none" from "wrong".
+    pub async fn control_request_with_iopub(
+        &mut self,
+        content: impl Into<JupyterMessageContent>,
+        drain: Duration,
+    ) -> Result<(String, JupyterMessage, Vec<JupyterMessage>)> {
+        let request: JupyterMessage = JupyterMessage::new(content, None);
+        let msg_id = request.header.msg_id.clone();
+        let matcher = ReplyMatcher::for_request(&request);
+
+        self.control
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        let iopub_messages = self.collect_iopub(Instant::now() + drain).await?;
+
+        let reply = self
+            .read_reply(
+                Channel::Control,
+                matcher,
+                "control reply",
+                self.test_timeout,
+            )
+            .await?;
+
+        Ok((msg_id, reply, iopub_messages))
+    }
+
+    /// Send a Debug Adapter Protocol request as a debug_request on control
+    /// and wait up to `wait` for the reply.
+    ///
+    /// DAP sequence numbers are allocated per kernel, starting at 1.
+    pub async fn debug_request(
+        &mut self,
+        command: &str,
+        arguments: serde_json::Value,
+        wait: Duration,
+    ) -> Result<JupyterMessage> {
+        self.debug_seq += 1;
+        let request = DebugRequest {
+            content: serde_json::json!({
+                "type": "request",
+                "seq": self.debug_seq,
+                "command": command,
+                "arguments": arguments,
+            }),
+        };
+        self.control_request_within(request, wait).await
+    }
+
+    /// Execute code and collect all IOPub messages until idle.
+    pub async fn execute_and_collect(
+        &mut self,
+        code: &str,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        self.execute_and_collect_with_metadata(code, serde_json::json!({}))
+            .await
+    }
+
+    /// Execute the `complete_code` snippet `runs` times outside of any test,
+    /// so JIT compilation and lazy imports are done before anything is timed.
+    ///
+    /// Returns how long each run took, stopping at the first that fails.
+    pub async fn warm_up(&mut self, runs: u32) -> Vec<Duration> {
+        let code = self.snippets.complete_code.to_string();
+        let mut durations = Vec::new();
+        for _ in 0..runs {
+            let started = Instant::now();
+            if let Err(e) = self.execute_and_collect(&code).await {
+                eprintln!("Warning: warm-up execution failed: {}", e);
+                break;
+            }
+            durations.push(started.elapsed());
+        }
+        // Not a finding of whichever test happens to run first
+        self.missing_parent_header = false;
+        durations
+    }
+
+    /// Like [`execute_and_collect`](Self::execute_and_collect), but with custom
+    /// request metadata.
+    pub async fn execute_and_collect_with_metadata(
+        &mut self,
+        code: &str,
+        metadata: serde_json::Value,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let request = ExecuteRequest::new(code.to_string());
+        let msg = JupyterMessage::from(request).with_metadata(metadata);
+        self.send_execute_and_collect(msg).await
+    }
+
+    /// Like [`execute_and_collect`](Self::execute_and_collect), but with
+    /// hand-built execute_request content.
+    ///
+    /// The content is sent as-is, so it can carry fields the typed
+    /// `ExecuteRequest` doesn't know about.
+    pub async fn execute_and_collect_raw(
+        &mut self,
+        content: serde_json::Value,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let content = UnknownMessage {
+            msg_type: "execute_request".to_string(),
+            content,
+        };
+        self.send_execute_and_collect(JupyterMessage::new(content, None))
+            .await
+    }
+
+    /// Send a prepared execute_request and collect IOPub messages until idle.
+    async fn send_execute_and_collect(
+        &mut self,
+        msg: JupyterMessage,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let pending = self.send_execute(msg).await?;
+        self.finish_execute(pending).await
+    }
+
+    /// Send an execute_request without waiting for it to finish.
+    ///
+    /// Other channels can be exercised while the kernel is busy; pass the
+    /// returned handle to [`finish_execute`](Self::finish_execute) to collect
+    /// the results.
+    pub async fn start_execute(&mut self, code: &str) -> Result<PendingExecute> {
+        self.send_execute(ExecuteRequest::new(code.to_string()).into())
+            .await
+    }
+
+    async fn send_execute(&mut self, msg: JupyterMessage) -> Result<PendingExecute> {
+        let msg_id = msg.header.msg_id.clone();
+        let matcher = ReplyMatcher::for_request(&msg);
 
         self.shell
             .send(msg)
             .await
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
 
-        // Collect IOPub messages until we see idle status
-        let mut iopub_messages = Vec::new();
-        let start = Instant::now();
+        Ok(PendingExecute {
+            msg_id,
+            matcher,
+            sent_at: Instant::now(),
+        })
+    }
 
-        loop {
-            if start.elapsed() > self.test_timeout {
-                return Err(HarnessError::Timeout("iopub idle".to_string()));
+    /// Collect IOPub messages for a started execution until idle, then read
+    /// its execute_reply.
+    pub async fn finish_execute(
+        &mut self,
+        pending: PendingExecute,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let (reply, timed) = self.finish_execute_timed(pending).await?;
+        Ok((reply, timed.into_iter().map(|(_, msg)| msg).collect()))
+    }
+
+    /// Like [`execute_and_collect`](Self::execute_and_collect), but each
+    /// IOPub message is paired with how long after the request was sent the
+    /// harness received it.
+    pub async fn execute_and_collect_timed(
+        &mut self,
+        code: &str,
+    ) -> Result<(JupyterMessage, Vec<(Duration, JupyterMessage)>)> {
+        let pending = self.start_execute(code).await?;
+        self.finish_execute_timed(pending).await
+    }
+
+    /// [`finish_execute`](Self::finish_execute), with each IOPub message's
+    /// arrival time relative to when the request was sent.
+    pub async fn finish_execute_timed(
+        &mut self,
+        pending: PendingExecute,
+    ) -> Result<(JupyterMessage, Vec<(Duration, JupyterMessage)>)> {
+        let PendingExecute {
+            msg_id,
+            matcher,
+            sent_at,
+        } = pending;
+
+        // Collect IOPub messages for our request until we see idle status
+        let deadline = Instant::now() + self.test_timeout;
+        let (iopub_messages, idle) = self.collect_until_idle(&msg_id, deadline).await?;
+        if !idle {
+            return Err(HarnessError::Timeout("iopub idle".to_string()));
+        }
+
+        // Read the execute_reply
+        let reply = self.read_shell_reply(matcher, "execute_reply").await?;
+
+        let iopub_messages = iopub_messages
+            .into_iter()
+            .map(|(at, msg)| (at.saturating_duration_since(sent_at), msg))
+            .collect();
+        Ok((reply, iopub_messages))
+    }
+
+    /// Execute code and collect IOPub messages for it until idle or until
+    /// `window` has passed, whichever comes first, then read the reply.
+    ///
+    /// Unlike [`execute_and_collect`](Self::execute_and_collect), a missing
+    /// idle status is not an error, so tests can assert on what arrived.
+    pub async fn execute_and_collect_within(
+        &mut self,
+        code: &str,
+        window: Duration,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let PendingExecute {
+            msg_id,
+            matcher,
+            sent_at,
+        } = self.start_execute(code).await?;
+
+        let (iopub_messages, _idle) = self.collect_until_idle(&msg_id, sent_at + window).await?;
+
+        let reply = self.read_shell_reply(matcher, "execute_reply").await?;
+        let iopub_messages = iopub_messages.into_iter().map(|(_, msg)| msg).collect();
+        Ok((reply, iopub_messages))
+    }
+
+    /// Send several execute_requests back to back, then collect the results.
+    ///
+    /// Nothing is read between sends, so the kernel has all of them queued at
+    /// once. Returns the request msg_ids in submission order, the execute
+    /// replies in arrival order, and every IOPub message parented to any of
+    /// the requests. Replies are correlated by parent_header; an un-parented
+    /// execute_reply is still returned (and flagged) so the caller can report
+    /// it rather than time out.
+    pub async fn execute_pipelined(
+        &mut self,
+        codes: &[String],
+    ) -> Result<(Vec<String>, Vec<JupyterMessage>, Vec<JupyterMessage>)> {
+        let mut msg_ids = Vec::with_capacity(codes.len());
+        for code in codes {
+            let msg: JupyterMessage = ExecuteRequest::new(code.clone()).into();
+            msg_ids.push(msg.header.msg_id.clone());
+            self.shell
+                .send(msg)
+                .await
+                .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+        }
+
+        // Collect IOPub messages until every request has gone idle
+        let mut iopub_messages = Vec::new();
+        let mut idle = 0;
+        let deadline = Instant::now() + self.test_timeout;
+
+        while idle < msg_ids.len() {
+            let ours = |msg: &JupyterMessage| msg_ids.iter().any(|id| is_child_of(msg, id));
+            let Some((_, msg)) = self.next_iopub(deadline, ours).await? else {
+                return Err(HarnessError::Timeout(format!(
+                    "iopub idle ({} of {} requests)",
+                    idle,
+                    msg_ids.len()
+                )));
+            };
+            if is_idle(&msg) {
+                idle += 1;
             }
+            iopub_messages.push(msg);
+        }
 
-            match timeout(Duration::from_millis(100), self.iopub.read()).await {
-                Ok(Ok(msg)) => {
-                    // Only collect messages for our request
-                    if msg.parent_header.as_ref().map(|h| &h.msg_id) == Some(&msg_id) {
-                        let is_idle = matches!(
-                            &msg.content,
-                            JupyterMessageContent::Status(Status { execution_state })
-                            if *execution_state == ExecutionState::Idle
-                        );
-                        iopub_messages.push(msg);
-                        if is_idle {
-                            break;
-                        }
-                    }
-                }
-                Ok(Err(e)) => {
-                    return Err(HarnessError::ProtocolError(e.to_string()));
-                }
+        // Read one execute_reply per request, skipping replies to anything else
+        let mut replies = Vec::with_capacity(msg_ids.len());
+        let deadline = Instant::now() + self.test_timeout;
+
+        while replies.len() < msg_ids.len() {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let msg = match timeout(wait, self.shell.read()).await {
+                Ok(Ok(msg)) => msg,
+                Ok(Err(e)) => return Err(HarnessError::ProtocolError(e.to_string())),
                 Err(_) => {
-                    // Timeout on this read, continue loop
+                    return Err(HarnessError::Timeout(format!(
+                        "execute_reply ({} of {} received)",
+                        replies.len(),
+                        msg_ids.len()
+                    )))
                 }
+            };
+            self.observe(&msg);
+            match &msg.parent_header {
+                Some(parent) if msg_ids.contains(&parent.msg_id) => replies.push(msg),
+                Some(_) => {}
+                None if msg.content.message_type() == "execute_reply" => {
+                    self.missing_parent_header = true;
+                    replies.push(msg);
+                }
+                None => {}
             }
         }
 
-        // Read the execute_reply
-        let reply = timeout(self.test_timeout, self.shell.read())
-            .await
-            .map_err(|_| HarnessError::Timeout("execute_reply".to_string()))?
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+        Ok((msg_ids, replies, iopub_messages))
+    }
 
-        Ok((reply, iopub_messages))
+    /// Read every IOPub message that arrives within `duration`, along with
+    /// any that arrived earlier and no request has claimed.
+    ///
+    /// Used after [`execute_and_collect`](Self::execute_and_collect) to catch
+    /// output a kernel publishes after its idle status.
+    pub async fn drain_iopub(&mut self, duration: Duration) -> Result<Vec<JupyterMessage>> {
+        self.collect_iopub(Instant::now() + duration).await
+    }
+
+    /// Run the language's `state_fingerprint_code` and return its stdout.
+    ///
+    /// Returns `Ok(None)` when the language has no fingerprint snippet. The
+    /// request doesn't store history, and its messages are kept out of
+    /// [`observed_messages`](Self::observed_messages) so suite finalizers
+    /// only see what the tests produced.
+    pub async fn state_fingerprint(&mut self) -> Result<Option<String>> {
+        let code = self.snippets.state_fingerprint_code.clone();
+        if code.contains("not available") {
+            return Ok(None);
+        }
+
+        let mut request = ExecuteRequest::new(code);
+        request.store_history = false;
+        // Whatever arrived before now belongs to the tests
+        self.iopub.receive_pending(&mut self.observed);
+        let observed = self.observed.len();
+        let result = self.send_execute_and_collect(request.into()).await;
+        self.observed.truncate(observed);
+        let (reply, iopub) = result?;
+
+        if let JupyterMessageContent::ExecuteReply(er) = &reply.content {
+            if er.status != ReplyStatus::Ok {
+                return Err(HarnessError::ProtocolError(format!(
+                    "state fingerprint snippet returned status {:?}",
+                    er.status
+                )));
+            }
+        }
+
+        let stdout = iopub
+            .iter()
+            .filter_map(|msg| match &msg.content {
+                JupyterMessageContent::StreamContent(StreamContent {
+                    name: jupyter_protocol::messaging::Stdio::Stdout,
+                    text,
+                }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        Ok(Some(stdout))
     }
 
     /// Execute code that may request stdin input, providing a mock response.
     ///
-    /// Returns the execute_reply, IOPub messages, and whether an input_request was received.
+    /// Returns the execute_reply, IOPub messages, and the first input_request
+    /// received on stdin (if any), along with the execute_request's msg_id.
     pub async fn execute_with_stdin(
         &mut self,
         code: &str,
         input_response: &str,
-    ) -> Result<(JupyterMessage, Vec<JupyterMessage>, bool)> {
+    ) -> Result<StdinExecution> {
         let mut request = ExecuteRequest::new(code.to_string());
         request.allow_stdin = true;
         let msg: JupyterMessage = request.into();
         let msg_id = msg.header.msg_id.clone();
+        let matcher = ReplyMatcher::for_request(&msg);
 
         self.shell
             .send(msg)
@@ -441,20 +1529,26 @@ impl KernelUnderTest {
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
 
         let mut iopub_messages = Vec::new();
-        let mut received_input_request = false;
-        let start = Instant::now();
+        let mut input_request = None;
+        let mut stdin_readable = true;
+        let deadline = Instant::now() + self.test_timeout;
 
-        // Poll both IOPub and stdin until we see idle
+        // Answer input_requests on stdin until IOPub reports idle
         loop {
-            if start.elapsed() > self.test_timeout {
-                return Err(HarnessError::Timeout("iopub idle (stdin test)".to_string()));
-            }
+            let ours = |msg: &JupyterMessage| is_child_of(msg, &msg_id);
+            let received = tokio::select! {
+                stdin_msg = self.stdin.read(), if stdin_readable => StdinOrIoPub::Stdin(stdin_msg),
+                iopub_msg = self.iopub.next_matching(&mut self.observed, deadline, ours) => {
+                    StdinOrIoPub::IoPub(iopub_msg)
+                }
+            };
 
-            // Check for stdin input_request
-            match timeout(Duration::from_millis(50), self.stdin.read()).await {
-                Ok(Ok(stdin_msg)) => {
-                    if let JupyterMessageContent::InputRequest(_req) = &stdin_msg.content {
-                        received_input_request = true;
+            match received {
+                StdinOrIoPub::Stdin(Ok(stdin_msg)) => {
+                    if let JupyterMessageContent::InputRequest(_) = &stdin_msg.content {
+                        if input_request.is_none() {
+                            input_request = Some(stdin_msg.clone());
+                        }
                         // Send input_reply with our mock response
                         let reply = InputReply {
                             value: input_response.to_string(),
@@ -468,193 +1562,1059 @@ impl KernelUnderTest {
                             .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
                     }
                 }
-                Ok(Err(e)) => {
-                    // Log but don't fail on stdin errors
+                StdinOrIoPub::Stdin(Err(e)) => {
+                    // Log but don't fail on stdin errors; keep waiting for idle
                     eprintln!("stdin read error: {}", e);
+                    stdin_readable = false;
                 }
-                Err(_) => {
-                    // Timeout on stdin read, that's fine
-                }
-            }
-
-            // Check for IOPub messages
-            match timeout(Duration::from_millis(50), self.iopub.read()).await {
-                Ok(Ok(msg)) => {
-                    if msg.parent_header.as_ref().map(|h| &h.msg_id) == Some(&msg_id) {
-                        let is_idle = matches!(
-                            &msg.content,
-                            JupyterMessageContent::Status(Status { execution_state })
-                            if *execution_state == ExecutionState::Idle
-                        );
-                        iopub_messages.push(msg);
-                        if is_idle {
-                            break;
-                        }
+                StdinOrIoPub::IoPub(Ok(Some((_, msg)))) => {
+                    let idle = is_idle(&msg);
+                    iopub_messages.push(msg);
+                    if idle {
+                        break;
                     }
                 }
-                Ok(Err(e)) => {
-                    return Err(HarnessError::ProtocolError(e.to_string()));
-                }
-                Err(_) => {
-                    // Timeout on this read, continue loop
+                StdinOrIoPub::IoPub(Ok(None)) => {
+                    return Err(HarnessError::Timeout("iopub idle (stdin test)".to_string()));
                 }
+                StdinOrIoPub::IoPub(Err(e)) => return Err(e),
             }
         }
 
         // Read the execute_reply
-        let reply = timeout(self.test_timeout, self.shell.read())
-            .await
-            .map_err(|_| HarnessError::Timeout("execute_reply (stdin test)".to_string()))?
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
-
-        Ok((reply, iopub_messages, received_input_request))
+        let reply = self.read_shell_reply(matcher, "execute_reply (stdin test)").await?;
+
+        Ok(StdinExecution {
+            msg_id,
+            reply,
+            iopub: iopub_messages,
+            input_request,
+        })
     }
 
     /// Test heartbeat.
     pub async fn heartbeat(&mut self) -> Result<()> {
-        timeout(self.test_timeout, self.heartbeat.single_heartbeat())
+        if let KernelLink::Server(_) = self.link {
+            return Err(self.unavailable("the heartbeat channel"));
+        }
+        timeout(self.test_timeout, self.heartbeat.beat())
             .await
             .map_err(|_| HarnessError::Timeout("heartbeat".to_string()))?
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))
     }
 
     /// Access stdin channel for input tests.
-    pub fn stdin_mut(&mut self) -> &mut ClientStdinConnection {
-        &mut self.stdin
+    pub fn stdin_mut(&mut self) -> &mut dyn MessageChannel {
+        self.stdin.as_mut()
     }
 
-    /// Send comm_open and check if kernel rejects it (returns true if rejected).
-    pub async fn send_comm_open(&mut self, msg: CommOpen) -> Result<bool> {
-        let comm_id = msg.comm_id.clone();
-        let request: JupyterMessage = JupyterMessage::new(msg, None);
+    /// Open an additional shell connection as a separate frontend.
+    ///
+    /// The new client gets its own session and peer identity, so the kernel
+    /// must route its replies back to it rather than to the primary shell.
+    pub async fn open_shell_client(&self) -> Result<ShellClient> {
+        let Some(connection_info) = &self.connection_info else {
+            return Err(self.unavailable("a second shell connection"));
+        };
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let identity = peer_identity_for_session(&session_id)?;
+        let shell =
+            create_client_shell_connection_with_identity(connection_info, &session_id, identity)
+                .await
+                .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+        Ok(ShellClient {
+            shell: Box::new(shell),
+        })
+    }
 
-        self.shell
-            .send(request)
+    /// Open an additional shell connection whose requests run on the
+    /// subshell `subshell_id` (JEP 91), for use with
+    /// [`request_via`](Self::request_via).
+    ///
+    /// The subshell is named by a `subshell_id` field in each request's
+    /// header, which the connection adds when it signs the message.
+    pub async fn open_subshell_client(&self, subshell_id: &str) -> Result<ShellClient> {
+        let Some(connection_info) = &self.connection_info else {
+            return Err(self.unavailable("a shell connection that sets subshell_id"));
+        };
+        let mut header_fields = serde_json::Map::new();
+        header_fields.insert("subshell_id".to_string(), subshell_id.into());
+        let shell = RawShell::connect(connection_info, &self.session_id, header_fields)
             .await
-            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+            .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+        Ok(ShellClient {
+            shell: Box::new(shell),
+        })
+    }
 
-        // Brief wait for potential comm_close rejection on IOPub
-        let start = Instant::now();
-        while start.elapsed() < Duration::from_millis(500) {
-            match timeout(Duration::from_millis(100), self.iopub.read()).await {
+    /// Open an additional IOPub subscription, as a second frontend would.
+    ///
+    /// Waits for iopub_welcome (or the same timeout used at launch) so the
+    /// subscription is in place before anything is executed.
+    pub async fn open_iopub_subscriber(&self) -> Result<IoPubSubscriber> {
+        let Some(connection_info) = &self.connection_info else {
+            return Err(self.unavailable("a second IOPub subscription"));
+        };
+        let mut iopub = create_client_iopub_connection(connection_info, "", &self.session_id)
+            .await
+            .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+        let _ = wait_for_iopub_welcome(&mut iopub, self.iopub_welcome_timeout).await;
+        Ok(IoPubSubscriber { iopub })
+    }
+
+    /// Read messages parented to `msg_id` from `subscriber` until idle or the
+    /// test timeout, returning whatever arrived.
+    ///
+    /// These duplicate what the primary subscription sees, so they are not
+    /// added to [`observed_messages`](Self::observed_messages).
+    pub async fn collect_via(
+        &self,
+        subscriber: &mut IoPubSubscriber,
+        msg_id: &str,
+    ) -> Result<Vec<JupyterMessage>> {
+        let mut iopub_messages = Vec::new();
+        let deadline = Instant::now() + self.test_timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            match timeout(wait, subscriber.iopub.read()).await {
                 Ok(Ok(msg)) => {
-                    if let JupyterMessageContent::CommClose(close) = &msg.content {
-                        if close.comm_id == comm_id {
-                            return Ok(true); // Rejected
-                        }
+                    if !is_child_of(&msg, msg_id) {
+                        continue;
+                    }
+                    let idle = is_idle(&msg);
+                    iopub_messages.push(msg);
+                    if idle {
+                        break;
                     }
                 }
-                _ => {}
+                Ok(Err(e)) => return Err(HarnessError::ProtocolError(e.to_string())),
+                Err(_) => break,
             }
         }
-
-        Ok(false) // Not rejected (accepted or ignored)
+        Ok(iopub_messages)
     }
 
-    /// Send comm_close to clean up a comm.
-    pub async fn send_comm_close(&mut self, msg: CommClose) -> Result<()> {
-        let request: JupyterMessage = JupyterMessage::new(msg, None);
-        self.shell
+    /// Send a request through `client` and wait for the reply on its socket.
+    ///
+    /// Returns the reply along with any other shell messages that arrived on
+    /// that socket first; for a fresh client those were routed to it by mistake.
+    pub async fn request_via(
+        &mut self,
+        client: &mut ShellClient,
+        content: impl Into<JupyterMessageContent>,
+    ) -> Result<(JupyterMessage, Vec<JupyterMessage>)> {
+        let request: JupyterMessage = JupyterMessage::new(content, None);
+        let msg_id = request.header.msg_id.clone();
+        client
+            .shell
             .send(request)
             .await
             .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
 
-        // Brief wait for processing
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        Ok(())
-    }
-
-    /// Try to read any stderr output from the kernel process (for diagnostics).
-    pub async fn try_read_stderr(&mut self) -> Option<String> {
-        if let Some(stderr) = self.process.stderr.take() {
-            use tokio::io::AsyncReadExt;
-            let mut buf = Vec::new();
-            let mut reader = tokio::io::BufReader::new(stderr);
-            let _ = tokio::time::timeout(
-                Duration::from_millis(100),
-                reader.read_to_end(&mut buf),
-            )
-            .await;
-            if !buf.is_empty() {
-                Some(String::from_utf8_lossy(&buf).to_string())
-            } else {
-                None
+        let mut strays = Vec::new();
+        let deadline = Instant::now() + self.test_timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let msg = timeout(wait, client.shell.read())
+                .await
+                .map_err(|_| HarnessError::Timeout("reply on second shell".to_string()))?
+                .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+            self.observe(&msg);
+            if msg.parent_header.as_ref().map(|h| &h.msg_id) == Some(&msg_id) {
+                return Ok((msg, strays));
             }
-        } else {
-            None
+            strays.push(msg);
         }
     }
 
-    /// Shutdown the kernel cleanly.
-    pub async fn shutdown(mut self) -> Result<()> {
-        let request = ShutdownRequest { restart: false };
-        let _ = self.control_request(request).await;
-
-        // Give kernel time to exit
-        tokio::time::sleep(Duration::from_millis(500)).await;
-
-        // Force kill if still running
-        let _ = self.process.kill().await;
-
-        // Clean up connection file
-        let _ = tokio::fs::remove_file(&self.connection_path).await;
+    /// Open a shell connection and IOPub subscription that hand over messages
+    /// as the JSON the kernel sent, for checks on fields that typed decoding
+    /// would default (e.g. a missing `metadata`) or reject (e.g. a header
+    /// date without a timezone).
+    ///
+    /// Waits for iopub_welcome (or the same timeout used at launch) so the
+    /// subscription is in place before anything is sent.
+    pub async fn open_raw_client(&self) -> Result<RawClient> {
+        let Some(connection_info) = &self.connection_info else {
+            return Err(self.unavailable("messages as the kernel's own JSON"));
+        };
+        let shell = RawShell::connect(connection_info, &self.session_id, serde_json::Map::new())
+            .await
+            .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
+        let mut iopub = RawIoPub::connect(connection_info)
+            .await
+            .map_err(|e| HarnessError::ConnectionFailed(e.to_string()))?;
 
-        Ok(())
+        let deadline = Instant::now() + self.iopub_welcome_timeout;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            match timeout(wait, iopub.read_value()).await {
+                Ok(Ok(msg)) if msg["header"]["msg_type"] == "iopub_welcome" => break,
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => return Err(HarnessError::ProtocolError(e.to_string())),
+                Err(_) => break,
+            }
+        }
+        Ok(RawClient { shell, iopub })
     }
+
+    /// Send a request through `client` and return, as the JSON the kernel
+    /// sent, its reply and the IOPub messages parented to it until idle.
+    ///
+    /// These duplicate what the harness's own connections would see, so
+    /// they are not added to [`observed_messages`](Self::observed_messages).
+    pub async fn raw_request_via(
+        &self,
+        client: &mut RawClient,
+        content: impl Into<JupyterMessageContent>,
+    ) -> Result<(serde_json::Value, Vec<serde_json::Value>)> {
+        let request: JupyterMessage = JupyterMessage::new(content, None);
+        let msg_id = request.header.msg_id.clone();
+        client
+            .shell
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        let deadline = Instant::now() + self.test_timeout;
+        let is_ours = |msg: &serde_json::Value| msg["parent_header"]["msg_id"] == msg_id.as_str();
+        let mut iopub_messages = Vec::new();
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let msg = timeout(wait, client.iopub.read_value())
+                .await
+                .map_err(|_| HarnessError::Timeout("iopub idle".to_string()))?
+                .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+            if !is_ours(&msg) {
+                continue;
+            }
+            let idle = msg["content"]["execution_state"] == "idle";
+            iopub_messages.push(msg);
+            if idle {
+                break;
+            }
+        }
+
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let msg = timeout(wait, client.shell.read_value())
+                .await
+                .map_err(|_| HarnessError::Timeout("shell reply".to_string()))?
+                .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+            if is_ours(&msg) {
+                return Ok((msg, iopub_messages));
+            }
+        }
+    }
+
+    /// Read messages from the primary shell socket until it has been quiet
+    /// for `quiet`.
+    pub async fn drain_shell(&mut self, quiet: Duration) -> Result<Vec<JupyterMessage>> {
+        let mut drained = Vec::new();
+        loop {
+            match timeout(quiet, self.shell.read()).await {
+                Ok(Ok(msg)) => {
+                    self.observe(&msg);
+                    drained.push(msg);
+                }
+                Ok(Err(e)) => return Err(HarnessError::ProtocolError(e.to_string())),
+                Err(_) => return Ok(drained),
+            }
+        }
+    }
+
+    /// Send comm_open and check if kernel rejects it (returns true if rejected).
+    pub async fn send_comm_open(&mut self, msg: CommOpen) -> Result<bool> {
+        let comm_id = msg.comm_id.clone();
+        let request: JupyterMessage = JupyterMessage::new(msg, None);
+
+        self.shell
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        // Brief wait for potential comm_close rejection on IOPub
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let closes_ours = |msg: &JupyterMessage| match &msg.content {
+            JupyterMessageContent::CommClose(close) => close.comm_id == comm_id,
+            _ => false,
+        };
+        match self.next_iopub(deadline, closes_ours).await {
+            Ok(Some(_)) => Ok(true), // Rejected
+            _ => Ok(false),          // Not rejected (accepted or ignored)
+        }
+    }
+
+    /// Send comm_close to clean up a comm.
+    pub async fn send_comm_close(&mut self, msg: CommClose) -> Result<()> {
+        let request: JupyterMessage = JupyterMessage::new(msg, None);
+        self.shell
+            .send(request)
+            .await
+            .map_err(|e| HarnessError::ProtocolError(e.to_string()))?;
+
+        // Brief wait for processing
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(())
+    }
+
+    /// Try to read any stderr output from the kernel process (for diagnostics).
+    ///
+    /// Only the most recent lines are kept, so a long-lived kernel's early
+    /// output may be missing.
+    pub async fn try_read_stderr(&mut self) -> Option<String> {
+        self.kernel_log.settle().await;
+        self.kernel_log.stderr_tail(usize::MAX)
+    }
+
+    /// The last few lines the kernel wrote to stderr, to attach to a failure.
+    pub fn kernel_log_excerpt(&self) -> Option<String> {
+        self.kernel_log.stderr_tail(KERNEL_LOG_EXCERPT_LINES)
+    }
+
+    /// Sequence number of the last DAP request sent by
+    /// [`debug_request`](Self::debug_request).
+    pub fn debug_seq(&self) -> u64 {
+        self.debug_seq
+    }
+
+    /// Whether a shutdown_request has been sent on control.
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+    }
+
+    /// Restart the kernel the way a kernel manager does: a shutdown_request
+    /// with `restart` set on control and, if the process then exits, the
+    /// same command started again on the same connection file.
+    ///
+    /// The harness's connections reconnect on their own, so the kernel is
+    /// back once it answers kernel_info_request on them again. A kernel that
+    /// restarts in place, or an attached one whose own manager restarts it,
+    /// is only waited for.
+    ///
+    /// Returns the shutdown_reply, which is left out of the messages suite
+    /// finalizers see: they check replies to the suite's restart=false
+    /// requests.
+    pub async fn restart(&mut self) -> Result<JupyterMessage> {
+        if let KernelLink::Replay(_) | KernelLink::Server(_) = self.link {
+            return Err(self.unavailable("a restart over control"));
+        }
+        let reply = self
+            .control_request(ShutdownRequest { restart: true })
+            .await?;
+        self.observed
+            .retain(|msg| msg.header.msg_id != reply.header.msg_id);
+
+        let options = self.builder.options().clone();
+        if let Some(process) = self.process.as_mut() {
+            if exit_within(process, self.shutdown_grace).await?.is_some() {
+                self.respawn(&options).await?;
+            }
+        }
+        self.shutdown_requested = false;
+        self.discard_iopub_backlog();
+
+        // Probing resets snippets to the language's defaults; keep ours
+        let snippets = self.snippets.clone();
+        self.fetch_kernel_info(Instant::now(), options.startup_timeout)
+            .await?;
+        self.snippets = snippets;
+
+        // A kernel that sent iopub_welcome at launch sends it again once
+        // the IOPub subscription has reconnected
+        if self.iopub_welcome_received {
+            let deadline = Instant::now() + self.iopub_welcome_timeout;
+            let is_welcome = |msg: &JupyterMessage| msg.content.message_type() == "iopub_welcome";
+            let _ = self.next_iopub(deadline, is_welcome).await;
+        }
+        Ok(reply)
+    }
+
+    /// Start the kernel process again on its existing connection file.
+    async fn respawn(&mut self, options: &KernelLaunchOptions) -> Result<()> {
+        let (Some(kernelspec), Some(connection_path)) =
+            (self.builder.kernelspec(), &self.connection_path)
+        else {
+            return Err(HarnessError::NotOwned("restart"));
+        };
+        let mut kernelspec = kernelspec.clone();
+        kernelspec.kernelspec.argv = self.launch_info.argv.clone();
+        let scratch_dir = self.scratch_dir.as_ref().map(ScratchDir::path);
+        let (process, kernel_log) =
+            Self::spawn_process(kernelspec, connection_path, options, scratch_dir).await?;
+        self.process = Some(process);
+        self.kernel_log = kernel_log;
+        Ok(())
+    }
+
+    /// Wait up to the shutdown grace period for the kernel process to exit,
+    /// killing it if it doesn't.
+    pub async fn wait_for_exit(&mut self) -> Result<KernelExit> {
+        if let KernelLink::Replay(_) | KernelLink::Server(_) = self.link {
+            return Err(self.unavailable("the kernel process"));
+        }
+        let process = self
+            .process
+            .as_mut()
+            .ok_or(HarnessError::NotOwned("wait for"))?;
+        if let Some(status) = exit_within(process, self.shutdown_grace).await? {
+            return Ok(KernelExit::Exited(status));
+        }
+
+        process.kill().await?;
+        self.force_killed = true;
+        Ok(KernelExit::Killed(process.try_wait()?))
+    }
+
+    /// Shutdown the kernel cleanly, returning how the process ended.
+    ///
+    /// Skips the request if the process has already exited; anything still
+    /// running after the shutdown grace period is force-killed. An attached kernel is left as it
+    /// is: we only disconnect, and there is no exit to report. A kernel
+    /// started through a Jupyter Server is deleted through its REST API,
+    /// which reports no exit either.
+    pub async fn shutdown(mut self) -> Result<Option<KernelExitStatus>> {
+        if let KernelLink::Server(server_kernel) = &mut self.link {
+            server_kernel.delete().await?;
+            return Ok(None);
+        }
+        let Some(mut process) = self.process.take() else {
+            return Ok(None);
+        };
+
+        if !matches!(process.try_wait(), Ok(Some(_))) {
+            let request = ShutdownRequest { restart: false };
+            let _ = self.control_request(request).await;
+
+            // Force kill if still running after the grace period
+            let exited = exit_within(&mut process, self.shutdown_grace).await;
+            if !matches!(exited, Ok(Some(_))) && process.kill().await.is_ok() {
+                self.force_killed = true;
+            }
+        }
+        let exit = match process.try_wait() {
+            Ok(Some(_)) if self.force_killed => KernelExitStatus::ForceKilled,
+            Ok(Some(status)) => exit_status_of(status),
+            Ok(None) => KernelExitStatus::StillRunning,
+            Err(_) => KernelExitStatus::Unknown,
+        };
+
+        if let Some(connection_path) = self.connection_path.take() {
+            remove_launch_files(&connection_path, self.connection_info.as_ref());
+        }
+
+        Ok(Some(exit))
+    }
+
+    /// Kill the kernel process and remove its launch files without asking it
+    /// to shut down.
+    ///
+    /// Unlike [`shutdown`](Self::shutdown) this leaves `self` in place and can
+    /// be called more than once, so it works on a run that was cancelled
+    /// mid-test. An attached kernel is left running; one started through a
+    /// Jupyter Server is deleted.
+    pub async fn abort(&mut self) {
+        if let KernelLink::Server(server_kernel) = &mut self.link {
+            let _ = server_kernel.delete().await;
+        }
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill().await;
+        }
+        self.scratch_dir = None;
+        if let Some(connection_path) = self.connection_path.take() {
+            remove_launch_files(&connection_path, self.connection_info.as_ref());
+        }
+    }
+}
+
+impl Drop for KernelUnderTest {
+    /// Last-resort cleanup for a kernel that was never shut down, e.g. because
+    /// a test panicked or the suite returned early. Drop can't await, so the
+    /// kill is only started and the files are removed synchronously.
+    fn drop(&mut self) {
+        if let Some(process) = self.process.as_mut() {
+            let _ = process.start_kill();
+        }
+        if let Some(connection_path) = self.connection_path.take() {
+            remove_launch_files(&connection_path, self.connection_info.as_ref());
+        }
+    }
+}
+
+/// Remove the connection file we wrote and any IPC socket files the kernel
+/// left behind.
+fn remove_launch_files(connection_path: &Path, connection_info: Option<&ConnectionInfo>) {
+    let _ = std::fs::remove_file(connection_path);
+    for socket in connection_info.into_iter().flat_map(ipc_socket_paths) {
+        let _ = std::fs::remove_file(socket);
+    }
+}
+
+/// The error for a kernel that exited before replying to kernel_info,
+/// logged along with whatever it wrote to stderr.
+fn exited_during_startup(exit_status: ExitStatus, stderr: Option<String>) -> HarnessError {
+    let msg = match stderr {
+        Some(stderr) => format!(
+            "Kernel process exited with {} before replying to kernel_info. Stderr:\n{}",
+            exit_status, stderr
+        ),
+        None => format!(
+            "Kernel process exited with {} before replying to kernel_info",
+            exit_status
+        ),
+    };
+    eprintln!("{}", msg);
+    HarnessError::ExitedDuringStartup(msg)
 }
 
+/// Wait up to `grace` for `process` to exit; None if it is still running.
+async fn exit_within(process: &mut Child, grace: Duration) -> std::io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + grace;
+    loop {
+        if let Some(status) = process.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Report form of an exit the harness didn't cause.
+fn exit_status_of(status: ExitStatus) -> KernelExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return KernelExitStatus::Signaled(signal);
+        }
+    }
+    match status.code() {
+        Some(code) => KernelExitStatus::CleanExit(code),
+        None => KernelExitStatus::Unknown,
+    }
+}
+
+/// What carries a [`KernelUnderTest`]'s channels.
+enum KernelLink {
+    /// The kernel's own ZeroMQ sockets
+    Zmq,
+    /// A transcript's recorded exchanges, served one test at a time
+    Replay(ReplaySession),
+    /// A Jupyter Server's channels websocket to a kernel it started
+    Server(ServerKernel),
+}
+
+impl KernelLink {
+    const SERVER: &'static str = "through a Jupyter Server";
+
+    /// Completes "... is not available", for what this link can't do.
+    fn describe(&self) -> &'static str {
+        match self {
+            KernelLink::Zmq => "over ZeroMQ",
+            KernelLink::Replay(_) => "when replaying a transcript",
+            KernelLink::Server(_) => Self::SERVER,
+        }
+    }
+}
+
+/// The channels of a [`KernelUnderTest`].
+struct KernelChannels {
+    shell: Box<dyn MessageChannel>,
+    control: Box<dyn MessageChannel>,
+    stdin: Box<dyn MessageChannel>,
+    heartbeat: Box<dyn HeartbeatChannel>,
+    iopub: IoPubCollector,
+}
+
+impl From<RelayedChannels> for KernelChannels {
+    fn from(relayed: RelayedChannels) -> Self {
+        Self {
+            shell: Box::new(relayed.shell),
+            control: Box::new(relayed.control),
+            stdin: Box::new(relayed.stdin),
+            heartbeat: Box::new(relayed.heartbeat),
+            iopub: relayed.iopub,
+        }
+    }
+}
+
+/// How the kernel process ended, from [`KernelUnderTest::wait_for_exit`].
+#[derive(Debug, Clone, Copy)]
+pub enum KernelExit {
+    /// The process exited on its own within the grace period
+    Exited(ExitStatus),
+    /// The process had to be killed; the status if it could be collected
+    Killed(Option<ExitStatus>),
+}
+
+/// An execute_request that has been sent but not yet collected, from
+/// [`KernelUnderTest::start_execute`].
+#[derive(Debug)]
+pub struct PendingExecute {
+    msg_id: String,
+    matcher: ReplyMatcher,
+    /// When the request went out, for timing its IOPub messages
+    sent_at: Instant,
+}
+
+impl PendingExecute {
+    /// msg_id of the execute_request
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+}
+
+/// Result of [`KernelUnderTest::execute_with_stdin`].
+#[derive(Debug)]
+pub struct StdinExecution {
+    /// msg_id of the execute_request
+    pub msg_id: String,
+    /// The execute_reply
+    pub reply: JupyterMessage,
+    /// IOPub messages parented to the request
+    pub iopub: Vec<JupyterMessage>,
+    /// The first input_request received on stdin, as sent by the kernel
+    pub input_request: Option<JupyterMessage>,
+}
+
+impl StdinExecution {
+    /// Content of the input_request, if one was received.
+    pub fn input_request_content(&self) -> Option<&InputRequest> {
+        match self.input_request.as_ref().map(|msg| &msg.content) {
+            Some(JupyterMessageContent::InputRequest(req)) => Some(req),
+            _ => None,
+        }
+    }
+}
+
+/// One message from either of the sockets [`KernelUnderTest::execute_with_stdin`]
+/// waits on.
+enum StdinOrIoPub {
+    Stdin(std::result::Result<JupyterMessage, anyhow::Error>),
+    IoPub(Result<Option<TimedMessage>>),
+}
+
+/// Whether `msg` was published in response to the request `msg_id`.
+fn is_child_of(msg: &JupyterMessage, msg_id: &str) -> bool {
+    msg.parent_header
+        .as_ref()
+        .is_some_and(|h| h.msg_id == msg_id)
+}
+
+/// Whether `msg` is an idle status, which ends a request's IOPub output.
+fn is_idle(msg: &JupyterMessage) -> bool {
+    matches!(
+        &msg.content,
+        JupyterMessageContent::Status(Status { execution_state })
+        if *execution_state == ExecutionState::Idle
+    )
+}
+
+/// Whether a failed launch looks like the kernel couldn't have the ports it
+/// was given, so relaunching on others may succeed.
+fn is_port_conflict(error: &HarnessError) -> bool {
+    match error {
+        HarnessError::ExitedDuringStartup(_) => true,
+        HarnessError::ConnectionFailed(message) => is_connection_refused(message),
+        _ => false,
+    }
+}
+
+/// Connection errors only reach the harness as text.
+fn is_connection_refused(message: &str) -> bool {
+    message.to_lowercase().contains("connection refused")
+}
+
+/// An additional shell connection, opened with
+/// [`KernelUnderTest::open_shell_client`] or
+/// [`KernelUnderTest::open_subshell_client`].
+pub struct ShellClient {
+    shell: Box<dyn MessageChannel>,
+}
+
+/// A shell connection and IOPub subscription that return messages as the
+/// JSON the kernel sent, opened with [`KernelUnderTest::open_raw_client`].
+pub struct RawClient {
+    shell: RawShell,
+    iopub: RawIoPub,
+}
+
+/// An additional IOPub subscription, opened with
+/// [`KernelUnderTest::open_iopub_subscriber`].
+pub struct IoPubSubscriber {
+    iopub: ClientIoPubConnection,
+}
+
+/// Picks the reply to one shell or control request out of the messages read
+/// from its channel.
+#[derive(Debug)]
+struct ReplyMatcher {
+    /// msg_id of the request
+    msg_id: String,
+    /// Expected reply message type (e.g. "execute_reply")
+    reply_type: String,
+    /// First un-parented reply of the expected type, used if nothing matches
+    fallback: Option<JupyterMessage>,
+}
+
+impl ReplyMatcher {
+    fn for_request(request: &JupyterMessage) -> Self {
+        let request_type = request.content.message_type();
+        let reply_type = match request_type.strip_suffix("_request") {
+            Some(base) => format!("{}_reply", base),
+            None => request_type.to_string(),
+        };
+        Self {
+            msg_id: request.header.msg_id.clone(),
+            reply_type,
+            fallback: None,
+        }
+    }
+
+    /// Returns the message if it is the reply to our request.
+    fn offer(&mut self, msg: JupyterMessage) -> Option<JupyterMessage> {
+        match &msg.parent_header {
+            Some(parent) if parent.msg_id == self.msg_id => Some(msg),
+            // A reply to some earlier request
+            Some(_) => None,
+            None => {
+                if self.fallback.is_none() && msg.content.message_type() == self.reply_type {
+                    self.fallback = Some(msg);
+                }
+                None
+            }
+        }
+    }
+
+    fn has_fallback(&self) -> bool {
+        self.fallback.is_some()
+    }
+
+    fn take_fallback(&mut self) -> Option<JupyterMessage> {
+        self.fallback.take()
+    }
+}
+
+/// Future a conformance test resolves to its result with.
+pub type TestFuture<'a> = Pin<Box<dyn Future<Output = TestResult> + Send + 'a>>;
+
+/// Body of a conformance test: a plain `fn` or a closure.
+pub type TestFn = Arc<dyn for<'a> Fn(&'a mut KernelUnderTest) -> TestFuture<'a> + Send + Sync>;
+
 /// Definition of a single conformance test.
+#[derive(Clone)]
 pub struct ConformanceTest {
+    pub name: String,
+    pub category: TestCategory,
+    /// Human-readable description of what this test validates
+    pub description: String,
+    /// The primary protocol message type being tested (e.g., "kernel_info_request")
+    pub message_type: String,
+    /// Snippet fields the test executes (e.g., "print_hello"), for generated docs
+    pub snippets: &'static [&'static str],
+    pub run: TestFn,
+}
+
+impl ConformanceTest {
+    /// A test that uses no language snippets, e.g. a kernel-specific check
+    /// from a downstream crate.
+    pub fn new<F>(
+        name: impl Into<String>,
+        category: TestCategory,
+        description: impl Into<String>,
+        message_type: impl Into<String>,
+        run: F,
+    ) -> Self
+    where
+        F: for<'a> Fn(&'a mut KernelUnderTest) -> TestFuture<'a> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            category,
+            description: description.into(),
+            message_type: message_type.into(),
+            snippets: &[],
+            run: Arc::new(run),
+        }
+    }
+
+    /// Whether the test ends the kernel, so has to run after every other.
+    fn is_shutdown(&self) -> bool {
+        self.message_type == "shutdown_request"
+    }
+}
+
+/// The conformance tests a suite runs: usually the built-in ones from
+/// [`all_tests`](crate::all_tests), plus any a downstream crate registers.
+///
+/// ```no_run
+/// # async fn example(kernelspec: runtimelib::KernelspecDir) {
+/// use jupyter_kernel_test::*;
+///
+/// let mut registry = TestRegistry::new();
+/// registry.extend(all_tests());
+/// registry.register(ConformanceTest::new(
+///     "sql_cell_magic",
+///     TestCategory::Tier4Advanced,
+///     "%%sql cells run against the bundled database",
+///     "execute_request",
+///     |kernel| {
+///         Box::pin(async move {
+///             match kernel.execute_and_collect("%%sql\nSELECT 1").await {
+///                 Ok(_) => TestResult::Pass,
+///                 Err(e) => TestResult::Fail {
+///                     kind: None,
+///                     reason: e.to_string(),
+///                 },
+///             }
+///         })
+///     },
+/// ));
+///
+/// let report = run_conformance_suite(
+///     KernelUnderTest::builder(kernelspec),
+///     &[TestCategory::Tier4Advanced],
+///     &registry,
+///     &all_finalizers(),
+///     &[],
+///     &new_run_id(),
+///     IsolationOptions::default(),
+///     &SuiteInterrupt::new(),
+/// )
+/// .await;
+/// // sql_cell_magic is reported under Tier 4 like any built-in test
+/// println!("{}", render_markdown(&report));
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct TestRegistry {
+    tests: Vec<ConformanceTest>,
+}
+
+impl TestRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a test, replacing any registered test with the same name.
+    ///
+    /// Tests run in registration order, except that shutdown tests always
+    /// run after the rest.
+    pub fn register(&mut self, test: ConformanceTest) -> &mut Self {
+        match self.tests.iter_mut().find(|t| t.name == test.name) {
+            Some(existing) => *existing = test,
+            None => self.tests.push(test),
+        }
+        self
+    }
+
+    /// [`register`](Self::register) each of `tests`.
+    pub fn extend(&mut self, tests: impl IntoIterator<Item = ConformanceTest>) -> &mut Self {
+        for test in tests {
+            self.register(test);
+        }
+        self
+    }
+
+    pub fn tests(&self) -> &[ConformanceTest] {
+        &self.tests
+    }
+}
+
+/// A suite-level check computed from messages observed during the whole run.
+///
+/// Finalizers don't interact with the kernel; they run after every other test
+/// and receive all IOPub messages the harness read.
+pub struct SuiteFinalizer {
+    /// Unique test name
     pub name: &'static str,
+    /// Test category/tier
     pub category: TestCategory,
     /// Human-readable description of what this test validates
     pub description: &'static str,
-    /// The primary protocol message type being tested (e.g., "kernel_info_request")
+    /// The primary protocol message type being checked
     pub message_type: &'static str,
-    pub run: fn(&mut KernelUnderTest) -> std::pin::Pin<Box<dyn std::future::Future<Output = TestResult> + Send + '_>>,
+    pub run: fn(&[JupyterMessage]) -> TestResult,
+}
+
+/// How kernel state is kept apart between tests in one suite run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IsolationOptions {
+    /// When to relaunch the kernel
+    pub mode: IsolationMode,
+    /// Fingerprint kernel state between tests and report which tests leak
+    /// state (diagnostic only)
+    pub check_state: bool,
+}
+
+/// Tells running suites to stop early, e.g. on Ctrl-C.
+///
+/// Clones share one flag. Once it is triggered, each suite kills its kernel,
+/// removes the connection file and returns a partial report.
+#[derive(Debug, Clone)]
+pub struct SuiteInterrupt {
+    triggered: Arc<watch::Sender<bool>>,
+}
+
+impl Default for SuiteInterrupt {
+    fn default() -> Self {
+        Self {
+            triggered: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl SuiteInterrupt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every suite sharing this interrupt to stop.
+    pub fn trigger(&self) {
+        self.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Resolve once [`trigger`](Self::trigger) has been called.
+    async fn wait(&self) {
+        let mut triggered = self.triggered.subscribe();
+        let _ = triggered.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// The kernel a conformance suite runs against: one for the suite to launch
+/// (or attach to), or one the caller already has.
+///
+/// Only ever passed once per suite, so neither variant is boxed.
+#[allow(clippy::large_enum_variant)]
+pub enum SuiteKernel {
+    Builder(KernelUnderTestBuilder),
+    Launched(KernelUnderTest),
+}
+
+impl From<KernelUnderTestBuilder> for SuiteKernel {
+    fn from(builder: KernelUnderTestBuilder) -> Self {
+        SuiteKernel::Builder(builder)
+    }
+}
+
+impl From<KernelUnderTest> for SuiteKernel {
+    fn from(kernel: KernelUnderTest) -> Self {
+        SuiteKernel::Launched(kernel)
+    }
 }
 
 /// Run the full conformance suite against a kernel.
 ///
 /// Returns a report even if the kernel fails during startup - in that case,
 /// the report will have `startup_error` set and a single failed test record.
+///
+/// `kernel` is either a builder, so launch options (timeouts, environment,
+/// benchmark thresholds, ...) are set there, or a kernel the caller has
+/// already launched, e.g. to run their own checks on first. Either way the
+/// suite shuts the kernel down when it's done, and its finalizers see every
+/// message the kernel has exchanged with the harness.
+///
+/// `extra_tests` are user-supplied specs; they run after the built-in tests
+/// but before the shutdown test so they still see a live kernel.
+/// `finalizers` run last, over every message observed during the run.
+///
+/// If `interrupt` is triggered, the test in flight is abandoned, the kernel
+/// is killed rather than shut down, and every test not yet run is recorded
+/// as skipped. Once the builder's suite timeout has passed, no further test
+/// is started: the rest are recorded as skipped and the kernel is shut down
+/// as usual.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_conformance_suite(
-    kernelspec: KernelspecDir,
+    kernel: impl Into<SuiteKernel>,
     tiers: &[TestCategory],
-    test_timeout: Duration,
-    tests: &[ConformanceTest],
+    tests: &TestRegistry,
+    finalizers: &[SuiteFinalizer],
+    extra_tests: &[ExtraTest],
+    run_id: &str,
+    isolation_options: IsolationOptions,
+    interrupt: &SuiteInterrupt,
 ) -> KernelReport {
     let start = Instant::now();
-    let kernel_name = kernelspec.kernel_name.clone();
-    let language = kernelspec.kernelspec.language.clone();
+    let started_at = Utc::now();
+    let (builder, launched) = match kernel.into() {
+        SuiteKernel::Builder(builder) => (builder, None),
+        SuiteKernel::Launched(kernel) => (kernel.builder.clone(), Some(kernel)),
+    };
+    let kernel_name = builder.kernel_name();
+    let language = builder.kernelspec().map_or_else(
+        || "unknown".to_string(),
+        |kernelspec| kernelspec.kernelspec.language.clone(),
+    );
+    let attached = builder.is_attach();
+    let transcript_dir = builder.options().transcript_dir.clone();
+    let measure_resources = builder.options().measure_resources;
+    let deadline = builder
+        .options()
+        .suite_timeout
+        .map(|timeout| start + timeout);
+    let warmup = builder.options().warmup;
+    // Env overrides only apply to a process we spawn
+    let env = if attached {
+        BTreeMap::new()
+    } else {
+        builder.options().env.clone()
+    };
+    // Until a launch succeeds, the configured directory is all there is to
+    // report (a scratch directory doesn't exist yet)
+    let cwd = builder.options().cwd.clone().filter(|_| !attached);
 
     // Try to launch the kernel
-    let mut kernel = match KernelUnderTest::launch(kernelspec, test_timeout).await {
+    let launch = match launched {
+        Some(kernel) => Ok(kernel),
+        None => builder.launch().await,
+    };
+    let mut kernel = match launch {
         Ok(k) => k,
         Err(e) => {
             // Kernel failed during startup - return a partial report
             let error_msg = e.to_string();
             eprintln!("Kernel startup failed: {}", error_msg);
-            return KernelReport::new_failed_at_startup(
-                kernel_name,
-                language,
-                error_msg,
-                start.elapsed(),
-            );
+            return KernelReport {
+                env,
+                cwd,
+                attached,
+                isolation_mode: isolation_options.mode,
+                ..KernelReport::new_failed_at_startup(
+                    run_id.to_string(),
+                    kernel_name,
+                    language,
+                    error_msg,
+                    start.elapsed(),
+                )
+            };
         }
     };
+    let cwd = kernel.cwd().map(Path::to_path_buf).or(cwd);
 
     let kernel_info = match kernel.kernel_info() {
         Some(info) => info,
         None => {
             // Shouldn't happen since launch succeeded, but handle gracefully
             let _ = kernel.shutdown().await;
-            return KernelReport::new_failed_at_startup(
-                kernel_name,
-                language,
-                "No kernel info after launch".to_string(),
-                start.elapsed(),
-            );
+            return KernelReport {
+                env,
+                cwd,
+                attached,
+                isolation_mode: isolation_options.mode,
+                ..KernelReport::new_failed_at_startup(
+                    run_id.to_string(),
+                    kernel_name,
+                    language,
+                    "No kernel info after launch".to_string(),
+                    start.elapsed(),
+                )
+            };
         }
     };
 
@@ -662,38 +2622,872 @@ pub async fn run_conformance_suite(
     let implementation = kernel_info.implementation.clone();
     let protocol_version = kernel_info.protocol_version.clone();
 
+    let startup_duration = kernel.startup_duration();
+    let monitor = if !measure_resources {
+        None
+    } else if !RESOURCES_SUPPORTED {
+        eprintln!("Warning: can't measure resources on this platform");
+        None
+    } else if let Some(pid) = kernel.pid() {
+        Some(ResourceMonitor::start(pid))
+    } else {
+        eprintln!("Warning: can't measure resources of a kernel the harness didn't start");
+        None
+    };
+
+    let warmup_durations = kernel.warm_up(warmup).await;
+    let snippet_overrides = kernel.overridden_snippets().to_vec();
+
     let mut results = Vec::new();
+    if tiers.contains(&TestCategory::Tier1Basic) {
+        results.push(startup_time_record(startup_duration));
+    }
+    let mut isolation = if isolation_options.check_state {
+        Some(IsolationTracker::start(&mut kernel).await)
+    } else {
+        None
+    };
 
-    for test in tests {
-        // Skip tests not in requested tiers
-        if !tiers.contains(&test.category) {
-            continue;
+    // Shutdown tests end the kernel, so every other test (registered and
+    // extra ones included) runs before them
+    let (shutdown_tests, mut body): (Vec<&ConformanceTest>, Vec<&ConformanceTest>) =
+        tests.tests().iter().partition(|test| test.is_shutdown());
+
+    // Per-tier isolation runs each tier together on its own kernel
+    let per_tier = isolation_options.mode == IsolationMode::PerTier;
+    if per_tier {
+        body.sort_by_key(|test| test.category.tier_number());
+    }
+
+    let mut kernel_launches = 1;
+    // Resolves to why the suite stopped early, if it did
+    let run_tests = async {
+        let mut last_category = None;
+        let mut followed_pid = kernel.pid();
+        for test in body {
+            if !tiers.contains(&test.category) {
+                continue;
+            }
+            if is_past(deadline) {
+                return Some(EarlyStop::SuiteTimeout);
+            }
+
+            // A dead kernel isn't relaunched: the next test reports it
+            if per_tier
+                && last_category.is_some_and(|category| category != test.category)
+                && kernel.exit_status().is_none()
+            {
+                match relaunch(&mut kernel).await {
+                    Ok(()) => {
+                        kernel_launches += 1;
+                        if let (Some(monitor), Some(pid)) = (&monitor, kernel.pid()) {
+                            monitor.follow(pid);
+                            followed_pid = Some(pid);
+                        }
+                        kernel.warm_up(warmup).await;
+                        if let Some(tracker) = isolation.as_mut() {
+                            tracker.rebaseline(&mut kernel).await;
+                        }
+                    }
+                    Err(e) => eprintln!(
+                        "Warning: not restarting kernel before tier {}: {}",
+                        test.category.tier_number(),
+                        e
+                    ),
+                }
+            }
+            last_category = Some(test.category);
+
+            if run_builtin_test(&mut kernel, test, &mut results, &mut isolation).await {
+                return Some(EarlyStop::KernelDied);
+            }
+            // A restart may have started the kernel process again
+            if let (Some(monitor), Some(pid)) = (&monitor, kernel.pid()) {
+                if followed_pid != Some(pid) {
+                    monitor.follow(pid);
+                    followed_pid = Some(pid);
+                }
+            }
         }
 
-        let test_start = Instant::now();
-        let result = (test.run)(&mut kernel).await;
+        let stopped = run_extra_tests(
+            &mut kernel,
+            tiers,
+            extra_tests,
+            &mut results,
+            &mut isolation,
+            deadline,
+        )
+        .await;
+        if stopped.is_some() {
+            return stopped;
+        }
 
-        results.push(TestRecord {
-            name: test.name.to_string(),
-            category: test.category,
-            description: test.description.to_string(),
-            message_type: test.message_type.to_string(),
-            result,
-            duration: test_start.elapsed(),
+        for test in shutdown_tests {
+            if tiers.contains(&test.category) {
+                if is_past(deadline) {
+                    return Some(EarlyStop::SuiteTimeout);
+                }
+                if run_builtin_test(&mut kernel, test, &mut results, &mut isolation).await {
+                    return Some(EarlyStop::KernelDied);
+                }
+            }
+        }
+        None
+    };
+    // Dropping run_tests abandons whatever test was in flight
+    let (interrupted, stopped) = tokio::select! {
+        stopped = run_tests => (false, stopped),
+        () = interrupt.wait() => (true, None),
+    };
+    let timed_out = stopped == Some(EarlyStop::SuiteTimeout);
+    let kernel_crash = results
+        .iter()
+        .enumerate()
+        .find_map(|(after_tests, record)| match record.result.failure_kind() {
+            Some(FailureKind::KernelDied { status }) => Some(KernelCrash {
+                after_tests,
+                status: status.clone(),
+            }),
+            _ => None,
         });
+
+    let resources = monitor.and_then(ResourceMonitor::finish);
+
+    if interrupted || stopped.is_some() {
+        let reason = if interrupted {
+            "interrupted"
+        } else if timed_out {
+            "suite timeout exceeded"
+        } else {
+            "kernel process exited"
+        };
+        let mut ran: HashSet<String> = results.iter().map(|r| r.name.clone()).collect();
+        let mut skip = |name: &str, category, description: &str, message_type: &str, custom| {
+            if tiers.contains(&category) && ran.insert(name.to_string()) {
+                results.push(skipped_record(
+                    reason,
+                    name,
+                    category,
+                    description,
+                    message_type,
+                    custom,
+                ));
+            }
+        };
+        for test in tests.tests() {
+            skip(
+                &test.name,
+                test.category,
+                &test.description,
+                &test.message_type,
+                false,
+            );
+        }
+        for test in extra_tests {
+            skip(
+                &test.name,
+                test.category,
+                &test.description,
+                &test.message_type,
+                true,
+            );
+        }
+        for finalizer in finalizers {
+            skip(
+                finalizer.name,
+                finalizer.category,
+                finalizer.description,
+                finalizer.message_type,
+                false,
+            );
+        }
+        if interrupted {
+            kernel.abort().await;
+        }
+    } else {
+        results.extend(run_finalizers(&kernel, tiers, finalizers));
+    }
+
+    if let Some(dir) = &transcript_dir {
+        let transcript = Transcript {
+            kernel_name: kernel_name.clone(),
+            language: language.clone(),
+            implementation: implementation.clone(),
+            protocol_version: protocol_version.clone(),
+            run_id: run_id.to_string(),
+            messages: kernel.observed_messages().to_vec(),
+            attached,
+            kernel_info: kernel.kernel_info().cloned(),
+            snippets: Some(kernel.snippets().clone()),
+            iopub_welcome_received: kernel.iopub_welcome_received(),
+            test_timeout: kernel.options().test_timeout,
+            exchanges: std::mem::take(&mut kernel.exchanges),
+        };
+        if let Err(e) = transcript.save(&transcript_path(dir, &kernel_name)) {
+            eprintln!("Warning: failed to save transcript: {}", e);
+        }
     }
 
     // Shutdown kernel (ignore errors during shutdown)
-    let _ = kernel.shutdown().await;
+    let kernel_exit = kernel.shutdown().await.ok().flatten();
 
     KernelReport {
+        run_id: run_id.to_string(),
         kernel_name,
         language,
         implementation,
         protocol_version,
         results,
-        timestamp: Utc::now(),
+        timestamp: started_at,
         total_duration: start.elapsed(),
+        startup_duration,
+        warmup_durations,
+        env,
+        cwd,
+        snippet_overrides,
+        attached,
+        isolation_mode: isolation_options.mode,
+        kernel_launches,
         startup_error: None,
+        requirements: Vec::new(),
+        isolation: isolation.map(|tracker| tracker.report),
+        resources,
+        interrupted,
+        timed_out,
+        kernel_crash,
+        kernel_exit,
+    }
+}
+
+/// Re-run `tests` and `extra_tests` against the exchanges recorded in a
+/// transcript, then re-evaluate the suite finalizers over its messages,
+/// without launching a kernel.
+///
+/// A test the transcript has no exchange for, that sends something other
+/// than what was recorded, or that needs more than the recorded channels
+/// (a second connection, a relaunch, the process itself) is skipped.
+pub async fn replay_suite(
+    replay: &ReplayKernel,
+    tiers: &[TestCategory],
+    tests: &TestRegistry,
+    finalizers: &[SuiteFinalizer],
+    extra_tests: &[ExtraTest],
+    run_id: &str,
+) -> KernelReport {
+    let start = Instant::now();
+    let started_at = Utc::now();
+    let transcript = replay.transcript();
+    let mut kernel = KernelUnderTest::replaying(Arc::new(transcript.clone()));
+
+    let mut results = Vec::new();
+    let mut isolation = None;
+    let (shutdown_tests, body): (Vec<&ConformanceTest>, Vec<&ConformanceTest>) =
+        tests.tests().iter().partition(|test| test.is_shutdown());
+    let selected = |test: &&ConformanceTest| tiers.contains(&test.category);
+    // Same order as the recorded run, shutdown tests last
+    for test in body.into_iter().filter(selected) {
+        run_builtin_test(&mut kernel, test, &mut results, &mut isolation).await;
+    }
+    run_extra_tests(
+        &mut kernel,
+        tiers,
+        extra_tests,
+        &mut results,
+        &mut isolation,
+        None,
+    )
+    .await;
+    for test in shutdown_tests.into_iter().filter(selected) {
+        run_builtin_test(&mut kernel, test, &mut results, &mut isolation).await;
+    }
+    results.extend(run_finalizers(replay, tiers, finalizers));
+
+    KernelReport {
+        run_id: run_id.to_string(),
+        kernel_name: transcript.kernel_name.clone(),
+        language: transcript.language.clone(),
+        implementation: transcript.implementation.clone(),
+        protocol_version: transcript.protocol_version.clone(),
+        results,
+        timestamp: started_at,
+        total_duration: start.elapsed(),
+        startup_duration: Duration::ZERO,
+        warmup_durations: Vec::new(),
+        env: BTreeMap::new(),
+        cwd: None,
+        snippet_overrides: Vec::new(),
+        attached: transcript.attached,
+        isolation_mode: IsolationMode::default(),
+        kernel_launches: 0,
+        startup_error: None,
+        requirements: Vec::new(),
+        isolation: None,
+        resources: None,
+        interrupted: false,
+        timed_out: false,
+        kernel_crash: None,
+        kernel_exit: None,
+    }
+}
+
+/// Informational record of how long the kernel took to start, so the figure
+/// reaches consumers that only look at per-test results. Always passes and
+/// never counts toward scores or requirements.
+fn startup_time_record(startup_duration: Duration) -> TestRecord {
+    TestRecord {
+        name: "kernel_startup_time".to_string(),
+        category: TestCategory::Tier1Basic,
+        description: "Time from spawning the kernel to its first kernel_info_reply (informational)"
+            .to_string(),
+        message_type: "kernel_info_request".to_string(),
+        result: TestResult::Pass,
+        duration: startup_duration,
+        custom: false,
+        informational: true,
+        metrics: BTreeMap::from([(
+            "startup_ms".to_string(),
+            startup_duration.as_secs_f64() * 1000.0,
+        )]),
+        flagged: None,
+        missing_parent_header: false,
+        kernel_log_excerpt: None,
+        attempts: Vec::new(),
+    }
+}
+
+/// Record for a test the run never got to, e.g. because it was interrupted.
+fn skipped_record(
+    reason: &str,
+    name: &str,
+    category: TestCategory,
+    description: &str,
+    message_type: &str,
+    custom: bool,
+) -> TestRecord {
+    TestRecord {
+        name: name.to_string(),
+        category,
+        description: description.to_string(),
+        message_type: message_type.to_string(),
+        result: TestResult::Skipped {
+            reason: reason.to_string(),
+        },
+        duration: Duration::ZERO,
+        custom,
+        informational: false,
+        metrics: BTreeMap::new(),
+        flagged: None,
+        missing_parent_header: false,
+        kernel_log_excerpt: None,
+        attempts: Vec::new(),
+    }
+}
+
+/// Run the finalizers for `tiers` over every message `source` observed.
+fn run_finalizers(
+    source: &impl MessageSource,
+    tiers: &[TestCategory],
+    finalizers: &[SuiteFinalizer],
+) -> Vec<TestRecord> {
+    finalizers
+        .iter()
+        .filter(|finalizer| tiers.contains(&finalizer.category))
+        .map(|finalizer| {
+            let test_start = Instant::now();
+            let result = (finalizer.run)(source.observed_messages());
+
+            TestRecord {
+                name: finalizer.name.to_string(),
+                category: finalizer.category,
+                description: finalizer.description.to_string(),
+                message_type: finalizer.message_type.to_string(),
+                result,
+                duration: test_start.elapsed(),
+                custom: false,
+                informational: false,
+                metrics: BTreeMap::new(),
+                flagged: None,
+                missing_parent_header: false,
+                kernel_log_excerpt: None,
+                attempts: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Recent kernel stderr for a test that timed out, hit a kernel error or
+/// found the kernel dead, where the kernel's own output is most likely to
+/// explain what happened.
+fn kernel_log_excerpt_for(kernel: &KernelUnderTest, result: &TestResult) -> Option<String> {
+    match result.failure_kind() {
+        Some(FailureKind::Timeout | FailureKind::KernelError | FailureKind::KernelDied { .. }) => {
+            kernel.kernel_log_excerpt()
+        }
+        _ => None,
+    }
+}
+
+/// Run one built-in test and record its result.
+///
+/// Returns true if the kernel process turned out to have exited.
+async fn run_builtin_test(
+    kernel: &mut KernelUnderTest,
+    test: &ConformanceTest,
+    results: &mut Vec<TestRecord>,
+    isolation: &mut Option<IsolationTracker>,
+) -> bool {
+    let test_start = Instant::now();
+    // A failed shutdown test may have ended the kernel; don't try again
+    let retries = if test.is_shutdown() {
+        0
+    } else {
+        kernel.retries
+    };
+    let mut attempts = Vec::new();
+    let (result, metrics, flagged, missing_parent_header) = loop {
+        // Each attempt starts without IOPub messages left over from earlier
+        kernel.discard_iopub_backlog();
+        // Never shut down a kernel we merely attached to unless allowed
+        let result = if test.is_shutdown() && !kernel.may_shut_down() {
+            TestResult::Unsupported
+        } else if let Some(died) = kernel_died(kernel) {
+            died
+        } else {
+            kernel.begin_exchange(&test.name);
+            let result = (test.run)(kernel).await;
+            let result = kernel.end_exchange(&test.name, result);
+            // Shutdown tests end the kernel on purpose
+            match kernel_died(kernel) {
+                Some(died) if result.is_failure() && !test.is_shutdown() => died,
+                _ => result,
+            }
+        };
+        let (metrics, flagged) = kernel.take_metrics();
+        let missing_parent_header = std::mem::take(&mut kernel.missing_parent_header);
+        attempts.push(result.clone());
+        if !result.is_failure() || result.is_kernel_died() || attempts.len() > retries as usize {
+            break (result, metrics, flagged, missing_parent_header);
+        }
+    };
+    if attempts.len() == 1 {
+        attempts.clear();
+    }
+    let duration = test_start.elapsed();
+    let died = result.is_kernel_died();
+    if died {
+        kernel.kernel_log.settle().await;
+    }
+    let kernel_log_excerpt = kernel_log_excerpt_for(kernel, &result);
+
+    results.push(TestRecord {
+        name: test.name.clone(),
+        category: test.category,
+        description: test.description.clone(),
+        message_type: test.message_type.clone(),
+        result,
+        duration,
+        custom: false,
+        informational: false,
+        metrics,
+        flagged,
+        missing_parent_header,
+        kernel_log_excerpt,
+        attempts,
+    });
+
+    if let Some(tracker) = isolation.as_mut() {
+        if test.is_shutdown() || died {
+            tracker.stop();
+        } else {
+            tracker.after_test(kernel, &test.name).await;
+        }
+    }
+    died
+}
+
+/// Replace `kernel` with a fresh launch of the same kernelspec, carrying
+/// over the messages observed and the exchanges recorded so far so
+/// finalizers and the transcript still see the whole run.
+///
+/// The new kernel is started before the old one is shut down, so a failed
+/// relaunch leaves `kernel` usable.
+async fn relaunch(kernel: &mut KernelUnderTest) -> Result<()> {
+    let builder = kernel
+        .relaunch_builder()
+        .ok_or(HarnessError::NotOwned("relaunch"))?;
+    let mut fresh = builder.launch().await?;
+    kernel.discard_iopub_backlog();
+    let mut observed = std::mem::take(&mut kernel.observed);
+    observed.append(&mut fresh.observed);
+    fresh.observed = observed;
+    fresh.exchanges = std::mem::take(&mut kernel.exchanges);
+
+    let previous = std::mem::replace(kernel, fresh);
+    let _ = previous.shutdown().await;
+    Ok(())
+}
+
+/// Run user-supplied extra tests that fall within the requested tiers.
+///
+/// Returns why they stopped early if `deadline` passed or the kernel
+/// process exited before they had all run.
+async fn run_extra_tests(
+    kernel: &mut KernelUnderTest,
+    tiers: &[TestCategory],
+    extra_tests: &[ExtraTest],
+    results: &mut Vec<TestRecord>,
+    isolation: &mut Option<IsolationTracker>,
+    deadline: Option<Instant>,
+) -> Option<EarlyStop> {
+    for test in extra_tests {
+        if !tiers.contains(&test.category) {
+            continue;
+        }
+        if is_past(deadline) {
+            return Some(EarlyStop::SuiteTimeout);
+        }
+
+        let test_start = Instant::now();
+        let mut attempts = Vec::new();
+        let (result, missing_parent_header) = loop {
+            kernel.discard_iopub_backlog();
+            let result = match kernel_died(kernel) {
+                Some(died) => died,
+                None => {
+                    kernel.begin_exchange(&test.name);
+                    let result = test.run(kernel).await;
+                    let result = kernel.end_exchange(&test.name, result);
+                    match kernel_died(kernel) {
+                        Some(died) if result.is_failure() => died,
+                        _ => result,
+                    }
+                }
+            };
+            let missing_parent_header = std::mem::take(&mut kernel.missing_parent_header);
+            attempts.push(result.clone());
+            if !result.is_failure()
+                || result.is_kernel_died()
+                || attempts.len() > kernel.retries as usize
+            {
+                break (result, missing_parent_header);
+            }
+        };
+        if attempts.len() == 1 {
+            attempts.clear();
+        }
+        let died = result.is_kernel_died();
+        if died {
+            kernel.kernel_log.settle().await;
+        }
+        let kernel_log_excerpt = kernel_log_excerpt_for(kernel, &result);
+
+        results.push(TestRecord {
+            name: test.name.clone(),
+            category: test.category,
+            description: test.description.clone(),
+            message_type: test.message_type.clone(),
+            result,
+            duration: test_start.elapsed(),
+            custom: true,
+            informational: false,
+            metrics: BTreeMap::new(),
+            flagged: None,
+            missing_parent_header,
+            kernel_log_excerpt,
+            attempts,
+        });
+
+        if died {
+            if let Some(tracker) = isolation.as_mut() {
+                tracker.stop();
+            }
+            return Some(EarlyStop::KernelDied);
+        }
+        if let Some(tracker) = isolation.as_mut() {
+            tracker.after_test(kernel, &test.name).await;
+        }
+    }
+    None
+}
+
+/// Why the suite stopped before running every test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EarlyStop {
+    /// The suite timeout ran out
+    SuiteTimeout,
+    /// The kernel process exited
+    KernelDied,
+}
+
+/// Whether the suite has run past `deadline`; never if there is none.
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// A `KernelDied` failure if the kernel process has exited.
+fn kernel_died(kernel: &mut KernelUnderTest) -> Option<TestResult> {
+    let status = kernel.exit_status()?;
+    Some(TestResult::fail(
+        format!("Kernel process exited with {}", status),
+        FailureKind::KernelDied {
+            status: status.to_string(),
+        },
+    ))
+}
+
+/// Compares state fingerprints between tests for isolation checking.
+struct IsolationTracker {
+    /// Fingerprint after the previous test; `None` once checking has stopped
+    last: Option<String>,
+    report: IsolationReport,
+}
+
+impl IsolationTracker {
+    /// Take the baseline fingerprint before any test runs.
+    async fn start(kernel: &mut KernelUnderTest) -> Self {
+        let mut report = IsolationReport::default();
+        let last = match kernel.state_fingerprint().await {
+            Ok(Some(fingerprint)) => Some(fingerprint),
+            Ok(None) => {
+                report.error = Some(format!(
+                    "no state_fingerprint_code for language {}",
+                    kernel.snippets().language
+                ));
+                None
+            }
+            Err(e) => {
+                report.error = Some(format!("baseline fingerprint failed: {}", e));
+                None
+            }
+        };
+        Self { last, report }
+    }
+
+    /// Fingerprint again and note `test_name` if the state changed.
+    async fn after_test(&mut self, kernel: &mut KernelUnderTest, test_name: &str) {
+        let Some(last) = &self.last else {
+            return;
+        };
+        match kernel.state_fingerprint().await {
+            Ok(Some(fingerprint)) => {
+                if fingerprint != *last {
+                    self.report.state_mutating_tests.push(test_name.to_string());
+                }
+                self.last = Some(fingerprint);
+            }
+            Ok(None) => self.last = None,
+            Err(e) => {
+                self.report.error = Some(format!("fingerprint after {} failed: {}", test_name, e));
+                self.last = None;
+            }
+        }
+    }
+
+    /// Take a new baseline, e.g. after the kernel was relaunched, unless
+    /// checking has already stopped.
+    async fn rebaseline(&mut self, kernel: &mut KernelUnderTest) {
+        if self.last.is_none() {
+            return;
+        }
+        match kernel.state_fingerprint().await {
+            Ok(fingerprint) => self.last = fingerprint,
+            Err(e) => {
+                self.report.error = Some(format!("baseline fingerprint after relaunch failed: {}", e));
+                self.last = None;
+            }
+        }
+    }
+
+    /// Stop checking, e.g. once the kernel has been shut down.
+    fn stop(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jupyter_protocol::messaging::ExecuteReply;
+
+    fn execute_request() -> JupyterMessage {
+        ExecuteRequest::new("1".to_string()).into()
+    }
+
+    fn reply_to(parent: Option<&JupyterMessage>) -> JupyterMessage {
+        JupyterMessage::new(ExecuteReply::default(), parent)
+    }
+
+    #[tokio::test]
+    async fn test_stale_control_reply_is_skipped() {
+        // e.g. the reply to a debug_request that already timed out
+        let stale = execute_request();
+        let relayed = channels::relay("session-1", Recorder::default(), |mut relay| {
+            tokio::spawn(async move {
+                while let Some((_, outgoing)) = relay.outgoing.recv().await {
+                    if let channels::Outgoing::Message(channel, request) = outgoing {
+                        relay.deliver(channel, reply_to(Some(&stale)));
+                        relay.deliver(channel, reply_to(Some(&request)));
+                    }
+                }
+            })
+        });
+        let connection_info = KernelLaunchOptions::default().connection_info(
+            "relayed",
+            "abc",
+            &[1, 2, 3, 4, 5],
+            Path::new("/tmp/runtime"),
+            "key".to_string(),
+        );
+        let mut kernel = KernelUnderTest::with_channels(
+            KernelUnderTestBuilder::attach_to(connection_info),
+            None,
+            "session-1".to_string(),
+            KernelLink::Zmq,
+            KernelChannels::from(relayed),
+            Recorder::default(),
+        );
+
+        let (msg_id, reply, _) = kernel
+            .control_request_with_iopub(KernelInfoRequest {}, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(reply.parent_header.unwrap().msg_id, msg_id);
+        assert!(!kernel.missing_parent_header());
+    }
+
+    #[test]
+    fn test_interrupt_is_shared_by_clones() {
+        let interrupt = SuiteInterrupt::new();
+        let suite = interrupt.clone();
+        assert!(!suite.is_triggered());
+        interrupt.trigger();
+        assert!(suite.is_triggered());
+    }
+
+    #[test]
+    fn test_registry_replaces_tests_by_name() {
+        let test = |name: &str, description: &str| {
+            ConformanceTest::new(
+                name,
+                TestCategory::Tier4Advanced,
+                description,
+                "execute_request",
+                |_| Box::pin(async { TestResult::Pass }),
+            )
+        };
+        let mut registry = TestRegistry::new();
+        registry.extend([test("a", "first"), test("b", "second")]);
+        registry.register(test("a", "replaced"));
+
+        let tests = registry.tests();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "a");
+        assert_eq!(tests[0].description, "replaced");
+        assert_eq!(tests[1].name, "b");
+    }
+
+    #[test]
+    fn test_suite_deadline() {
+        assert!(!is_past(None));
+        assert!(is_past(Some(Instant::now())));
+        assert!(!is_past(Some(Instant::now() + Duration::from_secs(60))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_status_of() {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(
+            exit_status_of(ExitStatus::from_raw(3 << 8)),
+            KernelExitStatus::CleanExit(3)
+        );
+        assert_eq!(
+            exit_status_of(ExitStatus::from_raw(11)),
+            KernelExitStatus::Signaled(11)
+        );
+        // Stopped by SIGSTOP: neither an exit code nor a terminating signal
+        assert_eq!(
+            exit_status_of(ExitStatus::from_raw(0x137f)),
+            KernelExitStatus::Unknown
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exit_within_waits_out_a_slow_exit() {
+        // Exits after longer than the fixed wait shutdown used to allow
+        let mut slow = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 0.6; exit 4"])
+            .spawn()
+            .unwrap();
+        let status = exit_within(&mut slow, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .expect("exits within the grace period");
+        assert_eq!(exit_status_of(status), KernelExitStatus::CleanExit(4));
+
+        let mut stuck = tokio::process::Command::new("sleep")
+            .arg("5")
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let waited = exit_within(&mut stuck, Duration::from_millis(100)).await;
+        assert!(matches!(waited, Ok(None)));
+    }
+
+    #[test]
+    fn test_port_conflicts_are_retried() {
+        let exited = HarnessError::ExitedDuringStartup("exited with 1".to_string());
+        assert!(is_port_conflict(&exited));
+        let refused = HarnessError::ConnectionFailed("Connection refused (os error 111)".into());
+        assert!(is_port_conflict(&refused));
+
+        for error in [
+            HarnessError::ConnectionFailed("bad key".to_string()),
+            HarnessError::Timeout("kernel startup".to_string()),
+            HarnessError::LaunchFailed("not found".to_string()),
+        ] {
+            assert!(!is_port_conflict(&error), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_parented_reply_matches() {
+        let request = execute_request();
+        let mut matcher = ReplyMatcher::for_request(&request);
+        assert_eq!(matcher.reply_type, "execute_reply");
+        assert!(matcher.offer(reply_to(Some(&request))).is_some());
+    }
+
+    #[test]
+    fn test_unparented_reply_is_only_a_fallback() {
+        let request = execute_request();
+        let mut matcher = ReplyMatcher::for_request(&request);
+
+        assert!(matcher.offer(reply_to(None)).is_none());
+        assert!(matcher.has_fallback());
+        assert!(matcher.take_fallback().unwrap().parent_header.is_none());
+
+        // Un-parented replies of the wrong type are never accepted
+        let mut matcher = ReplyMatcher::for_request(&request);
+        let unrelated = JupyterMessage::new(ShutdownRequest { restart: false }, None);
+        assert!(matcher.offer(unrelated).is_none());
+        assert!(!matcher.has_fallback());
+    }
+
+    #[test]
+    fn test_mixed_replies_prefer_parented() {
+        let earlier = execute_request();
+        let request = execute_request();
+        let mut matcher = ReplyMatcher::for_request(&request);
+
+        // A stale reply to an earlier request is skipped outright
+        assert!(matcher.offer(reply_to(Some(&earlier))).is_none());
+        assert!(!matcher.has_fallback());
+
+        assert!(matcher.offer(reply_to(None)).is_none());
+        let reply = matcher.offer(reply_to(Some(&request))).unwrap();
+        assert_eq!(reply.parent_header.unwrap().msg_id, request.header.msg_id);
     }
 }
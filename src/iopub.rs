@@ -0,0 +1,276 @@
+//! Background collection of the kernel's IOPub messages.
+//!
+//! A dedicated task owns the IOPub subscription and forwards every message,
+//! stamped with when it was read, the moment it arrives. Messages wait in a
+//! backlog until a query claims them by whatever it is looking for (usually
+//! the parent msg_id), so output published between two requests is never
+//! dropped on the floor and no message waits on a polling interval.
+
+use crate::channels::Channel;
+use crate::harness::{HarnessError, Result};
+use crate::transcript::Recorder;
+use jupyter_protocol::JupyterMessage;
+use runtimelib::ClientIoPubConnection;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Most unclaimed messages kept; the oldest are dropped beyond this
+const IOPUB_BACKLOG_CAPACITY: usize = 10_000;
+
+/// Pause after a failed read, so a socket that keeps failing can't spin
+const READ_ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+/// An IOPub message and when the collector read it.
+pub type TimedMessage = (Instant, JupyterMessage);
+
+/// What the reader task hands over for each read.
+pub(crate) type Delivery = std::result::Result<TimedMessage, String>;
+
+/// Reader task for the kernel's IOPub subscription, plus the messages it
+/// has delivered that no query has claimed yet.
+pub(crate) struct IoPubCollector {
+    receiver: mpsc::UnboundedReceiver<Delivery>,
+    /// Unclaimed messages, oldest first
+    backlog: VecDeque<TimedMessage>,
+    /// First reader error received outside a query, for the next one to return
+    error: Option<String>,
+    reader: JoinHandle<()>,
+}
+
+impl IoPubCollector {
+    /// Hand `iopub` to a reader task that forwards each message as it
+    /// arrives, adding it to `recorder` on the way.
+    pub(crate) fn start(mut iopub: ClientIoPubConnection, recorder: Recorder) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let reader = tokio::spawn(async move {
+            loop {
+                let delivery = match iopub.read().await {
+                    Ok(msg) => {
+                        recorder.received(Channel::IoPub, Some(&msg));
+                        Ok((Instant::now(), msg))
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                let failed = delivery.is_err();
+                if sender.send(delivery).is_err() {
+                    break;
+                }
+                if failed {
+                    tokio::time::sleep(READ_ERROR_BACKOFF).await;
+                }
+            }
+        });
+        Self::new(receiver, reader)
+    }
+
+    /// Collect what `reader`, a task feeding `receiver`, delivers; the task
+    /// is stopped along with the collector.
+    pub(crate) fn new(receiver: mpsc::UnboundedReceiver<Delivery>, reader: JoinHandle<()>) -> Self {
+        Self {
+            receiver,
+            backlog: VecDeque::new(),
+            error: None,
+            reader,
+        }
+    }
+
+    /// Claim the oldest message matching `wanted`, waiting until `deadline`
+    /// for one to arrive; None if none did.
+    ///
+    /// Every message delivered meanwhile is recorded in `observed`; those
+    /// that don't match stay in the backlog for a later query. A reader
+    /// error received since the last query fails this one.
+    pub(crate) async fn next_matching(
+        &mut self,
+        observed: &mut Vec<JupyterMessage>,
+        deadline: Instant,
+        wanted: impl Fn(&JupyterMessage) -> bool,
+    ) -> Result<Option<TimedMessage>> {
+        if let Some(error) = self.error.take() {
+            return Err(HarnessError::ProtocolError(error));
+        }
+        if let Some(index) = self.backlog.iter().position(|(_, msg)| wanted(msg)) {
+            return Ok(self.backlog.remove(index));
+        }
+
+        loop {
+            let delivery =
+                match tokio::time::timeout_at(deadline.into(), self.receiver.recv()).await {
+                    Ok(Some(delivery)) => delivery,
+                    Ok(None) => {
+                        return Err(HarnessError::ProtocolError(
+                            "IOPub reader stopped".to_string(),
+                        ))
+                    }
+                    Err(_) => return Ok(None),
+                };
+            let (at, msg) = delivery.map_err(HarnessError::ProtocolError)?;
+            observe(observed, &msg);
+            if wanted(&msg) {
+                return Ok(Some((at, msg)));
+            }
+            self.push((at, msg));
+        }
+    }
+
+    /// Move whatever the reader has delivered so far into the backlog,
+    /// without waiting for more.
+    ///
+    /// The first reader error among them is kept for the next query, so a
+    /// corrupted message fails a test whichever call received it.
+    pub(crate) fn receive_pending(&mut self, observed: &mut Vec<JupyterMessage>) {
+        while let Ok(delivery) = self.receiver.try_recv() {
+            match delivery {
+                Ok((at, msg)) => {
+                    observe(observed, &msg);
+                    self.push((at, msg));
+                }
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+    }
+
+    /// Receive what has been delivered and drop every unclaimed message.
+    /// A reader error among them is still kept for the next query.
+    pub(crate) fn discard_backlog(&mut self, observed: &mut Vec<JupyterMessage>) {
+        self.receive_pending(observed);
+        self.backlog.clear();
+    }
+
+    fn push(&mut self, message: TimedMessage) {
+        if self.backlog.len() == IOPUB_BACKLOG_CAPACITY {
+            self.backlog.pop_front();
+        }
+        self.backlog.push_back(message);
+    }
+}
+
+impl Drop for IoPubCollector {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Record `msg` for suite finalizers. iopub_welcome is part of subscribing,
+/// not something the kernel published, so it is left out.
+fn observe(observed: &mut Vec<JupyterMessage>, msg: &JupyterMessage) {
+    if msg.content.message_type() != "iopub_welcome" {
+        observed.push(msg.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jupyter_protocol::{ExecuteRequest, ExecutionState, JupyterMessageContent, Status};
+
+    fn status_for(parent: &JupyterMessage, execution_state: ExecutionState) -> JupyterMessage {
+        JupyterMessage::new(Status { execution_state }, Some(parent))
+    }
+
+    fn is_child_of(msg: &JupyterMessage, parent: &JupyterMessage) -> bool {
+        msg.parent_header
+            .as_ref()
+            .is_some_and(|h| h.msg_id == parent.header.msg_id)
+    }
+
+    #[tokio::test]
+    async fn test_unclaimed_messages_wait_for_their_query() {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut collector = IoPubCollector::new(receiver, tokio::spawn(async {}));
+        let first: JupyterMessage = ExecuteRequest::new("1".to_string()).into();
+        let second: JupyterMessage = ExecuteRequest::new("2".to_string()).into();
+        for msg in [
+            status_for(&first, ExecutionState::Busy),
+            status_for(&second, ExecutionState::Busy),
+            status_for(&second, ExecutionState::Idle),
+            status_for(&first, ExecutionState::Idle),
+        ] {
+            sender.send(Ok((Instant::now(), msg))).unwrap();
+        }
+
+        let mut observed = Vec::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let mut claimed = Vec::new();
+        while let Some((_, msg)) = collector
+            .next_matching(&mut observed, deadline, |msg| is_child_of(msg, &second))
+            .await
+            .unwrap()
+        {
+            claimed.push(msg);
+        }
+        assert_eq!(claimed.len(), 2);
+        assert_eq!(observed.len(), 4);
+        assert_eq!(collector.backlog.len(), 2);
+
+        // The first request's messages arrived while the second was being
+        // collected, and are still there in order
+        let (_, busy) = collector
+            .next_matching(&mut observed, deadline, |msg| is_child_of(msg, &first))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            busy.content,
+            JupyterMessageContent::Status(Status {
+                execution_state: ExecutionState::Busy
+            })
+        ));
+        assert_eq!(observed.len(), 4);
+
+        collector.discard_backlog(&mut observed);
+        assert!(collector.backlog.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reader_errors_surface_and_welcome_is_not_observed() {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut collector = IoPubCollector::new(receiver, tokio::spawn(async {}));
+        let welcome = JupyterMessage::new(
+            jupyter_protocol::UnknownMessage {
+                msg_type: "iopub_welcome".to_string(),
+                content: serde_json::json!({ "subscription": "" }),
+            },
+            None,
+        );
+        sender.send(Ok((Instant::now(), welcome))).unwrap();
+        sender.send(Err("bad signature".to_string())).unwrap();
+
+        let mut observed = Vec::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let welcomed = collector
+            .next_matching(&mut observed, deadline, |msg| {
+                msg.content.message_type() == "iopub_welcome"
+            })
+            .await
+            .unwrap();
+        assert!(welcomed.is_some());
+        assert!(observed.is_empty());
+
+        let err = collector
+            .next_matching(&mut observed, deadline, |_| true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("bad signature"), "{}", err);
+
+        // An error received outside a query is returned by the next one
+        sender.send(Err("undecodable message".to_string())).unwrap();
+        sender.send(Err("bad signature".to_string())).unwrap();
+        collector.discard_backlog(&mut observed);
+        let err = collector
+            .next_matching(&mut observed, deadline, |_| true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("undecodable message"), "{}", err);
+
+        drop(sender);
+        assert!(collector
+            .next_matching(&mut observed, deadline, |_| true)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Capture of the kernel process's stdout and stderr.
+//!
+//! Both streams are drained in the background so a chatty kernel never
+//! blocks on a full pipe. The most recent stderr lines are kept in memory
+//! for failure excerpts; with a log directory, everything is also appended
+//! to per-kernel files that outlive the run.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Child;
+use tokio::task::JoinHandle;
+
+/// Most stderr lines kept in memory per kernel
+const KERNEL_LOG_CAPACITY: usize = 1000;
+
+/// Lines of stderr attached to a failed test record
+pub const KERNEL_LOG_EXCERPT_LINES: usize = 50;
+
+/// Paths of the stdout and stderr logs for `kernel_name` under `dir`.
+pub fn kernel_log_paths(dir: &Path, kernel_name: &str) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("{}.stdout.log", kernel_name)),
+        dir.join(format!("{}.stderr.log", kernel_name)),
+    )
+}
+
+/// Bounded, most-recent-last buffer of lines.
+#[derive(Debug, Default)]
+struct LineRing {
+    lines: VecDeque<String>,
+}
+
+impl LineRing {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == KERNEL_LOG_CAPACITY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    fn tail(&self, n: usize) -> Vec<String> {
+        let skip = self.lines.len().saturating_sub(n);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Output captured from a running kernel process.
+#[derive(Debug, Default)]
+pub struct KernelLog {
+    stderr: Arc<Mutex<LineRing>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl KernelLog {
+    /// Start draining the piped stdout and stderr of `process`, appending
+    /// them to the files from [`kernel_log_paths`] when `log_dir` is set.
+    pub(crate) async fn capture(
+        process: &mut Child,
+        log_dir: Option<&Path>,
+        kernel_name: &str,
+    ) -> std::io::Result<Self> {
+        let (stdout_path, stderr_path) = match log_dir {
+            Some(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                let (stdout, stderr) = kernel_log_paths(dir, kernel_name);
+                (Some(stdout), Some(stderr))
+            }
+            None => (None, None),
+        };
+
+        let stderr = Arc::new(Mutex::new(LineRing::default()));
+        let mut readers = Vec::new();
+        if let Some(pipe) = process.stdout.take() {
+            readers.push(drain(pipe, open_log(stdout_path).await?, None));
+        }
+        if let Some(pipe) = process.stderr.take() {
+            readers.push(drain(
+                pipe,
+                open_log(stderr_path).await?,
+                Some(stderr.clone()),
+            ));
+        }
+
+        Ok(Self { stderr, readers })
+    }
+
+    /// The last `n` stderr lines, or None if the kernel wrote nothing.
+    pub fn stderr_tail(&self, n: usize) -> Option<String> {
+        let lines = self.stderr.lock().ok()?.tail(n);
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    /// Give the readers a moment to catch up with an exited process, so its
+    /// last words are in the buffer before anyone reads it.
+    pub(crate) async fn settle(&mut self) {
+        for reader in &mut self.readers {
+            let _ = tokio::time::timeout(Duration::from_millis(100), reader).await;
+        }
+    }
+}
+
+impl Drop for KernelLog {
+    fn drop(&mut self) {
+        for reader in &self.readers {
+            reader.abort();
+        }
+    }
+}
+
+async fn open_log(path: Option<PathBuf>) -> std::io::Result<Option<tokio::fs::File>> {
+    match path {
+        Some(path) => Ok(Some(
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Copy `pipe` line by line into `file` and `ring` until EOF.
+fn drain(
+    pipe: impl AsyncRead + Unpin + Send + 'static,
+    mut file: Option<tokio::fs::File>,
+    ring: Option<Arc<Mutex<LineRing>>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(pipe).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(file) = file.as_mut() {
+                let _ = file.write_all(format!("{}\n", line).as_bytes()).await;
+            }
+            if let Some(ring) = &ring {
+                if let Ok(mut ring) = ring.lock() {
+                    ring.push(line);
+                }
+            }
+        }
+        if let Some(file) = file.as_mut() {
+            let _ = file.flush().await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_keeps_most_recent_lines() {
+        let mut ring = LineRing::default();
+        for i in 0..KERNEL_LOG_CAPACITY + 10 {
+            ring.push(i.to_string());
+        }
+        assert_eq!(ring.lines.len(), KERNEL_LOG_CAPACITY);
+        assert_eq!(ring.lines.front().map(String::as_str), Some("10"));
+        assert_eq!(
+            ring.tail(2),
+            vec![
+                (KERNEL_LOG_CAPACITY + 8).to_string(),
+                (KERNEL_LOG_CAPACITY + 9).to_string()
+            ]
+        );
+        assert_eq!(ring.tail(usize::MAX).len(), KERNEL_LOG_CAPACITY);
+    }
+
+    #[test]
+    fn test_log_paths_named_after_kernel() {
+        let (stdout, stderr) = kernel_log_paths(Path::new("/tmp/logs"), "python3");
+        assert_eq!(stdout, Path::new("/tmp/logs/python3.stdout.log"));
+        assert_eq!(stderr, Path::new("/tmp/logs/python3.stderr.log"));
+    }
+}
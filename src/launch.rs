@@ -0,0 +1,1083 @@
+//! Options for launching a kernel under test.
+//!
+//! ```no_run
+//! # async fn example(kernelspec: runtimelib::KernelspecDir) -> jupyter_kernel_test::harness::Result<()> {
+//! use jupyter_kernel_test::KernelUnderTest;
+//! use std::time::Duration;
+//!
+//! let kernel = KernelUnderTest::builder(kernelspec)
+//!     .timeout(Duration::from_secs(30))
+//!     .env("PYTHONUNBUFFERED", "1")
+//!     .launch()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A launched kernel can be handed to the standard suite afterwards, so one
+//! kernel serves both a caller's own checks and the conformance tests:
+//!
+//! ```no_run
+//! # async fn example(kernelspec: runtimelib::KernelspecDir) -> jupyter_kernel_test::harness::Result<()> {
+//! use jupyter_kernel_test::*;
+//!
+//! let mut kernel = KernelUnderTest::builder(kernelspec)
+//!     .capture_kernel_output(false)
+//!     .launch()
+//!     .await?;
+//! kernel.execute_and_collect("import mypackage").await?;
+//!
+//! let mut tests = TestRegistry::new();
+//! tests.extend(all_tests());
+//! let report = run_conformance_suite(
+//!     kernel,
+//!     &[TestCategory::Tier1Basic],
+//!     &tests,
+//!     &all_finalizers(),
+//!     &[],
+//!     &new_run_id(),
+//!     IsolationOptions::default(),
+//!     &SuiteInterrupt::new(),
+//! )
+//! .await;
+//! println!("{}", render_terminal(&report));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::config::BenchmarkConfig;
+use crate::harness::{HarnessError, KernelUnderTest, Result};
+use crate::server::JupyterServer;
+use crate::snippets::{LanguageSnippets, SnippetOverrides};
+use crate::transcript::Transcript;
+use jupyter_protocol::connection_info::{ConnectionInfo, Transport};
+use runtimelib::{peek_ports, KernelspecDir};
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default per-test timeout
+pub const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time a kernel gets to answer kernel_info after spawning
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time to wait for iopub_welcome (JEP 65) once the kernel is ready
+pub const DEFAULT_IOPUB_WELCOME_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Default time a kernel gets to exit on its own after shutdown_request
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Ports a kernel needs: shell, iopub, stdin, control and heartbeat
+const KERNEL_PORT_COUNT: usize = 5;
+
+/// Everything that controls how a kernel is spawned and connected to.
+#[derive(Debug, Clone)]
+pub struct KernelLaunchOptions {
+    /// Per-test timeout
+    pub test_timeout: Duration,
+    /// How long the kernel gets to answer kernel_info after spawning
+    /// before launch gives up
+    pub startup_timeout: Duration,
+    /// How long to wait for iopub_welcome before treating the kernel as
+    /// not supporting JEP 65
+    pub iopub_welcome_timeout: Duration,
+    /// ZeroMQ transport for all channels
+    pub transport: Transport,
+    /// Address the kernel binds to (TCP only)
+    pub ip: IpAddr,
+    /// Ports to pick the kernel's TCP ports from; any free ports if None
+    pub port_range: Option<RangeInclusive<u16>>,
+    /// Environment overrides for the kernel process; an empty value removes
+    /// the variable instead of setting it
+    pub env: BTreeMap<String, String>,
+    /// Working directory for the kernel process
+    pub cwd: Option<PathBuf>,
+    /// Run the kernel in a fresh scratch directory, created at launch and
+    /// removed with the kernel; takes precedence over `cwd`
+    pub temp_cwd: bool,
+    /// Snippets to use instead of detecting them from language_info
+    pub snippets: Option<LanguageSnippets>,
+    /// Snippet fields to replace once the kernel's language is known
+    pub snippet_overrides: SnippetOverrides,
+    /// Thresholds for benchmark tests
+    pub benchmark_config: BenchmarkConfig,
+    /// HMAC key written to the connection file; a fresh random key if None.
+    /// `Some("")` disables message signing.
+    pub signature_key: Option<String>,
+    /// How long to wait for the process to exit after shutdown_request
+    /// before killing it
+    pub shutdown_grace: Duration,
+    /// Pipe the kernel's stdout/stderr into the harness, for failure
+    /// excerpts and `log_dir`; if false the kernel inherits the harness's
+    pub capture_output: bool,
+    /// Directory to keep the kernel's stdout/stderr logs in after the run
+    pub log_dir: Option<PathBuf>,
+    /// Directory to save the messages observed during the run in, for replay
+    pub transcript_dir: Option<PathBuf>,
+    /// Sample the kernel process's memory and CPU use during the run
+    pub measure_resources: bool,
+    /// When attached to a kernel we didn't start, whether shutdown tests may
+    /// shut it down (launched kernels are always shut down)
+    pub allow_shutdown: bool,
+    /// How many times to re-run a test that fails or times out
+    pub retries: u32,
+    /// Time after which the suite stops starting new tests; no limit if None
+    pub suite_timeout: Option<Duration>,
+    /// How many times the suite executes `complete_code` after each launch,
+    /// before any test is timed
+    pub warmup: u32,
+}
+
+impl Default for KernelLaunchOptions {
+    fn default() -> Self {
+        Self {
+            test_timeout: DEFAULT_TEST_TIMEOUT,
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            iopub_welcome_timeout: DEFAULT_IOPUB_WELCOME_TIMEOUT,
+            transport: Transport::TCP,
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port_range: None,
+            env: BTreeMap::new(),
+            cwd: None,
+            temp_cwd: false,
+            snippets: None,
+            snippet_overrides: SnippetOverrides::default(),
+            benchmark_config: BenchmarkConfig::default(),
+            signature_key: None,
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+            capture_output: true,
+            log_dir: None,
+            transcript_dir: None,
+            measure_resources: false,
+            allow_shutdown: false,
+            retries: 0,
+            suite_timeout: None,
+            warmup: 0,
+        }
+    }
+}
+
+impl KernelLaunchOptions {
+    /// Build the connection info written to the kernel's connection file.
+    ///
+    /// For IPC, `ports` are socket suffixes and the address is a path prefix
+    /// under `runtime_dir`.
+    pub(crate) fn connection_info(
+        &self,
+        kernel_name: &str,
+        session_id: &str,
+        ports: &[u16],
+        runtime_dir: &Path,
+        key: String,
+    ) -> ConnectionInfo {
+        let ip = match self.transport {
+            Transport::TCP => self.ip.to_string(),
+            Transport::IPC => runtime_dir
+                .join(format!("kernel-test-{}-ipc", session_id))
+                .to_string_lossy()
+                .to_string(),
+        };
+
+        ConnectionInfo {
+            transport: self.transport.clone(),
+            ip,
+            stdin_port: ports[0],
+            control_port: ports[1],
+            hb_port: ports[2],
+            shell_port: ports[3],
+            iopub_port: ports[4],
+            signature_scheme: "hmac-sha256".to_string(),
+            key,
+            kernel_name: Some(kernel_name.to_string()),
+        }
+    }
+
+    /// Pick free TCP ports for a kernel, none of them in `avoid`.
+    ///
+    /// Without a `port_range` the OS hands out fresh ephemeral ports each
+    /// time, so `avoid` only constrains scanning a range.
+    pub(crate) async fn allocate_ports(&self, avoid: &HashSet<u16>) -> Result<Vec<u16>> {
+        let Some(range) = &self.port_range else {
+            return Ok(peek_ports(self.ip, KERNEL_PORT_COUNT).await?);
+        };
+        let ports: Vec<u16> = range
+            .clone()
+            .filter(|port| !avoid.contains(port))
+            .filter(|&port| TcpListener::bind((self.ip, port)).is_ok())
+            .take(KERNEL_PORT_COUNT)
+            .collect();
+        if ports.len() < KERNEL_PORT_COUNT {
+            return Err(HarnessError::LaunchFailed(format!(
+                "fewer than {} free ports in {}-{}",
+                KERNEL_PORT_COUNT,
+                range.start(),
+                range.end()
+            )));
+        }
+        Ok(ports)
+    }
+
+    /// Apply process-level options to the kernel command.
+    pub(crate) fn apply_to_command(&self, command: &mut tokio::process::Command) {
+        for (key, value) in &self.env {
+            if value.is_empty() {
+                command.env_remove(key);
+            } else {
+                command.env(key, value);
+            }
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+    }
+}
+
+/// Fail early, with a clear error, for a transport this platform can't use.
+///
+/// ZeroMQ's ipc:// endpoints are Unix domain sockets.
+pub(crate) fn check_transport(transport: &Transport) -> Result<()> {
+    if *transport == Transport::IPC && cfg!(windows) {
+        return Err(HarnessError::UnsupportedTransport(transport.to_string()));
+    }
+    Ok(())
+}
+
+/// Socket files a kernel creates when bound over IPC; none for TCP.
+///
+/// Kernels follow jupyter_client's convention of `<ip>-<port>` for each
+/// channel.
+pub(crate) fn ipc_socket_paths(connection_info: &ConnectionInfo) -> Vec<PathBuf> {
+    if connection_info.transport != Transport::IPC {
+        return Vec::new();
+    }
+    [
+        connection_info.stdin_port,
+        connection_info.control_port,
+        connection_info.hb_port,
+        connection_info.shell_port,
+        connection_info.iopub_port,
+    ]
+    .iter()
+    .map(|port| PathBuf::from(format!("{}-{}", connection_info.ip, port)))
+    .collect()
+}
+
+/// A kernel's scratch working directory under the system temp dir, removed
+/// with everything in it when dropped.
+#[derive(Debug)]
+pub(crate) struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub(crate) fn create(session_id: &str) -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("kernel-test-cwd-{}", session_id));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Placeholder replaced with the connection file path.
+const CONNECTION_FILE_PLACEHOLDER: &str = "connection_file";
+
+/// Placeholder replaced with the kernelspec directory.
+const RESOURCE_DIR_PLACEHOLDER: &str = "resource_dir";
+
+/// Split a command line into words the way a POSIX shell would, without
+/// expansions: whitespace separates words, single quotes keep text as is,
+/// and a backslash escapes the next character (inside double quotes, only
+/// `"` and `\`).
+pub fn split_command_line(command: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Parse a port range written `START-END`, inclusive, with room for all of
+/// a kernel's ports.
+pub fn parse_port_range(range: &str) -> std::result::Result<RangeInclusive<u16>, String> {
+    let parse = |port: &str| {
+        port.trim()
+            .parse::<u16>()
+            .map_err(|e| format!("invalid port '{}': {}", port, e))
+    };
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, got '{}'", range))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start == 0 || start > end {
+        return Err(format!("expected 1 <= START <= END, got '{}'", range));
+    }
+    let count = usize::from(end - start) + 1;
+    if count < KERNEL_PORT_COUNT {
+        return Err(format!(
+            "{} has {} ports, a kernel needs {}",
+            range, count, KERNEL_PORT_COUNT
+        ));
+    }
+    Ok(start..=end)
+}
+
+/// How the kernel process was actually started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaunchInfo {
+    /// Kernelspec argv after placeholder substitution
+    pub argv: Vec<String>,
+    /// Values substituted into argv, keyed by placeholder name
+    pub substitutions: BTreeMap<String, String>,
+}
+
+impl LaunchInfo {
+    /// Substitute `{connection_file}` and `{resource_dir}` into the kernelspec argv.
+    ///
+    /// runtimelib only replaces an argv entry that is exactly
+    /// `{connection_file}`, so kernels relying on `{resource_dir}` (common
+    /// for xeus-based kernels) would otherwise die at startup. Any other
+    /// placeholder is an error rather than a doomed process.
+    pub(crate) fn resolve(kernelspec: &KernelspecDir, connection_path: &Path) -> Result<Self> {
+        let substitutions = BTreeMap::from([
+            (
+                CONNECTION_FILE_PLACEHOLDER.to_string(),
+                connection_path.to_string_lossy().to_string(),
+            ),
+            (
+                RESOURCE_DIR_PLACEHOLDER.to_string(),
+                kernelspec.path.to_string_lossy().to_string(),
+            ),
+        ]);
+
+        let mut unknown: Vec<String> = kernelspec
+            .kernelspec
+            .argv
+            .iter()
+            .flat_map(|arg| placeholders(arg))
+            .filter(|name| !substitutions.contains_key(name))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(HarnessError::UnknownPlaceholders(
+                unknown.iter().map(|name| format!("{{{}}}", name)).collect(),
+            ));
+        }
+
+        let argv = kernelspec
+            .kernelspec
+            .argv
+            .iter()
+            .map(|arg| {
+                substitutions.iter().fold(arg.clone(), |arg, (name, value)| {
+                    arg.replace(&format!("{{{}}}", name), value)
+                })
+            })
+            .collect();
+
+        // Only report substitutions that were actually used
+        let substitutions = substitutions
+            .into_iter()
+            .filter(|(name, _)| {
+                let placeholder = format!("{{{}}}", name);
+                kernelspec
+                    .kernelspec
+                    .argv
+                    .iter()
+                    .any(|arg| arg.contains(&placeholder))
+            })
+            .collect();
+
+        Ok(Self {
+            argv,
+            substitutions,
+        })
+    }
+}
+
+/// Names of `{placeholder}` tokens in a single argument.
+fn placeholders(arg: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            names.push(name.to_string());
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
+
+/// What a [`KernelUnderTestBuilder`] connects to.
+#[derive(Debug, Clone)]
+enum KernelTarget {
+    /// Spawn a new process from a kernelspec
+    Kernelspec(KernelspecDir),
+    /// Attach to a kernel that is already running
+    Attach(ConnectionInfo),
+    /// Re-run tests against a recorded transcript
+    Replay(Arc<Transcript>),
+    /// Start a kernel from a kernelspec through a Jupyter Server
+    Server {
+        server: JupyterServer,
+        kernel_name: String,
+    },
+}
+
+/// Builder for [`KernelUnderTest`], returned by [`KernelUnderTest::builder`],
+/// [`KernelUnderTest::command_builder`], [`KernelUnderTest::attach_builder`],
+/// [`KernelUnderTest::connection_file_builder`] and
+/// [`KernelUnderTest::server_builder`].
+#[derive(Debug, Clone)]
+pub struct KernelUnderTestBuilder {
+    target: KernelTarget,
+    options: KernelLaunchOptions,
+}
+
+impl KernelUnderTestBuilder {
+    pub(crate) fn new(kernelspec: KernelspecDir) -> Self {
+        Self {
+            target: KernelTarget::Kernelspec(kernelspec),
+            options: KernelLaunchOptions::default(),
+        }
+    }
+
+    pub(crate) fn command(command: &str, kernel_name: Option<&str>) -> Result<Self> {
+        let argv = split_command_line(command).map_err(HarnessError::InvalidCommand)?;
+        let Some(program) = argv.first() else {
+            return Err(HarnessError::InvalidCommand("empty command".to_string()));
+        };
+        let placeholder = format!("{{{}}}", CONNECTION_FILE_PLACEHOLDER);
+        if !argv.iter().any(|arg| arg.contains(&placeholder)) {
+            return Err(HarnessError::InvalidCommand(format!(
+                "{} must appear in the command",
+                placeholder
+            )));
+        }
+        let kernel_name = match kernel_name {
+            Some(name) => name.to_string(),
+            None => Path::new(program).file_name().map_or_else(
+                || program.clone(),
+                |name| name.to_string_lossy().to_string(),
+            ),
+        };
+
+        // A stand-in kernelspec so the command launches (and relaunches)
+        // exactly like an installed kernel; {resource_dir} is the cwd
+        let kernelspec = serde_json::from_value(serde_json::json!({
+            "argv": argv,
+            "display_name": kernel_name,
+            "language": "unknown",
+        }))
+        .map_err(|e| HarnessError::InvalidCommand(e.to_string()))?;
+        Ok(Self::new(KernelspecDir {
+            kernel_name,
+            path: std::env::current_dir()?,
+            kernelspec,
+        }))
+    }
+
+    pub(crate) fn attach_to(connection_info: ConnectionInfo) -> Self {
+        Self {
+            target: KernelTarget::Attach(connection_info),
+            options: KernelLaunchOptions::default(),
+        }
+    }
+
+    pub(crate) fn replay(transcript: Arc<Transcript>) -> Self {
+        Self {
+            target: KernelTarget::Replay(transcript),
+            options: KernelLaunchOptions::default(),
+        }
+    }
+
+    pub(crate) fn server(server: JupyterServer, kernel_name: &str) -> Self {
+        Self {
+            target: KernelTarget::Server {
+                server,
+                kernel_name: kernel_name.to_string(),
+            },
+            options: KernelLaunchOptions::default(),
+        }
+    }
+
+    pub(crate) fn connection_file(path: &Path) -> Result<Self> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            HarnessError::InvalidConnectionFile(format!("{}: {}", path.display(), e))
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let connection_info = serde_json::from_str(&content).map_err(|e| invalid(&e))?;
+        Ok(Self::attach_to(connection_info))
+    }
+
+    /// The kernelspec that will be launched, or None when attaching,
+    /// replaying or starting the kernel through a Jupyter Server.
+    pub fn kernelspec(&self) -> Option<&KernelspecDir> {
+        match &self.target {
+            KernelTarget::Kernelspec(kernelspec) => Some(kernelspec),
+            KernelTarget::Attach(_) | KernelTarget::Replay(_) | KernelTarget::Server { .. } => None,
+        }
+    }
+
+    /// Whether this builder attaches to a running kernel instead of
+    /// launching one.
+    pub fn is_attach(&self) -> bool {
+        matches!(self.target, KernelTarget::Attach(_))
+    }
+
+    /// Name to report the kernel under: the kernelspec name, or for an
+    /// attached kernel the connection file's kernel_name if it has one.
+    pub fn kernel_name(&self) -> String {
+        match &self.target {
+            KernelTarget::Kernelspec(kernelspec) => kernelspec.kernel_name.clone(),
+            KernelTarget::Attach(connection_info) => connection_info
+                .kernel_name
+                .clone()
+                .unwrap_or_else(|| "attached".to_string()),
+            KernelTarget::Replay(transcript) => transcript.kernel_name.clone(),
+            KernelTarget::Server { kernel_name, .. } => kernel_name.clone(),
+        }
+    }
+
+    /// The options collected so far.
+    pub fn options(&self) -> &KernelLaunchOptions {
+        &self.options
+    }
+
+    /// Replace all options at once.
+    pub fn with_options(mut self, options: KernelLaunchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the per-test timeout.
+    pub fn test_timeout(mut self, timeout: Duration) -> Self {
+        self.options.test_timeout = timeout;
+        self
+    }
+
+    /// Set the per-test timeout; the same as [`test_timeout`](Self::test_timeout).
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.test_timeout(timeout)
+    }
+
+    /// Set how long the kernel gets to become ready after spawning.
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.options.startup_timeout = timeout;
+        self
+    }
+
+    /// Set how long to wait for iopub_welcome once the kernel is ready.
+    pub fn iopub_welcome_timeout(mut self, timeout: Duration) -> Self {
+        self.options.iopub_welcome_timeout = timeout;
+        self
+    }
+
+    /// Set the ZeroMQ transport.
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.options.transport = transport;
+        self
+    }
+
+    /// Set the address the kernel binds to (TCP only).
+    pub fn ip(mut self, ip: IpAddr) -> Self {
+        self.options.ip = ip;
+        self
+    }
+
+    /// Pick the kernel's TCP ports from `range`, e.g. ports a firewall lets
+    /// through.
+    pub fn port_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.options.port_range = Some(range);
+        self
+    }
+
+    /// Add an environment variable for the kernel process (an empty value
+    /// unsets an inherited one).
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the working directory for the kernel process.
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Run each launched kernel in its own scratch directory.
+    pub fn temp_cwd(mut self, temp: bool) -> Self {
+        self.options.temp_cwd = temp;
+        self
+    }
+
+    /// Use these snippets instead of detecting them from language_info.
+    pub fn snippets(mut self, snippets: LanguageSnippets) -> Self {
+        self.options.snippets = Some(snippets);
+        self
+    }
+
+    /// Replace individual snippet fields for the kernel's language or name.
+    pub fn snippet_overrides(mut self, overrides: SnippetOverrides) -> Self {
+        self.options.snippet_overrides = overrides;
+        self
+    }
+
+    /// Set thresholds for benchmark tests.
+    pub fn benchmark_config(mut self, config: BenchmarkConfig) -> Self {
+        self.options.benchmark_config = config;
+        self
+    }
+
+    /// Set the HMAC key for the connection file (empty disables signing).
+    pub fn signature_key(mut self, key: impl Into<String>) -> Self {
+        self.options.signature_key = Some(key.into());
+        self
+    }
+
+    /// Set how long the kernel gets to exit on its own after shutdown.
+    pub fn shutdown_grace(mut self, grace: Duration) -> Self {
+        self.options.shutdown_grace = grace;
+        self
+    }
+
+    /// Capture the kernel's stdout/stderr (the default), or let it write
+    /// straight to the harness's own.
+    pub fn capture_kernel_output(mut self, capture: bool) -> Self {
+        self.options.capture_output = capture;
+        self
+    }
+
+    /// Append the kernel's stdout/stderr to log files in `dir`.
+    pub fn log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Save the suite's observed messages as a transcript in `dir`.
+    pub fn transcript_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.transcript_dir = Some(dir.into());
+        self
+    }
+
+    /// Sample the kernel's memory and CPU use while the suite runs.
+    pub fn measure_resources(mut self, measure: bool) -> Self {
+        self.options.measure_resources = measure;
+        self
+    }
+
+    /// Allow shutdown tests to shut down an attached kernel.
+    pub fn allow_shutdown(mut self, allow: bool) -> Self {
+        self.options.allow_shutdown = allow;
+        self
+    }
+
+    /// Re-run a test that fails or times out up to `retries` more times.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = retries;
+        self
+    }
+
+    /// Stop starting new tests once the suite has run for `timeout`.
+    pub fn suite_timeout(mut self, timeout: Duration) -> Self {
+        self.options.suite_timeout = Some(timeout);
+        self
+    }
+
+    /// Execute `complete_code` `runs` times after each launch, untimed by
+    /// any test.
+    pub fn warmup(mut self, runs: u32) -> Self {
+        self.options.warmup = runs;
+        self
+    }
+
+    /// Launch the kernel (or attach to it) and establish all connections.
+    pub async fn launch(self) -> Result<KernelUnderTest> {
+        match self.target {
+            KernelTarget::Kernelspec(kernelspec) => {
+                KernelUnderTest::launch_with_options(kernelspec, self.options).await
+            }
+            KernelTarget::Attach(connection_info) => {
+                KernelUnderTest::attach(connection_info, self.options).await
+            }
+            KernelTarget::Replay(transcript) => Ok(KernelUnderTest::replaying(transcript)),
+            KernelTarget::Server {
+                server,
+                kernel_name,
+            } => KernelUnderTest::start_on_server(server, &kernel_name, self.options).await,
+        }
+    }
+
+    /// Attach to the running kernel this builder was made for.
+    ///
+    /// Unlike [`launch`](Self::launch), this never spawns anything: a
+    /// builder for a kernelspec or command is an error.
+    pub async fn attach(self) -> Result<KernelUnderTest> {
+        match self.target {
+            KernelTarget::Attach(connection_info) => {
+                KernelUnderTest::attach(connection_info, self.options).await
+            }
+            KernelTarget::Kernelspec(kernelspec) => Err(HarnessError::ConnectionFailed(format!(
+                "{} is launched from a kernelspec, not attached to",
+                kernelspec.kernel_name
+            ))),
+            KernelTarget::Replay(transcript) => Err(HarnessError::ConnectionFailed(format!(
+                "{} is replayed from a transcript, not attached to",
+                transcript.kernel_name
+            ))),
+            KernelTarget::Server { kernel_name, .. } => {
+                Err(HarnessError::ConnectionFailed(format!(
+                    "{} is started through a Jupyter Server, not attached to",
+                    kernel_name
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_legacy_launch() {
+        let options = KernelLaunchOptions::default();
+        assert_eq!(options.test_timeout, Duration::from_millis(10000));
+        assert_eq!(options.startup_timeout, Duration::from_secs(30));
+        assert_eq!(options.iopub_welcome_timeout, Duration::from_millis(500));
+        assert_eq!(options.transport, Transport::TCP);
+        assert_eq!(options.ip, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        assert!(options.port_range.is_none());
+        assert!(options.env.is_empty());
+        assert!(options.cwd.is_none());
+        assert!(!options.temp_cwd);
+        assert!(options.snippets.is_none());
+        assert_eq!(options.snippet_overrides, SnippetOverrides::default());
+        assert!(options.signature_key.is_none());
+        assert_eq!(options.shutdown_grace, Duration::from_secs(5));
+        assert!(options.capture_output);
+        assert!(options.log_dir.is_none());
+        assert!(options.transcript_dir.is_none());
+        assert!(!options.measure_resources);
+        assert!(!options.allow_shutdown);
+        assert_eq!(options.retries, 0);
+        assert!(options.suite_timeout.is_none());
+        assert_eq!(options.warmup, 0);
+    }
+
+    #[test]
+    fn test_connection_info_uses_transport() {
+        let ports = [1, 2, 3, 4, 5];
+        let runtime_dir = Path::new("/tmp/runtime");
+
+        let tcp = KernelLaunchOptions::default().connection_info(
+            "python3",
+            "abc",
+            &ports,
+            runtime_dir,
+            "key".to_string(),
+        );
+        assert_eq!(tcp.transport, Transport::TCP);
+        assert_eq!(tcp.ip, "127.0.0.1");
+        assert_eq!(tcp.shell_port, 4);
+        assert_eq!(tcp.signature_scheme, "hmac-sha256");
+
+        let options = KernelLaunchOptions {
+            transport: Transport::IPC,
+            ..Default::default()
+        };
+        let ipc = options.connection_info("python3", "abc", &ports, runtime_dir, "key".to_string());
+        assert_eq!(ipc.transport, Transport::IPC);
+        assert_eq!(ipc.ip, "/tmp/runtime/kernel-test-abc-ipc");
+
+        assert!(ipc_socket_paths(&tcp).is_empty());
+        let sockets = ipc_socket_paths(&ipc);
+        assert_eq!(sockets.len(), 5);
+        assert!(sockets.contains(&PathBuf::from("/tmp/runtime/kernel-test-abc-ipc-4")));
+    }
+
+    #[test]
+    fn test_attach_builder_names_kernel() {
+        let mut connection_info = KernelLaunchOptions::default().connection_info(
+            "python3",
+            "abc",
+            &[1, 2, 3, 4, 5],
+            Path::new("/tmp/runtime"),
+            "key".to_string(),
+        );
+        let builder = KernelUnderTestBuilder::attach_to(connection_info.clone());
+        assert!(builder.is_attach());
+        assert!(builder.kernelspec().is_none());
+        assert_eq!(builder.kernel_name(), "python3");
+
+        connection_info.kernel_name = None;
+        let builder = KernelUnderTestBuilder::attach_to(connection_info);
+        assert_eq!(builder.kernel_name(), "attached");
+    }
+
+    #[test]
+    fn test_connection_file_builder() {
+        let connection_info = KernelLaunchOptions::default().connection_info(
+            "python3",
+            "abc",
+            &[1, 2, 3, 4, 5],
+            Path::new("/tmp/runtime"),
+            "key".to_string(),
+        );
+        let path = std::env::temp_dir().join(format!(
+            "kernel-testbed-connection-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, serde_json::to_string(&connection_info).unwrap()).unwrap();
+
+        let builder = KernelUnderTestBuilder::connection_file(&path).unwrap();
+        assert!(builder.is_attach());
+        assert_eq!(builder.kernel_name(), "python3");
+
+        std::fs::write(&path, "{}").unwrap();
+        let err = KernelUnderTestBuilder::connection_file(&path).unwrap_err();
+        assert!(matches!(err, HarnessError::InvalidConnectionFile(_)));
+
+        std::fs::remove_file(&path).unwrap();
+        let err = KernelUnderTestBuilder::connection_file(&path).unwrap_err();
+        assert!(matches!(err, HarnessError::InvalidConnectionFile(_)));
+    }
+
+    #[test]
+    fn test_env_and_cwd_reach_command() {
+        let options = KernelLaunchOptions {
+            env: BTreeMap::from([("TESTBED_FLAG".to_string(), "1".to_string())]),
+            cwd: Some(PathBuf::from("/tmp/work")),
+            ..Default::default()
+        };
+
+        let mut command = tokio::process::Command::new("true");
+        options.apply_to_command(&mut command);
+
+        let std_command = command.as_std();
+        let envs: Vec<_> = std_command.get_envs().collect();
+        assert!(envs.contains(&(
+            std::ffi::OsStr::new("TESTBED_FLAG"),
+            Some(std::ffi::OsStr::new("1"))
+        )));
+        assert_eq!(std_command.get_current_dir(), Some(Path::new("/tmp/work")));
+    }
+
+    #[test]
+    fn test_scratch_dir_removed_on_drop() {
+        let scratch = ScratchDir::create(&uuid::Uuid::new_v4().to_string()).unwrap();
+        let path = scratch.path().to_path_buf();
+        std::fs::write(path.join("history.sqlite"), "").unwrap();
+        assert!(path.is_dir());
+
+        drop(scratch);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_empty_env_value_unsets() {
+        let options = KernelLaunchOptions {
+            env: BTreeMap::from([("PATH".to_string(), String::new())]),
+            ..Default::default()
+        };
+
+        let mut command = tokio::process::Command::new("true");
+        options.apply_to_command(&mut command);
+
+        let envs: Vec<_> = command.as_std().get_envs().collect();
+        assert_eq!(envs, vec![(std::ffi::OsStr::new("PATH"), None)]);
+    }
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(
+            split_command_line(r#"  ./target/debug/kernel --connection {connection_file} "#)
+                .unwrap(),
+            vec!["./target/debug/kernel", "--connection", "{connection_file}"]
+        );
+        assert_eq!(
+            split_command_line(r#"'/opt/my kernel/bin' -c "print(\"hi\")" a\ b '' x"#).unwrap(),
+            vec!["/opt/my kernel/bin", "-c", r#"print("hi")"#, "a b", "", "x"]
+        );
+        assert!(split_command_line("kernel 'open").is_err());
+        assert!(split_command_line("kernel \"open").is_err());
+        assert!(split_command_line("kernel \\").is_err());
+    }
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("9000-9099"), Ok(9000..=9099));
+        assert_eq!(parse_port_range("9000-9004"), Ok(9000..=9004));
+        assert!(parse_port_range("9000-9003").is_err());
+        assert!(parse_port_range("9099-9000").is_err());
+        assert!(parse_port_range("0-100").is_err());
+        assert!(parse_port_range("9000").is_err());
+        assert!(parse_port_range("9000-70000").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allocate_ports_within_range_avoiding_tried_ones() {
+        // Hold one port so the scan has to step over a busy one
+        let busy = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let start = busy.local_addr().unwrap().port().saturating_sub(1).max(1);
+        let range = start..=start.saturating_add(40);
+        let options = KernelLaunchOptions {
+            port_range: Some(range.clone()),
+            ..KernelLaunchOptions::default()
+        };
+
+        let first = options.allocate_ports(&HashSet::new()).await.unwrap();
+        assert_eq!(first.len(), KERNEL_PORT_COUNT);
+        assert!(first.iter().all(|port| range.contains(port)));
+        assert!(!first.contains(&busy.local_addr().unwrap().port()));
+
+        let tried: HashSet<u16> = first.iter().copied().collect();
+        let second = options.allocate_ports(&tried).await.unwrap();
+        assert!(second.iter().all(|port| !tried.contains(port)));
+
+        let narrow = KernelLaunchOptions {
+            port_range: Some(start..=start.saturating_add(4)),
+            ..KernelLaunchOptions::default()
+        };
+        assert!(narrow.allocate_ports(&tried).await.is_err());
+    }
+
+    #[test]
+    fn test_command_builder_names_kernel() {
+        let builder =
+            KernelUnderTestBuilder::command("./build/xkernel -f {connection_file}", None).unwrap();
+        assert_eq!(builder.kernel_name(), "xkernel");
+        let kernelspec = builder.kernelspec().unwrap();
+        assert_eq!(
+            kernelspec.kernelspec.argv,
+            ["./build/xkernel", "-f", "{connection_file}"]
+        );
+
+        let builder =
+            KernelUnderTestBuilder::command("xkernel --connection={connection_file}", Some("dev"))
+                .unwrap();
+        assert_eq!(builder.kernel_name(), "dev");
+
+        assert!(matches!(
+            KernelUnderTestBuilder::command("xkernel -f conn.json", None),
+            Err(HarnessError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            KernelUnderTestBuilder::command("  ", None),
+            Err(HarnessError::InvalidCommand(_))
+        ));
+    }
+
+    fn fixture_kernelspec(name: &str) -> KernelspecDir {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/kernelspecs")
+            .join(name);
+        let content = std::fs::read_to_string(path.join("kernel.json")).unwrap();
+        KernelspecDir {
+            kernel_name: name.to_string(),
+            path,
+            kernelspec: serde_json::from_str(&content).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_resource_dir_substituted() {
+        let kernelspec = fixture_kernelspec("resource-dir");
+        let resource_dir = kernelspec.path.to_string_lossy().to_string();
+        let connection_path = Path::new("/tmp/runtime/kernel-test-abc.json");
+
+        let info = LaunchInfo::resolve(&kernelspec, connection_path).unwrap();
+        assert_eq!(
+            info.argv,
+            vec![
+                "xtestbed".to_string(),
+                "-f".to_string(),
+                "/tmp/runtime/kernel-test-abc.json".to_string(),
+                "--resources".to_string(),
+                resource_dir.clone(),
+                format!("--config={}/config.json", resource_dir),
+            ]
+        );
+        assert_eq!(info.substitutions["resource_dir"], resource_dir);
+        assert_eq!(
+            info.substitutions["connection_file"],
+            "/tmp/runtime/kernel-test-abc.json"
+        );
+    }
+
+    #[test]
+    fn test_unknown_placeholders_rejected() {
+        let kernelspec = fixture_kernelspec("unknown-placeholder");
+        let err = LaunchInfo::resolve(&kernelspec, Path::new("/tmp/k.json")).unwrap_err();
+        match err {
+            HarnessError::UnknownPlaceholders(names) => {
+                assert_eq!(names, vec!["{kernel_id}", "{prefix}"]);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_placeholder_scan_ignores_non_identifiers() {
+        assert_eq!(placeholders("{connection_file}"), vec!["connection_file"]);
+        assert_eq!(placeholders("--opt={a}{b}"), vec!["a", "b"]);
+        assert!(placeholders("{\"json\": 1}").is_empty());
+        assert!(placeholders("{unterminated").is_empty());
+    }
+}
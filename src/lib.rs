@@ -17,16 +17,72 @@
 //!
 //! # Output as JSON
 //! jupyter-kernel-test python3 --format json
+//!
+//! # Test a kernel you started yourself (e.g. in a container)
+//! jupyter-kernel-test --connection-file ./kernel-1234.json
+//!
+//! # Test a kernel a Jupyter Server starts, over its websocket (no heartbeat)
+//! jupyter-kernel-test python3 --server http://localhost:8888 --token "$JUPYTER_TOKEN"
+//!
+//! # Test a kernel straight from its build tree, without a kernelspec
+//! jupyter-kernel-test --kernel-cmd "./target/debug/my-kernel --connection {connection_file}"
+//!
+//! # Run additional user-supplied checks
+//! jupyter-kernel-test python3 --extra-tests ./kernel-checks
+//!
+//! # GitHub Actions defaults (JSON artifact, step summary, annotations, strict)
+//! jupyter-kernel-test python3 --ci
+//!
+//! # Include opt-in latency benchmarks
+//! jupyter-kernel-test python3 --benchmarks --config testbed.json
+//!
+//! # Save each kernel's messages, then re-run its tests later without a kernel
+//! jupyter-kernel-test python3 --transcript-dir ./transcripts
+//! jupyter-kernel-test replay ./transcripts/python3.transcript.json
+//!
+//! # Render the test registry as a markdown reference
+//! jupyter-kernel-test docs --output TESTS.md
 //! ```
 
+pub mod artifacts;
+pub mod channels;
+pub mod config;
+pub mod docs;
+pub mod extra_tests;
 pub mod harness;
+pub mod iopub;
+pub mod kernel_log;
+pub mod launch;
 pub mod report;
+pub mod resources;
+pub mod server;
+pub mod settings;
 pub mod snippets;
 pub mod tests;
+pub mod transcript;
 pub mod types;
 
-pub use harness::{run_conformance_suite, ConformanceTest, KernelUnderTest};
-pub use report::{render_json, render_markdown, render_matrix_json, render_matrix_markdown, render_terminal};
-pub use snippets::LanguageSnippets;
-pub use tests::all_tests;
-pub use types::{ConformanceMatrix, FailureKind, KernelReport, TestCategory, TestRecord, TestResult};
+pub use artifacts::{Artifact, ArtifactsSummary};
+pub use channels::{Channel, HeartbeatChannel, MessageChannel};
+pub use config::{load_config, BenchmarkConfig, ConfigError, KernelConfig, SuiteConfig};
+pub use docs::render_suite_docs;
+pub use extra_tests::{load_extra_tests, ExtraTest, ExtraTestError};
+pub use harness::{
+    replay_suite, run_conformance_suite, ConformanceTest, IsolationOptions, KernelUnderTest,
+    SuiteFinalizer, SuiteInterrupt, SuiteKernel, TestFn, TestFuture, TestRegistry,
+};
+pub use launch::{KernelLaunchOptions, KernelUnderTestBuilder, LaunchInfo};
+pub use report::{
+    render_github_annotations, render_json, render_markdown, render_matrix_json,
+    render_matrix_markdown, render_terminal,
+};
+pub use server::JupyterServer;
+pub use snippets::{load_snippet_overrides, LanguageSnippets, SnippetOverrides, SnippetsError};
+pub use tests::{all_finalizers, all_tests};
+pub use transcript::{
+    ChannelEvent, Direction, Exchange, MessageSource, ReplayKernel, Transcript, TranscriptError,
+};
+pub use types::{
+    new_run_id, ConformanceMatrix, FailureKind, IsolationMode, IsolationReport, KernelCrash,
+    KernelExitStatus, KernelReport, ResourceUsage, TestCategory, TestRecord, TestResult, TierRequirement,
+};
@@ -1,18 +1,39 @@
 //! CLI for running Jupyter kernel conformance tests.
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use jupyter_protocol::connection_info::Transport;
+use jupyter_kernel_test::kernel_log::kernel_log_paths;
+use jupyter_kernel_test::launch::parse_port_range;
+use jupyter_kernel_test::resources::RESOURCES_SUPPORTED;
+use jupyter_kernel_test::settings::{EffectiveSettings, ExplicitSettings, OutputFormat};
+use jupyter_kernel_test::transcript::transcript_path;
 use jupyter_kernel_test::{
-    all_tests, render_json, render_markdown, render_matrix_json, render_matrix_markdown,
-    render_terminal, run_conformance_suite, ConformanceMatrix, TestCategory,
+    all_finalizers, all_tests, load_config, load_extra_tests, load_snippet_overrides, new_run_id,
+    render_github_annotations, render_json, render_markdown, render_matrix_json,
+    render_matrix_markdown, render_suite_docs, render_terminal, replay_suite,
+    run_conformance_suite, ArtifactsSummary, ConformanceMatrix, ExtraTest, IsolationMode,
+    IsolationOptions, JupyterServer, KernelReport, KernelUnderTest, ReplayKernel, SnippetOverrides,
+    SuiteConfig, SuiteInterrupt, TestCategory, TestRegistry, TestResult, TierRequirement,
 };
-use std::path::PathBuf;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Exit code after Ctrl-C, following the shell's 128 + SIGINT convention
+const EXIT_INTERRUPTED: i32 = 130;
 
 #[derive(Parser, Debug)]
 #[command(name = "jupyter-kernel-test")]
 #[command(about = "Jupyter kernel protocol conformance test suite")]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Kernel names to test (e.g., python3, ir, rust)
     #[arg(value_name = "KERNEL")]
     kernels: Vec<String>,
@@ -25,28 +46,188 @@ struct Args {
     #[arg(long = "tier", value_name = "N")]
     tiers: Vec<u8>,
 
-    /// Output format
-    #[arg(long, short, default_value = "terminal")]
-    format: OutputFormat,
+    /// Output format [default: terminal, or json with --ci]
+    #[arg(long, short)]
+    format: Option<OutputFormat>,
 
     /// Write output to file
     #[arg(long, short)]
     output: Option<PathBuf>,
 
+    /// CI preset: JSON report to conformance-report.json, markdown to
+    /// $GITHUB_STEP_SUMMARY, failure annotations, and strict exit codes.
+    /// Explicit flags override each part.
+    #[arg(long)]
+    ci: bool,
+
+    /// Append a markdown summary to $GITHUB_STEP_SUMMARY
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+    step_summary: Option<bool>,
+
+    /// Print GitHub workflow annotations for failed tests
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+    annotations: Option<bool>,
+
+    /// Exit non-zero if any test fails or times out
+    #[arg(long, num_args = 0..=1, default_missing_value = "true", value_name = "BOOL")]
+    strict: Option<bool>,
+
     /// Per-test timeout in milliseconds
     #[arg(long, default_value = "10000")]
     timeout: u64,
 
+    /// How long a kernel gets to answer kernel_info after launch, in milliseconds
+    #[arg(long, default_value = "30000")]
+    startup_timeout: u64,
+
+    /// How long a kernel gets to exit on its own after shutdown, in milliseconds
+    #[arg(long, default_value = "5000")]
+    shutdown_grace: u64,
+
+    /// ZeroMQ transport for launched kernels: tcp, or ipc (Unix sockets
+    /// under the Jupyter runtime dir)
+    #[arg(long, default_value = "tcp", value_parser = parse_transport)]
+    transport: Transport,
+
+    /// Pick launched kernels' TCP ports from START-END (inclusive), e.g. the
+    /// ports a CI firewall allows
+    #[arg(long, value_name = "START-END", value_parser = parse_port_range)]
+    port_range: Option<RangeInclusive<u16>>,
+
+    /// Attach to an already-running kernel through its connection file
+    /// instead of launching one (KERNEL arguments are ignored)
+    #[arg(long, value_name = "PATH")]
+    connection_file: Option<PathBuf>,
+
+    /// Launch this command instead of an installed kernelspec, e.g.
+    /// "./target/debug/my-kernel --connection {connection_file}"
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["kernels", "connection_file"])]
+    kernel_cmd: Option<String>,
+
+    /// Name to report a --kernel-cmd kernel under (default: the executable's
+    /// file name)
+    #[arg(long, value_name = "NAME", requires = "kernel_cmd")]
+    kernel_name: Option<String>,
+
+    /// Start kernels through the Jupyter Server at URL and test them over its
+    /// websocket (KERNEL names the server's kernelspecs; default: the
+    /// server's default kernel). There is no heartbeat channel to test
+    #[arg(long, value_name = "URL", conflicts_with_all = ["connection_file", "kernel_cmd"])]
+    server: Option<String>,
+
+    /// Token for --server
+    #[arg(long, value_name = "TOKEN", requires = "server")]
+    token: Option<String>,
+
+    /// With --connection-file, let shutdown tests shut the kernel down
+    #[arg(long, requires = "connection_file")]
+    allow_shutdown: bool,
+
+    /// Set an environment variable for the kernel, can be repeated.
+    /// `KEY=` removes an inherited variable.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_override)]
+    env: Vec<(String, String)>,
+
+    /// Working directory for launched kernels: a PATH, or `tempdir` for a
+    /// fresh scratch directory per kernel that is removed afterwards
+    #[arg(long, default_value = "tempdir", value_name = "PATH", value_parser = parse_cwd)]
+    cwd: KernelCwd,
+
+    /// Keep each kernel's stdout/stderr in DIR/<kernel>.stdout.log and
+    /// DIR/<kernel>.stderr.log
+    #[arg(long, value_name = "DIR")]
+    kernel_log_dir: Option<PathBuf>,
+
+    /// Save the messages observed while testing each kernel to
+    /// DIR/<kernel>.transcript.json, for the replay subcommand
+    #[arg(long, value_name = "DIR")]
+    transcript_dir: Option<PathBuf>,
+
+    /// Sample each kernel's memory and CPU use every 250ms and report
+    /// peak/final RSS and total CPU time
+    #[arg(long)]
+    measure_resources: bool,
+
+    /// Execute the language's trivial snippet N times after each launch,
+    /// before any test is timed, so JIT kernels are measured warm
+    #[arg(long, default_value = "1", value_name = "N")]
+    warmup: u32,
+
+    /// Stop starting new tests for a kernel after SECS seconds; the tests
+    /// left over are reported as skipped
+    #[arg(long, value_name = "SECS")]
+    suite_timeout: Option<u64>,
+
+    /// Re-run a test that fails or times out up to N more times; retried
+    /// results are marked with their attempt count
+    #[arg(long, default_value = "0", value_name = "N")]
+    retries: u32,
+
+    /// Number of kernels to test concurrently
+    #[arg(long, short, default_value = "1", value_name = "N")]
+    jobs: usize,
+
     /// Verbose output
     #[arg(long, short)]
     verbose: bool,
+
+    /// Directory of TOML, YAML or JSON test specs to run alongside the
+    /// built-in tests
+    #[arg(long, value_name = "DIR")]
+    extra_tests: Option<PathBuf>,
+
+    /// Identifier recorded in every report (defaults to a random UUID)
+    #[arg(long)]
+    run_id: Option<String>,
+
+    /// Also run opt-in latency benchmarks
+    #[arg(long)]
+    benchmarks: bool,
+
+    /// JSON config file (benchmark thresholds, per-kernel requirements)
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// JSON file of snippet fields to replace, keyed by language under
+    /// "languages" or by kernel name under "kernels"
+    #[arg(long, value_name = "PATH")]
+    snippets: Option<PathBuf>,
+
+    /// Minimum tier score as a percentage (e.g. tier1=100), can be repeated.
+    /// Exits non-zero if any kernel misses a requirement.
+    #[arg(long = "require", alias = "min-tier-pass", value_name = "TIER=PERCENT")]
+    require: Vec<TierRequirement>,
+
+    /// Fingerprint kernel state between tests and report which tests leak
+    /// state (diagnostic only, never affects pass/fail)
+    #[arg(long)]
+    check_isolation: bool,
+
+    /// When to relaunch the kernel: shared (one kernel for the run) or
+    /// per-tier (a fresh kernel for each tier)
+    #[arg(long, value_enum, default_value_t = IsolationMode::Shared)]
+    isolation: IsolationMode,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
-enum OutputFormat {
-    Terminal,
-    Json,
-    Markdown,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the test registry as a markdown reference and exit
+    Docs {
+        /// Write output to file
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Also document the test specs in this directory
+        #[arg(long, value_name = "DIR")]
+        extra_tests: Option<PathBuf>,
+    },
+    /// Re-run the tests against a recorded transcript without launching a
+    /// kernel. Tests that need more than the recorded messages are skipped
+    Replay {
+        /// Transcript written by --transcript-dir
+        #[arg(value_name = "TRANSCRIPT")]
+        transcript: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -59,8 +240,34 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if let Some(Command::Docs {
+        output,
+        extra_tests,
+    }) = &args.command
+    {
+        let extra_tests = extra_tests_or_exit(extra_tests.as_deref());
+        let docs = render_suite_docs(&all_tests(), &all_finalizers(), &extra_tests);
+        write_output(output.as_deref(), &docs)?;
+        return Ok(());
+    }
+
+    let settings = EffectiveSettings::resolve(
+        &ExplicitSettings {
+            ci: args.ci,
+            format: args.format,
+            output: args.output.clone(),
+            step_summary: args.step_summary,
+            annotations: args.annotations,
+            strict: args.strict,
+        },
+        |key| std::env::var(key).ok(),
+    );
+    if args.ci || args.verbose {
+        eprintln!("Effective settings: {}", settings);
+    }
+
     // Determine which tiers to run
-    let tiers: Vec<TestCategory> = if args.tiers.is_empty() {
+    let mut tiers: Vec<TestCategory> = if args.tiers.is_empty() {
         vec![
             TestCategory::Tier1Basic,
             TestCategory::Tier2Interactive,
@@ -70,15 +277,12 @@ async fn main() -> anyhow::Result<()> {
     } else {
         args.tiers
             .iter()
-            .filter_map(|&n| match n {
-                1 => Some(TestCategory::Tier1Basic),
-                2 => Some(TestCategory::Tier2Interactive),
-                3 => Some(TestCategory::Tier3RichOutput),
-                4 => Some(TestCategory::Tier4Advanced),
-                _ => {
+            .filter_map(|&n| {
+                let tier = TestCategory::from_tier_number(n);
+                if tier.is_none() {
                     eprintln!("Warning: invalid tier {}, ignoring", n);
-                    None
                 }
+                tier
             })
             .collect()
     };
@@ -88,8 +292,117 @@ async fn main() -> anyhow::Result<()> {
         std::process::exit(1);
     }
 
+    if args.benchmarks {
+        tiers.push(TestCategory::Benchmark);
+    }
+
+    if let Some(Command::Replay { transcript }) = &args.command {
+        let replay = match ReplayKernel::load(transcript) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("Error loading transcript: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let run_id = args.run_id.clone().unwrap_or_else(new_run_id);
+        let extra_tests = extra_tests_or_exit(args.extra_tests.as_deref());
+        let mut tests = TestRegistry::new();
+        tests.extend(all_tests());
+        let report = replay_suite(
+            &replay,
+            &tiers,
+            &tests,
+            &all_finalizers(),
+            &extra_tests,
+            &run_id,
+        )
+        .await;
+        let output = match settings.format {
+            OutputFormat::Terminal => render_terminal(&report),
+            OutputFormat::Json => render_json(&report),
+            OutputFormat::Markdown => render_markdown(&report),
+        };
+        write_output(settings.output.as_deref(), &output)?;
+
+        if settings.strict && report.results.iter().any(|r| r.result.is_failure()) {
+            eprintln!("Strict mode: one or more tests failed");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let config = match &args.config {
+        Some(path) => match load_config(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error loading config: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => SuiteConfig::default(),
+    };
+
+    let snippet_overrides = match &args.snippets {
+        Some(path) => match load_snippet_overrides(path) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                eprintln!("Error loading snippets: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => SnippetOverrides::default(),
+    };
+
+    // Validate extra test specs before launching anything
+    let extra_tests = extra_tests_or_exit(args.extra_tests.as_deref());
+
+    if args.measure_resources && !RESOURCES_SUPPORTED {
+        eprintln!("Error: --measure-resources is not supported on this platform");
+        std::process::exit(1);
+    }
+
+    // An existing kernel stands in for kernelspec lookup entirely
+    let attach = args.connection_file.as_deref().map(|path| {
+        let builder = KernelUnderTest::connection_file_builder(path);
+        builder.unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    // So does a kernel command line
+    let command = args.kernel_cmd.as_deref().map(|command| {
+        let kernel_name = args.kernel_name.as_deref();
+        match KernelUnderTest::command_builder(command, kernel_name) {
+            Ok(builder) => builder,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    // A server resolves kernel names against its own kernelspecs
+    let server = args.server.as_deref().map(|url| {
+        JupyterServer::new(url, args.token.as_deref()).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+
     // Get kernels to test
-    let kernel_names = if args.kernels.is_empty() {
+    let kernel_names = if attach.is_some() || command.is_some() {
+        Vec::new()
+    } else if let Some(server) = server.as_ref().filter(|_| args.kernels.is_empty()) {
+        // Default to the server's default kernel
+        match server.default_kernel_name().await {
+            Ok(kernel_name) => vec![kernel_name],
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.kernels.is_empty() {
         // Default to first available kernel
         let specs = runtimelib::list_kernelspecs().await;
         if specs.is_empty() {
@@ -102,37 +415,203 @@ async fn main() -> anyhow::Result<()> {
     };
 
     let timeout = Duration::from_millis(args.timeout);
-    let tests = all_tests();
-
-    // Run tests for each kernel
-    let mut reports = Vec::new();
+    let mut tests = TestRegistry::new();
+    tests.extend(all_tests());
+    let finalizers = all_finalizers();
+    let run_id = args.run_id.clone().unwrap_or_else(new_run_id);
 
+    let mut targets: Vec<_> = attach.into_iter().chain(command).collect();
     for kernel_name in &kernel_names {
-        if args.verbose {
-            eprintln!("Testing kernel: {}", kernel_name);
+        if let Some(server) = &server {
+            targets.push(KernelUnderTest::server_builder(server.clone(), kernel_name));
+            continue;
         }
+        match runtimelib::find_kernelspec(kernel_name).await {
+            Ok(spec) => targets.push(KernelUnderTest::builder(spec)),
+            Err(e) => eprintln!("Error finding kernel '{}': {}", kernel_name, e),
+        }
+    }
 
-        let kernelspec = match runtimelib::find_kernelspec(kernel_name).await {
-            Ok(spec) => spec,
-            Err(e) => {
-                eprintln!("Error finding kernel '{}': {}", kernel_name, e);
-                continue;
+    // Shared by every kernel's suite, which may run concurrently
+    let tiers = Arc::new(tiers);
+    let tests = Arc::new(tests);
+    let finalizers = Arc::new(finalizers);
+    let extra_tests = Arc::new(extra_tests);
+    let isolation = IsolationOptions {
+        mode: args.isolation,
+        check_state: args.check_isolation,
+    };
+    let jobs = args.jobs.max(1);
+
+    // On Ctrl-C, running suites kill their kernels and report what they have;
+    // a second Ctrl-C exits immediately
+    let interrupt = SuiteInterrupt::new();
+    let on_ctrl_c = interrupt.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\nInterrupted: stopping kernels (Ctrl-C again to exit now)");
+            on_ctrl_c.trigger();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(EXIT_INTERRUPTED);
             }
-        };
+        }
+    });
 
-        let report = run_conformance_suite(kernelspec, &tiers, timeout, &tests).await;
+    // Run tests for each kernel, up to `jobs` at a time
+    let mut pending = targets.into_iter().enumerate();
+    let mut running = JoinSet::new();
+    // Which kernel each suite task is testing, so a panicked task still has a report
+    let mut suites = HashMap::new();
+    let mut finished = Vec::new();
+    loop {
+        while running.len() < jobs && !interrupt.is_triggered() {
+            let Some((index, target)) = pending.next() else {
+                break;
+            };
+            let kernel_name = target.kernel_name();
+            if args.verbose {
+                eprintln!("Testing kernel: {}", kernel_name);
+            }
+
+            // Logs are appended to by every launch (including relaunches), so
+            // start this run's from empty. An attached kernel's output isn't ours
+            let log_paths = args
+                .kernel_log_dir
+                .as_deref()
+                .filter(|_| !target.is_attach())
+                .map(|dir| kernel_log_paths(dir, &kernel_name));
+            if let Some((stdout_log, stderr_log)) = &log_paths {
+                let _ = std::fs::remove_file(stdout_log);
+                let _ = std::fs::remove_file(stderr_log);
+            }
+            // A failed launch writes no transcript; don't report an old one
+            if let Some(dir) = &args.transcript_dir {
+                let _ = std::fs::remove_file(transcript_path(dir, &kernel_name));
+            }
+
+            let mut builder = target
+                .timeout(timeout)
+                .startup_timeout(Duration::from_millis(args.startup_timeout))
+                .shutdown_grace(Duration::from_millis(args.shutdown_grace))
+                .benchmark_config(config.benchmarks.clone())
+                .snippet_overrides(snippet_overrides.clone())
+                .transport(args.transport.clone())
+                .allow_shutdown(args.allow_shutdown)
+                .measure_resources(args.measure_resources)
+                .warmup(args.warmup)
+                .retries(args.retries);
+            if let Some(range) = &args.port_range {
+                builder = builder.port_range(range.clone());
+            }
+            if let Some(dir) = &args.kernel_log_dir {
+                builder = builder.log_dir(dir);
+            }
+            if let Some(dir) = &args.transcript_dir {
+                builder = builder.transcript_dir(dir);
+            }
+            if let Some(secs) = args.suite_timeout {
+                builder = builder.suite_timeout(Duration::from_secs(secs));
+            }
+            for (key, value) in &args.env {
+                builder = builder.env(key, value);
+            }
+            builder = match &args.cwd {
+                KernelCwd::TempDir => builder.temp_cwd(true),
+                KernelCwd::Path(path) => builder.cwd(path),
+            };
+
+            let (tiers, tests, finalizers, extra_tests, run_id, interrupt) = (
+                tiers.clone(),
+                tests.clone(),
+                finalizers.clone(),
+                extra_tests.clone(),
+                run_id.clone(),
+                interrupt.clone(),
+            );
+            let suite = running.spawn(async move {
+                run_conformance_suite(
+                    builder,
+                    &tiers,
+                    &tests,
+                    &finalizers,
+                    &extra_tests,
+                    &run_id,
+                    isolation,
+                    &interrupt,
+                )
+                .await
+            });
+            suites.insert(suite.id(), (index, kernel_name, log_paths, Instant::now()));
+        }
+
+        let Some(joined) = running.join_next_with_id().await else {
+            break;
+        };
+        let (id, outcome) = match joined {
+            Ok((id, report)) => (id, Ok(report)),
+            Err(e) => (e.id(), Err(e)),
+        };
+        let (index, kernel_name, log_paths, started) = suites
+            .remove(&id)
+            .expect("every suite task is recorded when spawned");
+        // A panicked suite is reported as a failed startup, so it still
+        // counts against the summary and the exit code
+        let report = outcome.unwrap_or_else(|e| {
+            eprintln!("Error: kernel suite task for {} failed: {}", kernel_name, e);
+            KernelReport::new_failed_at_startup(
+                run_id.clone(),
+                kernel_name.clone(),
+                "unknown".to_string(),
+                format!("kernel suite task failed: {}", e),
+                started.elapsed(),
+            )
+        });
 
         if args.verbose {
+            // With several kernels in flight, say which one finished
+            let prefix = if jobs > 1 {
+                format!("[{}] ", kernel_name)
+            } else {
+                "  ".to_string()
+            };
             if report.has_startup_error() {
-                eprintln!("  Startup failed: {}", report.startup_error.as_ref().unwrap());
+                eprintln!(
+                    "{}Startup failed: {}",
+                    prefix,
+                    report.startup_error.as_ref().unwrap()
+                );
             } else {
                 eprintln!(
-                    "  Completed: {}/{} passed",
+                    "{}Completed: {}/{} passed",
+                    prefix,
                     report.passed(),
                     report.total()
                 );
             }
         }
+        finished.push((index, kernel_name, log_paths, report));
+    }
+
+    // Report in the order kernels were given, not the order they finished
+    finished.sort_by_key(|(index, ..)| *index);
+    let mut reports = Vec::new();
+    let mut artifacts = ArtifactsSummary::new();
+    for (_, kernel_name, log_paths, mut report) in finished {
+        report.requirements = config.requirements_for(&kernel_name, &args.require);
+        artifacts.record_kernel(&kernel_name, report.total_duration);
+        if let Some((stdout_log, stderr_log)) = &log_paths {
+            for (kind, path) in [("kernel stdout", stdout_log), ("kernel stderr", stderr_log)] {
+                if path.exists() {
+                    artifacts.record_file(kind, path);
+                }
+            }
+        }
+        if let Some(dir) = &args.transcript_dir {
+            let path = transcript_path(dir, &kernel_name);
+            if path.exists() {
+                artifacts.record_file("transcript", &path);
+            }
+        }
         reports.push(report);
     }
 
@@ -141,51 +620,172 @@ async fn main() -> anyhow::Result<()> {
         std::process::exit(1);
     }
 
+    // Evaluate per-tier requirements before the reports are consumed
+    let unmet: Vec<String> = reports
+        .iter()
+        .flat_map(|report| {
+            report
+                .unmet_requirements()
+                .into_iter()
+                .map(move |(req, actual)| match actual {
+                    Some(percent) => format!(
+                        "{}: tier {} scored {:.1}%, required {}% (short by {:.1} points)",
+                        report.kernel_name,
+                        req.tier.tier_number(),
+                        percent,
+                        req.min_percent,
+                        req.min_percent - percent
+                    ),
+                    None => format!(
+                        "{}: tier {} has no scored tests, required {}%",
+                        report.kernel_name,
+                        req.tier.tier_number(),
+                        req.min_percent
+                    ),
+                })
+        })
+        .collect();
+
+    let failed = reports.iter().any(|report| {
+        report
+            .results
+            .iter()
+            .any(|r| matches!(r.result, TestResult::Fail { .. } | TestResult::Timeout))
+    });
+    let matrix = ConformanceMatrix::new(run_id, reports);
+
     // Render output
-    let output = match args.format {
-        OutputFormat::Terminal => {
-            if reports.len() == 1 {
-                render_terminal(&reports[0])
-            } else {
-                let matrix = ConformanceMatrix::new(reports);
-                // For terminal, show each report
-                matrix
-                    .reports
-                    .iter()
-                    .map(render_terminal)
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            }
-        }
+    let output = match settings.format {
+        OutputFormat::Terminal => matrix
+            .reports
+            .iter()
+            .map(render_terminal)
+            .collect::<Vec<_>>()
+            .join("\n"),
         OutputFormat::Json => {
-            if reports.len() == 1 {
-                render_json(&reports[0])
+            if matrix.reports.len() == 1 {
+                render_json(&matrix.reports[0])
             } else {
-                let matrix = ConformanceMatrix::new(reports);
                 render_matrix_json(&matrix)
             }
         }
-        OutputFormat::Markdown => {
-            if reports.len() == 1 {
-                render_markdown(&reports[0])
-            } else {
-                let matrix = ConformanceMatrix::new(reports);
-                render_matrix_markdown(&matrix)
-            }
-        }
+        OutputFormat::Markdown => render_markdown_output(&matrix),
     };
 
-    // Write output
-    if let Some(path) = args.output {
-        std::fs::write(&path, &output)?;
+    write_output(settings.output.as_deref(), &output)?;
+    if let Some(path) = &settings.output {
+        artifacts.record_file("report", path);
+    }
+
+    if let Some(path) = &settings.step_summary {
+        let mut summary = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(summary, "{}", render_markdown_output(&matrix))?;
+        artifacts.record_file("step summary", path);
+    }
+
+    if settings.annotations {
+        for report in &matrix.reports {
+            print!("{}", render_github_annotations(report));
+        }
+    }
+
+    if settings.format == OutputFormat::Terminal || args.verbose {
+        eprint!("\n{}", artifacts.render());
+    }
+
+    if interrupt.is_triggered() {
+        let untested = pending.count();
+        if untested > 0 {
+            eprintln!("Interrupted before {} kernel(s) were tested", untested);
+        }
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+
+    if !unmet.is_empty() {
+        eprintln!("Tier requirements not met:");
+        for line in &unmet {
+            eprintln!("  {}", line);
+        }
+        std::process::exit(1);
+    }
+
+    if settings.strict && failed {
+        eprintln!("Strict mode: one or more tests failed");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Parse a `--transport` value.
+fn parse_transport(arg: &str) -> Result<Transport, String> {
+    match arg {
+        "tcp" => Ok(Transport::TCP),
+        "ipc" => Ok(Transport::IPC),
+        _ => Err(format!("expected tcp or ipc, got '{}'", arg)),
+    }
+}
+
+/// Where `--cwd` starts launched kernels.
+#[derive(Debug, Clone)]
+enum KernelCwd {
+    TempDir,
+    Path(PathBuf),
+}
+
+fn parse_cwd(arg: &str) -> Result<KernelCwd, String> {
+    match arg {
+        "" => Err("expected a directory or tempdir".to_string()),
+        "tempdir" => Ok(KernelCwd::TempDir),
+        path => Ok(KernelCwd::Path(PathBuf::from(path))),
+    }
+}
+
+/// Parse a `--env KEY=VALUE` override; an empty VALUE is kept and means unset.
+fn parse_env_override(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", arg)),
+    }
+}
+
+/// Load extra test specs, exiting with an error if any spec is invalid.
+fn extra_tests_or_exit(dir: Option<&Path>) -> Vec<ExtraTest> {
+    match dir {
+        Some(dir) => match load_extra_tests(dir) {
+            Ok(specs) => specs,
+            Err(e) => {
+                eprintln!("Error loading extra tests: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    }
+}
+
+/// Write rendered output to a file, or stdout if no path is given.
+fn write_output(path: Option<&Path>, output: &str) -> std::io::Result<()> {
+    if let Some(path) = path {
+        std::fs::write(path, output)?;
         eprintln!("Output written to: {}", path.display());
     } else {
         println!("{}", output);
     }
-
     Ok(())
 }
 
+/// Markdown for one report, or the comparison table for several.
+fn render_markdown_output(matrix: &ConformanceMatrix) -> String {
+    if matrix.reports.len() == 1 {
+        render_markdown(&matrix.reports[0])
+    } else {
+        render_matrix_markdown(matrix)
+    }
+}
+
 async fn list_kernels() -> anyhow::Result<()> {
     let kernelspecs = runtimelib::list_kernelspecs().await;
 
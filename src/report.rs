@@ -1,6 +1,10 @@
 //! Report rendering for different output formats.
 
-use crate::types::{ConformanceMatrix, KernelReport, TestCategory, TestResult};
+use crate::types::{
+    ConformanceMatrix, IsolationReport, KernelExitStatus, KernelReport, ResourceUsage,
+    TestCategory, TestRecord, TestResult,
+};
+use chrono::{DateTime, Utc};
 
 /// Render a report to terminal with colors.
 pub fn render_terminal(report: &KernelReport) -> String {
@@ -14,11 +18,57 @@ pub fn render_terminal(report: &KernelReport) -> String {
         report.implementation
     ));
     output.push_str(&format!(
-        "Language: {} | Protocol: {} | Duration: {:?}\n",
-        report.language, report.protocol_version, report.total_duration
+        "Language: {} | Protocol: {} | Duration: {:?} | Startup: {:?}\n",
+        report.language, report.protocol_version, report.total_duration, report.startup_duration
     ));
+    output.push_str(&format!(
+        "Run: {} | Started: {} | Isolation: {}\n",
+        report.run_id,
+        format_timestamp(&report.timestamp),
+        format_isolation_mode(report)
+    ));
+    if !report.env.is_empty() {
+        output.push_str(&format!("Env: {}\n", format_env(report)));
+    }
+    if let Some(cwd) = &report.cwd {
+        output.push_str(&format!("Cwd: {}\n", cwd.display()));
+    }
+    if !report.snippet_overrides.is_empty() {
+        output.push_str(&format!(
+            "Snippet overrides: {}\n",
+            report.snippet_overrides.join(", ")
+        ));
+    }
+    if !report.warmup_durations.is_empty() {
+        output.push_str(&format!("Warm-up: {}\n", format_warmup(report)));
+    }
+    if report.attached {
+        output.push_str("Mode: attached to a running kernel\n");
+    }
+    if let Some(resources) = &report.resources {
+        output.push_str(&format!("Resources: {}\n", format_resources(resources)));
+    }
+    if report.interrupted {
+        output.push_str("Interrupted: remaining tests were skipped\n");
+    }
+    if report.timed_out {
+        output.push_str("Suite timeout: remaining tests were skipped\n");
+    }
+    if let Some(exit) = &report.kernel_exit {
+        output.push_str(&format!("Kernel exit: {}\n", format_kernel_exit(exit)));
+    }
     output.push_str(&format!("{}\n\n", "=".repeat(60)));
 
+    if let Some(crash) = &report.kernel_crash {
+        output.push_str(&format!("{}\n", "!".repeat(60)));
+        output.push_str(&format!(
+            "💥 KERNEL CRASHED after {} tests ({})\n",
+            crash.after_tests, crash.status
+        ));
+        output.push_str("   Remaining tests were skipped\n");
+        output.push_str(&format!("{}\n\n", "!".repeat(60)));
+    }
+
     // Results by tier
     for tier in [
         TestCategory::Tier1Basic,
@@ -42,23 +92,44 @@ pub fn render_terminal(report: &KernelReport) -> String {
         output.push_str(&format!("{}\n", "-".repeat(50)));
 
         for record in tier_results {
-            let symbol = record.result.symbol();
-            let emoji = record.result.emoji();
-            output.push_str(&format!(
-                "  {} {} {} ({:?})\n",
-                emoji, symbol, record.name, record.duration
-            ));
+            render_terminal_record(&mut output, record);
+        }
+        output.push('\n');
+    }
 
-            // Show failure reason and hint
-            if let TestResult::Fail { reason, kind } = &record.result {
-                output.push_str(&format!("      Reason: {}\n", reason));
-                if let Some(k) = kind {
-                    output.push_str(&format!("      Likely source: {} | {}\n", k.likely_source(), k.actionable_hint()));
-                }
-            }
-            if let TestResult::PartialPass { score, notes } = &record.result {
-                output.push_str(&format!("      Score: {:.0}% - {}\n", score * 100.0, notes));
-            }
+    // User-supplied extra tests
+    let custom_results = report.custom_results();
+    if !custom_results.is_empty() {
+        let passed = custom_results.iter().filter(|r| r.result.is_pass()).count();
+        output.push_str(&format!(
+            "Custom Tests ({}/{})\n",
+            passed,
+            custom_results.len()
+        ));
+        output.push_str(&format!("{}\n", "-".repeat(50)));
+        for record in custom_results {
+            render_terminal_record(&mut output, record);
+        }
+        output.push('\n');
+    }
+
+    // Opt-in benchmarks
+    let benchmark_results = report.tier_results(TestCategory::Benchmark);
+    if !benchmark_results.is_empty() {
+        output.push_str("Benchmarks\n");
+        output.push_str(&format!("{}\n", "-".repeat(50)));
+        for record in benchmark_results {
+            render_terminal_record(&mut output, record);
+        }
+        output.push('\n');
+    }
+
+    // Isolation appendix
+    if let Some(isolation) = &report.isolation {
+        output.push_str("Isolation\n");
+        output.push_str(&format!("{}\n", "-".repeat(50)));
+        for line in isolation_lines(isolation) {
+            output.push_str(&format!("  {}\n", line));
         }
         output.push('\n');
     }
@@ -71,10 +142,181 @@ pub fn render_terminal(report: &KernelReport) -> String {
         report.total(),
         report.score() * 100.0
     ));
+    if !report.requirements.is_empty() {
+        output.push_str(&format!("Requirements: {}\n", format_requirements(report)));
+    }
 
     output
 }
 
+/// Render requirements with their outcome, e.g. "tier1=100 ✅, tier2=80 ❌ (75%)".
+fn format_requirements(report: &KernelReport) -> String {
+    report
+        .requirements
+        .iter()
+        .map(|req| match report.tier_percent(req.tier) {
+            Some(percent) if percent >= req.min_percent => format!("{} ✅", req),
+            Some(percent) => format!("{} ❌ ({:.0}%)", req, percent),
+            None => format!("{} ❌ (no scored tests)", req),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Render the isolation mode with launch count, e.g. "per-tier (4 kernel launches)".
+fn format_isolation_mode(report: &KernelReport) -> String {
+    let plural = if report.kernel_launches == 1 {
+        ""
+    } else {
+        "es"
+    };
+    format!(
+        "{} ({} kernel launch{})",
+        report.isolation_mode, report.kernel_launches, plural
+    )
+}
+
+/// Render resource usage, e.g. "peak RSS 84.2 MiB, final RSS 80.1 MiB, CPU 3.41s".
+fn format_resources(resources: &ResourceUsage) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    format!(
+        "peak RSS {:.1} MiB, final RSS {:.1} MiB, CPU {:.2}s",
+        resources.peak_rss_bytes as f64 / MIB,
+        resources.final_rss_bytes as f64 / MIB,
+        resources.cpu_seconds
+    )
+}
+
+/// Render how the kernel process ended, calling out anything but a clean
+/// exit since it points at a buggy shutdown path.
+fn format_kernel_exit(exit: &KernelExitStatus) -> String {
+    if exit.is_clean() {
+        exit.to_string()
+    } else {
+        format!("{} (expected exit code 0)", exit)
+    }
+}
+
+/// Render warm-up execution times, cold first, e.g. "1520ms cold, then 14ms, 12ms".
+fn format_warmup(report: &KernelReport) -> String {
+    let mut durations = report.warmup_durations.iter().map(|d| d.as_millis());
+    let mut output = match durations.next() {
+        Some(cold) => format!("{}ms cold", cold),
+        None => return String::new(),
+    };
+    let warm: Vec<String> = durations.map(|ms| format!("{}ms", ms)).collect();
+    if !warm.is_empty() {
+        output.push_str(&format!(", then {}", warm.join(", ")));
+    }
+    output
+}
+
+/// Render env overrides, e.g. "RUST_LOG=debug, PYTHONPATH (unset)".
+fn format_env(report: &KernelReport) -> String {
+    report
+        .env
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() {
+                format!("{} (unset)", key)
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_terminal_record(output: &mut String, record: &TestRecord) {
+    let symbol = record.result.symbol();
+    let emoji = record.result.emoji();
+    output.push_str(&format!(
+        "  {} {}{} {} ({:?})\n",
+        emoji,
+        symbol,
+        format_attempts(record),
+        record.name,
+        record.duration
+    ));
+
+    // Show failure reason and hint
+    if let TestResult::Fail { reason, kind } = &record.result {
+        output.push_str(&format!("      Reason: {}\n", reason));
+        if let Some(k) = kind {
+            output.push_str(&format!("      Likely source: {} | {}\n", k.likely_source(), k.actionable_hint()));
+        }
+    }
+    if let TestResult::Skipped { reason } = &record.result {
+        output.push_str(&format!("      Skipped: {}\n", reason));
+    }
+    if let TestResult::PartialPass { score, notes } = &record.result {
+        output.push_str(&format!("      Score: {:.0}% - {}\n", score * 100.0, notes));
+    }
+    if !record.metrics.is_empty() {
+        output.push_str(&format!("      Metrics: {}\n", format_metrics(record)));
+    }
+    if let Some(flagged) = &record.flagged {
+        output.push_str(&format!("      Flagged: {}\n", flagged));
+    }
+    if record.missing_parent_header {
+        output.push_str("      Note: accepted a shell reply without parent_header\n");
+    }
+    if let Some(excerpt) = &record.kernel_log_excerpt {
+        output.push_str("      Kernel stderr:\n");
+        for line in excerpt.lines() {
+            output.push_str(&format!("        {}\n", line));
+        }
+    }
+}
+
+/// " (N attempts)" for a retried test, so flakiness stays visible; empty
+/// otherwise.
+fn format_attempts(record: &TestRecord) -> String {
+    if record.attempt_count() > 1 {
+        format!(" ({} attempts)", record.attempt_count())
+    } else {
+        String::new()
+    }
+}
+
+fn format_metrics(record: &TestRecord) -> String {
+    record
+        .metrics
+        .iter()
+        .map(|(name, value)| format!("{}={:.1}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Render failed tests as GitHub Actions workflow annotations.
+pub fn render_github_annotations(report: &KernelReport) -> String {
+    let mut output = String::new();
+    for record in &report.results {
+        let message = match &record.result {
+            TestResult::Fail { reason, .. } => reason.clone(),
+            TestResult::Timeout => "Timed out".to_string(),
+            _ => continue,
+        };
+        let title = format!("{} {}", report.kernel_name, record.name);
+        output.push_str(&format!(
+            "::error title={}::{}\n",
+            escape_annotation_property(&title),
+            escape_annotation_data(&message)
+        ));
+    }
+    output
+}
+
+/// Escape a workflow command message (`%`, CR and LF must be encoded).
+fn escape_annotation_data(s: &str) -> String {
+    s.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escape a workflow command property, which additionally can't contain `:` or `,`.
+fn escape_annotation_property(s: &str) -> String {
+    escape_annotation_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
 /// Render a report as JSON.
 pub fn render_json(report: &KernelReport) -> String {
     serde_json::to_string_pretty(report).unwrap_or_else(|e| format!("{{\"error\": \"{}\"}}", e))
@@ -103,37 +345,157 @@ pub fn render_markdown(report: &KernelReport) -> String {
         report.protocol_version
     ));
     output.push_str(&format!(
-        "- **Score**: {}/{} ({:.0}%)\n\n",
+        "- **Startup**: {}ms\n",
+        report.startup_duration.as_millis()
+    ));
+    output.push_str(&format!("- **Run ID**: {}\n", report.run_id));
+    output.push_str(&format!(
+        "- **Isolation**: {}\n",
+        format_isolation_mode(report)
+    ));
+    if !report.env.is_empty() {
+        output.push_str(&format!("- **Env**: {}\n", format_env(report)));
+    }
+    if let Some(cwd) = &report.cwd {
+        output.push_str(&format!("- **Cwd**: `{}`\n", cwd.display()));
+    }
+    if !report.snippet_overrides.is_empty() {
+        output.push_str(&format!(
+            "- **Snippet overrides**: {}\n",
+            report.snippet_overrides.join(", ")
+        ));
+    }
+    if !report.warmup_durations.is_empty() {
+        output.push_str(&format!("- **Warm-up**: {}\n", format_warmup(report)));
+    }
+    if report.attached {
+        output.push_str("- **Mode**: attached to a running kernel\n");
+    }
+    if let Some(resources) = &report.resources {
+        output.push_str(&format!(
+            "- **Resources**: {}\n",
+            format_resources(resources)
+        ));
+    }
+    if report.interrupted {
+        output.push_str("- **Interrupted**: remaining tests were skipped\n");
+    }
+    if report.timed_out {
+        output.push_str("- **Suite timeout**: remaining tests were skipped\n");
+    }
+    if let Some(exit) = &report.kernel_exit {
+        output.push_str(&format!(
+            "- **Kernel exit**: {}\n",
+            format_kernel_exit(exit)
+        ));
+    }
+    if let Some(crash) = &report.kernel_crash {
+        output.push_str(&format!(
+            "- **Kernel crashed**: after {} tests ({}); remaining tests were skipped\n",
+            crash.after_tests, crash.status
+        ));
+    }
+    output.push_str(&format!(
+        "- **Started**: {}\n",
+        format_timestamp(&report.timestamp)
+    ));
+    output.push_str(&format!(
+        "- **Score**: {}/{} ({:.0}%)\n",
         report.passed(),
         report.total(),
         report.score() * 100.0
     ));
+    if !report.requirements.is_empty() {
+        output.push_str(&format!(
+            "- **Requirements**: {}\n",
+            format_requirements(report)
+        ));
+    }
+    output.push('\n');
 
     // Results table
     output.push_str("| Test | Tier | Result | Duration |\n");
     output.push_str("|------|------|--------|----------|\n");
 
     for record in &report.results {
-        let result_str = match &record.result {
+        let mut result_str = match &record.result {
             TestResult::Pass => "PASS".to_string(),
             TestResult::Fail { reason, .. } => format!("FAIL: {}", truncate(reason, 30)),
             TestResult::Unsupported => "SKIP".to_string(),
+            TestResult::Skipped { reason } => format!("SKIP: {}", truncate(reason, 30)),
             TestResult::Timeout => "TIMEOUT".to_string(),
             TestResult::PartialPass { score, .. } => format!("PARTIAL ({:.0}%)", score * 100.0),
         };
+        result_str.push_str(&format_attempts(record));
+
+        let tier = if record.custom {
+            "custom".to_string()
+        } else if record.category == TestCategory::Benchmark {
+            "bench".to_string()
+        } else {
+            record.category.tier_number().to_string()
+        };
 
         output.push_str(&format!(
             "| {} | {} | {} | {:?} |\n",
-            record.name,
-            record.category.tier_number(),
-            result_str,
-            record.duration
+            record.name, tier, result_str, record.duration
         ));
     }
 
+    let benchmark_results = report.tier_results(TestCategory::Benchmark);
+    if !benchmark_results.is_empty() {
+        output.push_str("\n## Benchmarks\n\n");
+        for record in benchmark_results {
+            output.push_str(&format!("- **{}**: {}", record.name, format_metrics(record)));
+            if let Some(flagged) = &record.flagged {
+                output.push_str(&format!(" (flagged: {})", flagged));
+            }
+            output.push('\n');
+        }
+    }
+
+    let logged: Vec<_> = report
+        .results
+        .iter()
+        .filter(|r| r.kernel_log_excerpt.is_some())
+        .collect();
+    if !logged.is_empty() {
+        output.push_str("\n## Kernel stderr\n");
+        for record in logged {
+            output.push_str(&format!(
+                "\n<details><summary>{}</summary>\n\n```\n{}\n```\n\n</details>\n",
+                record.name,
+                record.kernel_log_excerpt.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
+    if let Some(isolation) = &report.isolation {
+        output.push_str("\n## Isolation\n\n");
+        for line in isolation_lines(isolation) {
+            output.push_str(&format!("- {}\n", line));
+        }
+    }
+
     output
 }
 
+/// Describe isolation results, one line per state-mutating test.
+fn isolation_lines(isolation: &IsolationReport) -> Vec<String> {
+    let mut lines: Vec<String> = isolation
+        .state_mutating_tests
+        .iter()
+        .map(|name| format!("{} changed kernel state", name))
+        .collect();
+    if lines.is_empty() && isolation.error.is_none() {
+        lines.push("No test changed kernel state".to_string());
+    }
+    if let Some(error) = &isolation.error {
+        lines.push(format!("Checking stopped: {}", error));
+    }
+    lines
+}
+
 /// Render a matrix as a markdown comparison table.
 pub fn render_matrix_markdown(matrix: &ConformanceMatrix) -> String {
     if matrix.reports.is_empty() {
@@ -144,8 +506,9 @@ pub fn render_matrix_markdown(matrix: &ConformanceMatrix) -> String {
 
     output.push_str("# Kernel Conformance Matrix\n\n");
     output.push_str(&format!(
-        "Generated: {}\n\n",
-        matrix.generated_at.format("%Y-%m-%d %H:%M:%S UTC")
+        "Generated: {} | Run: {}\n\n",
+        format_timestamp(&matrix.generated_at),
+        matrix.run_id
     ));
 
     // Get all test names
@@ -154,7 +517,11 @@ pub fn render_matrix_markdown(matrix: &ConformanceMatrix) -> String {
     // Header row
     output.push_str("| Test |");
     for report in &matrix.reports {
-        output.push_str(&format!(" {} |", report.kernel_name));
+        if report.attached {
+            output.push_str(&format!(" {} (attached) |", report.kernel_name));
+        } else {
+            output.push_str(&format!(" {} |", report.kernel_name));
+        }
     }
     output.push('\n');
 
@@ -191,9 +558,64 @@ pub fn render_matrix_markdown(matrix: &ConformanceMatrix) -> String {
     }
     output.push('\n');
 
+    output.push_str("| **Startup** |");
+    for report in &matrix.reports {
+        output.push_str(&format!(" {}ms |", report.startup_duration.as_millis()));
+    }
+    output.push('\n');
+
+    if matrix
+        .reports
+        .iter()
+        .any(|report| report.resources.is_some())
+    {
+        output.push_str("| **Resources** |");
+        for report in &matrix.reports {
+            let resources = report
+                .resources
+                .as_ref()
+                .map(format_resources)
+                .unwrap_or_else(|| "-".to_string());
+            output.push_str(&format!(" {} |", resources));
+        }
+        output.push('\n');
+    }
+
+    // Benchmark comparison
+    let benchmark_metrics = matrix.all_benchmark_metrics();
+    if !benchmark_metrics.is_empty() {
+        output.push_str("\n## Benchmarks\n\n| Metric |");
+        for report in &matrix.reports {
+            output.push_str(&format!(" {} |", report.kernel_name));
+        }
+        output.push_str("\n|--------|");
+        for _ in &matrix.reports {
+            output.push_str("------|");
+        }
+        output.push('\n');
+
+        for (test_name, metric) in benchmark_metrics {
+            output.push_str(&format!("| {} {} |", test_name, metric));
+            for report in &matrix.reports {
+                let record = report.results.iter().find(|r| r.name == test_name);
+                let value = record
+                    .and_then(|r| r.metrics.get(metric).map(|v| (v, r.flagged.is_some())))
+                    .map(|(v, flagged)| format!("{:.1}{}", v, if flagged { " ⚑" } else { "" }))
+                    .unwrap_or_else(|| "-".to_string());
+                output.push_str(&format!(" {} |", value));
+            }
+            output.push('\n');
+        }
+    }
+
     output
 }
 
+/// Format a timestamp for human-readable output, always labeled UTC.
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
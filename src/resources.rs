@@ -0,0 +1,222 @@
+//! Sampling of the kernel process's memory and CPU use.
+//!
+//! On Linux counters are read straight from /proc; elsewhere they come from
+//! sysinfo, on the platforms it supports (see [`RESOURCES_SUPPORTED`]).
+
+use crate::types::ResourceUsage;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How often the kernel process is sampled
+pub const RESOURCE_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether the kernel process can be sampled on this platform
+#[cfg(target_os = "linux")]
+pub const RESOURCES_SUPPORTED: bool = true;
+
+/// Whether the kernel process can be sampled on this platform
+#[cfg(not(target_os = "linux"))]
+pub const RESOURCES_SUPPORTED: bool = sysinfo::IS_SUPPORTED_SYSTEM;
+
+/// USER_HZ, the unit of CPU times in /proc/<pid>/stat. Linux fixes it at 100
+/// for userspace on every mainstream architecture.
+#[cfg(target_os = "linux")]
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// One reading of a process's counters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    rss_bytes: u64,
+    cpu_seconds: f64,
+}
+
+/// What has been seen so far, across every kernel process of the run.
+#[derive(Debug, Default)]
+struct Totals {
+    pid: Option<u32>,
+    peak_rss_bytes: u64,
+    last_rss_bytes: u64,
+    /// CPU time of processes replaced by a relaunch
+    retired_cpu_seconds: f64,
+    current_cpu_seconds: f64,
+    sampled: bool,
+}
+
+impl Totals {
+    fn record(&mut self, sample: Sample) {
+        self.peak_rss_bytes = self.peak_rss_bytes.max(sample.rss_bytes);
+        self.last_rss_bytes = sample.rss_bytes;
+        self.current_cpu_seconds = sample.cpu_seconds;
+        self.sampled = true;
+    }
+
+    /// Take one sample of the current process, if it's still there.
+    fn sample(&mut self) {
+        if let Some(sample) = self.pid.and_then(read_sample) {
+            self.record(sample);
+        }
+    }
+
+    /// Switch to a new process, keeping the CPU time of the old one.
+    fn follow(&mut self, pid: u32) {
+        self.sample();
+        self.retired_cpu_seconds += self.current_cpu_seconds;
+        self.current_cpu_seconds = 0.0;
+        self.pid = Some(pid);
+    }
+
+    fn usage(&self) -> Option<ResourceUsage> {
+        self.sampled.then_some(ResourceUsage {
+            peak_rss_bytes: self.peak_rss_bytes,
+            final_rss_bytes: self.last_rss_bytes,
+            cpu_seconds: self.retired_cpu_seconds + self.current_cpu_seconds,
+        })
+    }
+}
+
+/// Background sampler of the kernel process's RSS and CPU time.
+pub(crate) struct ResourceMonitor {
+    totals: Arc<Mutex<Totals>>,
+    sampler: JoinHandle<()>,
+}
+
+impl ResourceMonitor {
+    /// Start sampling `pid` every [`RESOURCE_SAMPLE_INTERVAL`].
+    pub(crate) fn start(pid: u32) -> Self {
+        let totals = Arc::new(Mutex::new(Totals {
+            pid: Some(pid),
+            ..Totals::default()
+        }));
+        let shared = totals.clone();
+        let sampler = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(RESOURCE_SAMPLE_INTERVAL);
+            loop {
+                ticks.tick().await;
+                if let Ok(mut totals) = shared.lock() {
+                    totals.sample();
+                }
+            }
+        });
+        Self { totals, sampler }
+    }
+
+    /// Follow a relaunched kernel, adding its CPU time to its predecessor's.
+    pub(crate) fn follow(&self, pid: u32) {
+        if let Ok(mut totals) = self.totals.lock() {
+            totals.follow(pid);
+        }
+    }
+
+    /// Stop sampling and return what was measured, or None if no sample
+    /// ever succeeded.
+    pub(crate) fn finish(self) -> Option<ResourceUsage> {
+        self.sampler.abort();
+        let mut totals = self.totals.lock().ok()?;
+        totals.sample();
+        totals.usage()
+    }
+}
+
+impl Drop for ResourceMonitor {
+    fn drop(&mut self) {
+        self.sampler.abort();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_sample(pid: u32) -> Option<Sample> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    Some(Sample {
+        rss_bytes: parse_vm_rss(&status)?,
+        cpu_seconds: parse_cpu_seconds(&stat)?,
+    })
+}
+
+/// Counters of `pid` from sysinfo, which reports CPU time in milliseconds.
+#[cfg(not(target_os = "linux"))]
+fn read_sample(pid: u32) -> Option<Sample> {
+    use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    let refresh = ProcessRefreshKind::nothing().with_memory().with_cpu();
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), true, refresh);
+    let process = system.process(pid)?;
+    Some(Sample {
+        rss_bytes: process.memory(),
+        cpu_seconds: process.accumulated_cpu_time() as f64 / 1000.0,
+    })
+}
+
+/// VmRSS from /proc/<pid>/status, which reports it in kB.
+#[cfg(target_os = "linux")]
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// utime + stime from /proc/<pid>/stat.
+///
+/// The command name in field 2 may contain spaces and parentheses, so
+/// fields are counted from its closing parenthesis.
+#[cfg(target_os = "linux")]
+fn parse_cpu_seconds(stat: &str) -> Option<f64> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // rest starts at field 3 (state), so utime (14) and stime (15) are 11 and 12
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some((utime + stime) as f64 / CLOCK_TICKS_PER_SECOND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_counters() {
+        let status = "Name:\tpython3\nVmPeak:\t  300000 kB\nVmRSS:\t   51200 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(51200 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tkthreadd\n"), None);
+
+        let stat = "4242 (ipykernel (x) 1) S 1 4242 4242 0 -1 4194560 9000 0 0 0 \
+                    250 30 0 0 20 0 4 0 100 300000000 12800";
+        assert_eq!(parse_cpu_seconds(stat), Some(2.8));
+    }
+
+    #[test]
+    fn test_follow_keeps_cpu_of_replaced_kernel() {
+        let mut totals = Totals::default();
+        totals.record(Sample {
+            rss_bytes: 300,
+            cpu_seconds: 1.5,
+        });
+        totals.follow(u32::MAX);
+        totals.record(Sample {
+            rss_bytes: 100,
+            cpu_seconds: 0.5,
+        });
+        assert_eq!(
+            totals.usage(),
+            Some(ResourceUsage {
+                peak_rss_bytes: 300,
+                final_rss_bytes: 100,
+                cpu_seconds: 2.0,
+            })
+        );
+        assert_eq!(Totals::default().usage(), None);
+    }
+
+    #[test]
+    fn test_samples_own_process() {
+        if !RESOURCES_SUPPORTED {
+            return;
+        }
+        let sample = read_sample(std::process::id()).unwrap();
+        assert!(sample.rss_bytes > 0);
+    }
+}
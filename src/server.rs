@@ -0,0 +1,365 @@
+//! Kernels started through a Jupyter Server.
+//!
+//! The server's REST API starts and deletes the kernel, and a websocket on
+//! `/api/kernels/{id}/channels` carries shell, control, stdin and IOPub, each
+//! message naming the channel it belongs to. There is no heartbeat channel.
+
+use crate::channels::{Channel, Outgoing, Relay};
+use crate::harness::{HarnessError, Result};
+use crate::transcript::message_from_value;
+use anyhow::{anyhow, bail};
+use futures::{SinkExt, StreamExt};
+use jupyter_protocol::JupyterMessage;
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Client, Method, RequestBuilder, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A Jupyter Server to start kernels through.
+#[derive(Debug, Clone)]
+pub struct JupyterServer {
+    /// Base URL, ending in `/` so API paths join onto it
+    url: Url,
+    /// Sent as `Authorization: token ...` when set
+    token: Option<String>,
+    client: Client,
+}
+
+/// The part of the server's kernel model the harness uses.
+#[derive(Debug, Deserialize)]
+struct KernelModel {
+    id: String,
+}
+
+/// The part of the server's kernelspec listing the harness uses.
+#[derive(Debug, Deserialize)]
+struct KernelspecsModel {
+    default: String,
+}
+
+impl JupyterServer {
+    /// A server at `url`, e.g. `http://localhost:8888`, authenticating with
+    /// `token` if given.
+    pub fn new(url: &str, token: Option<&str>) -> Result<Self> {
+        let mut url = Url::parse(url)
+            .map_err(|e| HarnessError::InvalidServerUrl(format!("{}: {}", url, e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HarnessError::InvalidServerUrl(format!(
+                "{}: only http and https are supported",
+                url
+            )));
+        }
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Ok(Self {
+            url,
+            token: token.map(str::to_string),
+            client: Client::new(),
+        })
+    }
+
+    /// The server's base URL.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    fn api_url(&self, path: &str) -> Url {
+        // The base always ends in `/` and `path` is relative, so this can't fail
+        self.url.join(path).expect("relative API path")
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, self.api_url(path));
+        match &self.token {
+            Some(token) => request.header(AUTHORIZATION, format!("token {}", token)),
+            None => request,
+        }
+    }
+
+    /// Send `request`, failing with the server's explanation on an error
+    /// status.
+    async fn send(request: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        // Errors come as {"message": ..., "reason": ...}
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|error| error["message"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        if message.is_empty() {
+            bail!("{}", status)
+        }
+        bail!("{}: {}", status, message)
+    }
+
+    /// Name of the kernelspec the server starts when none is given.
+    pub async fn default_kernel_name(&self) -> Result<String> {
+        let failed = |e: anyhow::Error| {
+            HarnessError::ConnectionFailed(format!(
+                "GET {}: {}",
+                self.api_url("api/kernelspecs"),
+                e
+            ))
+        };
+        let response = Self::send(self.request(Method::GET, "api/kernelspecs"))
+            .await
+            .map_err(failed)?;
+        let kernelspecs: KernelspecsModel = response.json().await.map_err(|e| failed(e.into()))?;
+        Ok(kernelspecs.default)
+    }
+
+    /// Start a kernel from the server's kernelspec `kernel_name`.
+    pub(crate) async fn start_kernel(&self, kernel_name: &str) -> Result<ServerKernel> {
+        let body = serde_json::json!({ "name": kernel_name });
+        let failed = |e: anyhow::Error| {
+            HarnessError::LaunchFailed(format!("POST {}: {}", self.api_url("api/kernels"), e))
+        };
+        let response = Self::send(self.request(Method::POST, "api/kernels").json(&body))
+            .await
+            .map_err(failed)?;
+        let model: KernelModel = response.json().await.map_err(|e| failed(e.into()))?;
+        Ok(ServerKernel {
+            server: self.clone(),
+            id: model.id,
+            deleted: false,
+        })
+    }
+
+    async fn delete_kernel(&self, id: &str) -> Result<()> {
+        let path = format!("api/kernels/{}", id);
+        Self::send(self.request(Method::DELETE, &path))
+            .await
+            .map(drop)
+            .map_err(|e| {
+                HarnessError::ConnectionFailed(format!("DELETE {}: {}", self.api_url(&path), e))
+            })
+    }
+}
+
+/// A kernel the harness started through a [`JupyterServer`].
+///
+/// Deleted through the REST API by [`delete`](Self::delete), or failing
+/// that when dropped.
+#[derive(Debug)]
+pub(crate) struct ServerKernel {
+    server: JupyterServer,
+    id: String,
+    deleted: bool,
+}
+
+impl ServerKernel {
+    /// Open the kernel's channels websocket for the session `session_id`.
+    pub(crate) async fn connect(&self, session_id: &str) -> Result<WebSocket> {
+        let mut url = self
+            .server
+            .api_url(&format!("api/kernels/{}/channels", self.id));
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        // Both schemes are special, so switching between them can't fail
+        let _ = url.set_scheme(scheme);
+        url.query_pairs_mut().append_pair("session_id", session_id);
+
+        let failed = |e: &dyn std::fmt::Display| {
+            HarnessError::ConnectionFailed(format!("{}: {}", url.path(), e))
+        };
+        let mut request = url.as_str().into_client_request().map_err(|e| failed(&e))?;
+        if let Some(token) = &self.server.token {
+            let value = format!("token {}", token).parse().map_err(|e| failed(&e))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(|e| failed(&e))?;
+        Ok(socket)
+    }
+
+    /// Shut the kernel down by deleting it; later calls do nothing.
+    pub(crate) async fn delete(&mut self) -> Result<()> {
+        if std::mem::replace(&mut self.deleted, true) {
+            return Ok(());
+        }
+        self.server.delete_kernel(&self.id).await
+    }
+}
+
+impl Drop for ServerKernel {
+    /// Last-resort cleanup for a kernel that was never deleted. Drop can't
+    /// await, so the request is only started, and only inside a runtime.
+    fn drop(&mut self) {
+        if self.deleted {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let server = self.server.clone();
+            let id = std::mem::take(&mut self.id);
+            runtime.spawn(async move {
+                let _ = server.delete_kernel(&id).await;
+            });
+        }
+    }
+}
+
+/// Carry `relay`'s channels over `socket` until either side closes.
+pub(crate) fn relay_websocket(socket: WebSocket, relay: Relay) -> JoinHandle<()> {
+    tokio::spawn(carry(socket, relay))
+}
+
+async fn carry(mut socket: WebSocket, mut relay: Relay) {
+    loop {
+        tokio::select! {
+            outgoing = relay.outgoing.recv() => match outgoing {
+                Some((_, Outgoing::Message(channel, message))) => {
+                    let frame = match to_frame(channel, &message) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            eprintln!("Warning: can't send {} to the Jupyter Server: {}", channel, e);
+                            continue;
+                        }
+                    };
+                    if socket.send(frame).await.is_err() {
+                        break;
+                    }
+                }
+                // Nothing answers a heartbeat; dropping the echo fails it
+                Some((_, Outgoing::Heartbeat(_))) => {}
+                None => break,
+            },
+            incoming = socket.next() => match incoming {
+                Some(Ok(frame)) => match from_frame(frame) {
+                    Ok(Some((channel, message))) => {
+                        if !relay.deliver(channel, message) {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Warning: unreadable message from the Jupyter Server: {}", e),
+                },
+                Some(Err(_)) | None => break,
+            },
+        }
+    }
+    let _ = socket.close(None).await;
+}
+
+/// The websocket frame carrying `message` on `channel`.
+fn to_frame(channel: Channel, message: &JupyterMessage) -> anyhow::Result<Message> {
+    let mut value = serde_json::to_value(message)?;
+    value["channel"] = serde_json::to_value(channel)?;
+    Ok(Message::text(value.to_string()))
+}
+
+/// The channel and message a websocket frame from the server carries, or
+/// None for control frames.
+///
+/// Messages with buffers arrive as binary frames: a big-endian u32 count of
+/// parts, each part's offset, then the JSON message and its buffers.
+fn from_frame(frame: Message) -> anyhow::Result<Option<(Channel, JupyterMessage)>> {
+    let (json, buffers) = match &frame {
+        Message::Text(text) => (text.as_bytes(), Vec::new()),
+        Message::Binary(data) => {
+            let word = |index: usize| -> anyhow::Result<usize> {
+                let bytes = data
+                    .get(index * 4..index * 4 + 4)
+                    .ok_or_else(|| anyhow!("binary message is truncated"))?;
+                Ok(u32::from_be_bytes(bytes.try_into()?) as usize)
+            };
+            let parts = word(0)?;
+            let mut offsets = (1..=parts).map(word).collect::<anyhow::Result<Vec<_>>>()?;
+            offsets.push(data.len());
+            let mut slices = offsets.windows(2).map(|bounds| {
+                data.get(bounds[0]..bounds[1])
+                    .ok_or_else(|| anyhow!("binary message part is out of bounds"))
+            });
+            let json = slices
+                .next()
+                .ok_or_else(|| anyhow!("binary message has no parts"))??;
+            let buffers = slices
+                .map(|slice| slice.map(|bytes| data.slice_ref(bytes)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            (json, buffers)
+        }
+        _ => return Ok(None),
+    };
+
+    let value: Value = serde_json::from_slice(json)?;
+    let channel = serde_json::from_value(value["channel"].clone())
+        .map_err(|e| anyhow!("bad channel {}: {}", value["channel"], e))?;
+    let mut message = message_from_value(value)?;
+    message.buffers = buffers;
+    Ok(Some((channel, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jupyter_protocol::{ExecuteRequest, JupyterMessageContent};
+
+    #[test]
+    fn test_server_url_gets_trailing_slash() {
+        let server = JupyterServer::new("http://localhost:8888/user/me", Some("t")).unwrap();
+        assert_eq!(
+            server.api_url("api/kernels").as_str(),
+            "http://localhost:8888/user/me/api/kernels"
+        );
+        assert!(matches!(
+            JupyterServer::new("ftp://localhost", None),
+            Err(HarnessError::InvalidServerUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_frames_carry_channel() {
+        let request: JupyterMessage = ExecuteRequest::new("1 + 1".to_string()).into();
+        let frame = to_frame(Channel::Control, &request).unwrap();
+        let Message::Text(text) = &frame else {
+            panic!("expected a text frame, got {:?}", frame);
+        };
+        let value: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(value["channel"], "control");
+
+        let (channel, decoded) = from_frame(frame).unwrap().unwrap();
+        assert_eq!(channel, Channel::Control);
+        assert_eq!(decoded.header.msg_id, request.header.msg_id);
+        assert!(matches!(
+            decoded.content,
+            JupyterMessageContent::ExecuteRequest(_)
+        ));
+    }
+
+    #[test]
+    fn test_binary_frames_carry_buffers() {
+        let request: JupyterMessage = ExecuteRequest::new("1".to_string()).into();
+        let mut value = serde_json::to_value(&request).unwrap();
+        value["channel"] = "iopub".into();
+        let json = value.to_string().into_bytes();
+        let buffer = b"abc";
+
+        let mut data = Vec::new();
+        data.extend(2u32.to_be_bytes());
+        data.extend(12u32.to_be_bytes());
+        data.extend((12 + json.len() as u32).to_be_bytes());
+        data.extend(&json);
+        data.extend(buffer);
+
+        let (channel, decoded) = from_frame(Message::binary(data)).unwrap().unwrap();
+        assert_eq!(channel, Channel::IoPub);
+        assert_eq!(decoded.buffers.len(), 1);
+        assert_eq!(&decoded.buffers[0][..], buffer);
+
+        assert!(from_frame(Message::binary(vec![0, 0, 0, 2])).is_err());
+        assert!(from_frame(Message::Ping(Default::default()))
+            .unwrap()
+            .is_none());
+    }
+}
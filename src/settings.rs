@@ -0,0 +1,111 @@
+//! Resolution of CLI output settings, including the `--ci` preset.
+//!
+//! Explicit flags always win over the preset; the preset wins over built-in
+//! defaults. Keeping this out of `main` makes the precedence testable.
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// Default report path used by `--ci` when JSON output isn't redirected.
+pub const CI_REPORT_PATH: &str = "conformance-report.json";
+
+/// Output format for rendered reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Terminal,
+    Json,
+    Markdown,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Terminal => write!(f, "terminal"),
+            OutputFormat::Json => write!(f, "json"),
+            OutputFormat::Markdown => write!(f, "markdown"),
+        }
+    }
+}
+
+/// Output settings as given on the command line; `None` means "not set".
+#[derive(Debug, Clone, Default)]
+pub struct ExplicitSettings {
+    /// Enable the CI preset
+    pub ci: bool,
+    pub format: Option<OutputFormat>,
+    pub output: Option<PathBuf>,
+    pub step_summary: Option<bool>,
+    pub annotations: Option<bool>,
+    pub strict: Option<bool>,
+}
+
+/// Output settings after applying the CI preset and defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveSettings {
+    /// Format of the main report
+    pub format: OutputFormat,
+    /// Where to write the main report (stdout if None)
+    pub output: Option<PathBuf>,
+    /// File to append a markdown summary to (GitHub step summary)
+    pub step_summary: Option<PathBuf>,
+    /// Emit GitHub workflow annotations for failed tests
+    pub annotations: bool,
+    /// Exit non-zero when any test fails or times out
+    pub strict: bool,
+}
+
+impl EffectiveSettings {
+    /// Resolve explicit flags against the CI preset.
+    ///
+    /// `env` looks up environment variables; `GITHUB_STEP_SUMMARY` locates the
+    /// step summary file.
+    pub fn resolve(explicit: &ExplicitSettings, env: impl Fn(&str) -> Option<String>) -> Self {
+        let ci = explicit.ci;
+
+        let format = explicit.format.unwrap_or(if ci {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Terminal
+        });
+
+        // The default artifact path only makes sense for the JSON report
+        let output = explicit.output.clone().or_else(|| {
+            (ci && format == OutputFormat::Json).then(|| PathBuf::from(CI_REPORT_PATH))
+        });
+
+        let step_summary = if explicit.step_summary.unwrap_or(ci) {
+            env("GITHUB_STEP_SUMMARY")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        } else {
+            None
+        };
+
+        Self {
+            format,
+            output,
+            step_summary,
+            annotations: explicit.annotations.unwrap_or(ci),
+            strict: explicit.strict.unwrap_or(ci),
+        }
+    }
+}
+
+impl fmt::Display for EffectiveSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = |p: &Option<PathBuf>| {
+            p.as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        write!(
+            f,
+            "format={} output={} step_summary={} annotations={} strict={}",
+            self.format,
+            path(&self.output),
+            path(&self.step_summary),
+            self.annotations,
+            self.strict
+        )
+    }
+}
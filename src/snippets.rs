@@ -4,10 +4,28 @@
 //! snippets to test execution, completion, errors, etc.
 //!
 //! Snippets are loaded from `snippets/snippets.json` at compile time.
+//! Individual fields can be replaced at run time from a JSON file of
+//! [`SnippetOverrides`], for kernels whose dialect differs slightly from the
+//! built-in language's:
+//!
+//! ```json
+//! {
+//!   "languages": {
+//!     "python": { "sleep_code": "import utime; utime.sleep(2)" }
+//!   },
+//!   "kernels": {
+//!     "sql": { "completion_setup": "%load_ext sql" }
+//!   }
+//! }
+//! ```
 
-use serde::Deserialize;
-use std::collections::HashMap;
+use crate::extra_tests::without_location;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
+use thiserror::Error;
 
 /// Raw snippets data loaded from JSON.
 #[derive(Debug, Deserialize)]
@@ -33,10 +51,27 @@ struct RawSnippets {
     display_data_code: String,
     update_display_data_code: String,
     rich_execute_result_code: String,
+    inspect_function_setup: String,
+    state_fingerprint_code: String,
+    large_output_code: String,
+    counting_loop_code: String,
+    unicode_output_code: String,
+    interleaved_output_code: String,
+    comment_only_code: String,
+    password_prompt: String,
+    input_prompt_text: String,
+    runtime_error: String,
+    deep_recursion_code: String,
+    streaming_output_code: String,
+    markdown_output_code: String,
+    json_output_code: String,
+    latex_output_code: String,
+    multiline_code: String,
+    page_payload_code: String,
 }
 
 /// Code snippets for a specific kernel language.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LanguageSnippets {
     /// Language name (lowercase, e.g., "python", "r", "rust")
     pub language: String,
@@ -70,6 +105,40 @@ pub struct LanguageSnippets {
     pub update_display_data_code: String,
     /// Code that produces execute_result with rich MIME types (text/html, image/*, etc.)
     pub rich_execute_result_code: String,
+    /// Code that defines a function named `testbed_inspect_target` (for inspect detail levels)
+    pub inspect_function_setup: String,
+    /// Code that prints a stable summary of user-defined global names (for isolation checks)
+    pub state_fingerprint_code: String,
+    /// Code that writes 1,000,000 'x' characters to stdout (for large output integrity)
+    pub large_output_code: String,
+    /// Code that prints the integers 0 through 199, one per line (for stream ordering)
+    pub counting_loop_code: String,
+    /// Code that prints exactly "héllo 🦀 世界" to stdout (for encoding fidelity)
+    pub unicode_output_code: String,
+    /// Code that alternates flushed lines out1/err1 .. out3/err3 on stdout and stderr (for interleaving order)
+    pub interleaved_output_code: String,
+    /// Code made up only of a comment and whitespace lines (for no-op execution)
+    pub comment_only_code: String,
+    /// Code that reads a password from stdin (input_request with password=true)
+    pub password_prompt: String,
+    /// Prompt text that `input_prompt` passes to the kernel (empty if it passes none)
+    pub input_prompt_text: String,
+    /// Code that raises an error at runtime (not a syntax error)
+    pub runtime_error: String,
+    /// Code that fails with a recursion/stack overflow error (for deep tracebacks)
+    pub deep_recursion_code: String,
+    /// Code that prints testbed-first, flushes, sleeps ~1.5s, then prints testbed-second (for incremental streaming)
+    pub streaming_output_code: String,
+    /// Code that displays **bold** as text/markdown (execute_result or display_data)
+    pub markdown_output_code: String,
+    /// Code that displays the object {"a": 1} as application/json
+    pub json_output_code: String,
+    /// Code that displays `\frac{1}{2}` as text/latex
+    pub latex_output_code: String,
+    /// Several statements on separate lines that print 3 to stdout
+    pub multiline_code: String,
+    /// Code whose execute_reply carries a page payload (e.g. IPython's ? help)
+    pub page_payload_code: String,
 }
 
 impl From<(String, RawSnippets)> for LanguageSnippets {
@@ -91,6 +160,23 @@ impl From<(String, RawSnippets)> for LanguageSnippets {
             display_data_code: raw.display_data_code,
             update_display_data_code: raw.update_display_data_code,
             rich_execute_result_code: raw.rich_execute_result_code,
+            inspect_function_setup: raw.inspect_function_setup,
+            state_fingerprint_code: raw.state_fingerprint_code,
+            large_output_code: raw.large_output_code,
+            counting_loop_code: raw.counting_loop_code,
+            unicode_output_code: raw.unicode_output_code,
+            interleaved_output_code: raw.interleaved_output_code,
+            comment_only_code: raw.comment_only_code,
+            password_prompt: raw.password_prompt,
+            input_prompt_text: raw.input_prompt_text,
+            runtime_error: raw.runtime_error,
+            deep_recursion_code: raw.deep_recursion_code,
+            streaming_output_code: raw.streaming_output_code,
+            markdown_output_code: raw.markdown_output_code,
+            json_output_code: raw.json_output_code,
+            latex_output_code: raw.latex_output_code,
+            multiline_code: raw.multiline_code,
+            page_payload_code: raw.page_payload_code,
         }
     }
 }
@@ -151,10 +237,155 @@ impl LanguageSnippets {
             display_data_code: "1".to_string(),
             update_display_data_code: "// update_display not available".to_string(),
             rich_execute_result_code: "// rich execute_result not available".to_string(),
+            inspect_function_setup: "// inspect_function_setup not available".to_string(),
+            state_fingerprint_code: "// state_fingerprint_code not available".to_string(),
+            large_output_code: "// large_output_code not available".to_string(),
+            counting_loop_code: "// counting_loop_code not available".to_string(),
+            unicode_output_code: "// unicode_output_code not available".to_string(),
+            interleaved_output_code: "// interleaved_output_code not available".to_string(),
+            comment_only_code: "\n# nothing\n    \n".to_string(),
+            password_prompt: "// password_prompt not available".to_string(),
+            input_prompt_text: String::new(),
+            runtime_error: "1/0".to_string(),
+            deep_recursion_code: "// deep_recursion_code not available".to_string(),
+            streaming_output_code: "// streaming_output_code not available".to_string(),
+            markdown_output_code: "// markdown_output_code not available".to_string(),
+            json_output_code: "// json_output_code not available".to_string(),
+            latex_output_code: "// latex_output_code not available".to_string(),
+            multiline_code: "x = 1\ny = 2\nprint(x + y)".to_string(),
+            page_payload_code: "// page_payload_code not available".to_string(),
+        }
+    }
+}
+
+/// Error raised while loading a snippet overrides file.
+#[derive(Error, Debug)]
+pub enum SnippetsError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("{}: unknown snippet field `{field}` in {scope}", path.display())]
+    UnknownField {
+        path: PathBuf,
+        /// Where the field appeared, e.g. `kernels.micropython`
+        scope: String,
+        field: String,
+    },
+}
+
+/// Replacements for individual snippet fields, merged over the built-in
+/// snippets once kernel_info has told us the kernel's language.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnippetOverrides {
+    /// Fields to replace, keyed by language name (as in language_info)
+    pub languages: BTreeMap<String, BTreeMap<String, String>>,
+    /// Fields to replace, keyed by kernel name; applied after the language's
+    pub kernels: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl SnippetOverrides {
+    /// Replace the fields configured for `snippets.language` and then for
+    /// `kernel_name`, returning the names of the fields replaced.
+    ///
+    /// Fields that aren't snippet fields are ignored; [`load_snippet_overrides`]
+    /// rejects them up front.
+    pub fn apply(&self, kernel_name: &str, snippets: &mut LanguageSnippets) -> Vec<String> {
+        let language = snippets.language.to_lowercase();
+        let fields: Vec<(&String, &String)> = self
+            .languages
+            .iter()
+            .filter(|(name, _)| name.to_lowercase() == language)
+            .chain(self.kernels.get_key_value(kernel_name))
+            .flat_map(|(_, fields)| fields)
+            .collect();
+        if fields.is_empty() {
+            return Vec::new();
+        }
+
+        let Ok(Value::Object(mut merged)) = serde_json::to_value(&*snippets) else {
+            return Vec::new();
+        };
+        let mut replaced = Vec::new();
+        for (field, code) in fields {
+            if is_snippet_field(field) {
+                merged.insert(field.clone(), Value::String(code.clone()));
+                if !replaced.contains(field) {
+                    replaced.push(field.clone());
+                }
+            }
+        }
+        if let Ok(merged) = serde_json::from_value(Value::Object(merged)) {
+            *snippets = merged;
         }
+        replaced.sort();
+        replaced
+    }
+}
+
+/// Whether `field` names a code snippet (`language` is not one).
+fn is_snippet_field(field: &str) -> bool {
+    static FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+    FIELDS
+        .get_or_init(snippet_fields)
+        .iter()
+        .any(|known| known == field)
+}
+
+fn snippet_fields() -> Vec<String> {
+    match serde_json::to_value(LanguageSnippets::fallback("")) {
+        Ok(Value::Object(fields)) => fields
+            .keys()
+            .filter(|key| *key != "language")
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
     }
 }
 
+/// Load a snippet overrides file, rejecting any field that isn't a snippet.
+pub fn load_snippet_overrides(path: &Path) -> Result<SnippetOverrides, SnippetsError> {
+    let content = std::fs::read_to_string(path).map_err(|source| SnippetsError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let overrides: SnippetOverrides =
+        serde_json::from_str(&content).map_err(|e| SnippetsError::Invalid {
+            path: path.to_path_buf(),
+            line: e.line(),
+            column: e.column(),
+            message: without_location(&e.to_string()).to_string(),
+        })?;
+
+    let sections = [
+        ("languages", &overrides.languages),
+        ("kernels", &overrides.kernels),
+    ];
+    for (section, entries) in sections {
+        for (name, fields) in entries {
+            if let Some(field) = fields.keys().find(|field| !is_snippet_field(field)) {
+                return Err(SnippetsError::UnknownField {
+                    path: path.to_path_buf(),
+                    scope: format!("{}.{}", section, name),
+                    field: field.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(overrides)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -207,4 +438,63 @@ mod tests {
             assert!(!snippets.print_hello.is_empty(), "Empty print_hello for {}", lang);
         }
     }
+
+    #[test]
+    fn test_overrides_apply_language_then_kernel() {
+        let overrides: SnippetOverrides = serde_json::from_str(
+            r#"{
+                "languages": { "Python": { "sleep_code": "utime.sleep(2)", "complete_code": "2" } },
+                "kernels": { "micropython": { "complete_code": "3" } }
+            }"#,
+        )
+        .unwrap();
+
+        let mut snippets = LanguageSnippets::for_language("python");
+        let replaced = overrides.apply("micropython", &mut snippets);
+        assert_eq!(replaced, ["complete_code", "sleep_code"]);
+        assert_eq!(snippets.complete_code, "3");
+        assert_eq!(snippets.sleep_code, "utime.sleep(2)");
+        assert_eq!(snippets.print_hello, "print('hello')");
+        assert_eq!(snippets.language, "python");
+
+        let mut snippets = LanguageSnippets::for_language("r");
+        assert!(overrides.apply("ir", &mut snippets).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_override_field_is_named() {
+        let path = std::env::temp_dir().join(format!(
+            "kernel-testbed-snippets-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(
+            &path,
+            r#"{ "kernels": { "micropython": { "print_helo": "print('hello')" } } }"#,
+        )
+        .unwrap();
+
+        let err = load_snippet_overrides(&path).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("`print_helo` in kernels.micropython"),
+            "{}",
+            message
+        );
+
+        std::fs::write(&path, "{\n  \"language\": {}\n}").unwrap();
+        let err = load_snippet_overrides(&path).unwrap_err();
+        assert!(
+            matches!(err, SnippetsError::Invalid { line: 2, .. }),
+            "{}",
+            err
+        );
+        assert!(!err.to_string().contains("at line"), "{}", err);
+
+        let fixed = r#"{ "kernels": { "micropython": { "print_hello": "print(1)" } } }"#;
+        std::fs::write(&path, fixed).unwrap();
+        let overrides = load_snippet_overrides(&path).unwrap();
+        assert_eq!(overrides.kernels["micropython"]["print_hello"], "print(1)");
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
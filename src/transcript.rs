@@ -0,0 +1,815 @@
+//! Recorded message transcripts and offline replay.
+//!
+//! A transcript holds every message the harness observed while testing one
+//! kernel, plus what each test's last attempt sent and received on every
+//! channel. Replaying it re-runs the tests against those recorded exchanges
+//! and re-evaluates the suite finalizers without launching anything, so a
+//! transcript attached to a bug report reproduces the same verdicts on any
+//! machine, and a fixed assertion can be checked against the run that
+//! tripped it.
+
+use crate::channels::{self, Channel, Outgoing, Relay, RelayedChannels};
+use crate::extra_tests::without_location;
+use crate::harness::KernelUnderTest;
+use crate::launch::DEFAULT_TEST_TIMEOUT;
+use crate::snippets::LanguageSnippets;
+use crate::types::duration_millis;
+use jupyter_protocol::{JupyterMessage, KernelInfoReply};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+/// Shortest changed text a replay rewrites in the kernel's messages; a
+/// shorter change (a counter, a digit of a timestamp) is more likely to
+/// turn up by coincidence than a fresh id is
+const MIN_SUBSTITUTION_LEN: usize = 8;
+
+/// Something that can hand the suite the messages a kernel produced.
+pub trait MessageSource {
+    /// Every IOPub message and shell/control reply, in the order it was read
+    fn observed_messages(&self) -> &[JupyterMessage];
+}
+
+impl MessageSource for KernelUnderTest {
+    fn observed_messages(&self) -> &[JupyterMessage] {
+        KernelUnderTest::observed_messages(self)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+/// Path of the transcript for `kernel_name` under `dir`.
+pub fn transcript_path(dir: &Path, kernel_name: &str) -> PathBuf {
+    dir.join(format!("{}.transcript.json", kernel_name))
+}
+
+/// The messages observed during one kernel's run, with enough about the
+/// kernel to label a report built from them and to re-run its tests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub kernel_name: String,
+    pub language: String,
+    pub implementation: String,
+    pub protocol_version: String,
+    /// Run the transcript was recorded in
+    #[serde(default)]
+    pub run_id: String,
+    #[serde(deserialize_with = "deserialize_messages")]
+    pub messages: Vec<JupyterMessage>,
+    /// Whether the harness attached to a kernel it didn't start
+    #[serde(default)]
+    pub attached: bool,
+    /// The kernel_info_reply the kernel started up with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_info: Option<KernelInfoReply>,
+    /// Snippets the tests executed, overrides applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippets: Option<LanguageSnippets>,
+    /// Whether the kernel sent iopub_welcome when the harness subscribed
+    #[serde(default)]
+    pub iopub_welcome_received: bool,
+    /// Per-test timeout the tests waited with
+    #[serde(default = "default_test_timeout", with = "duration_millis")]
+    pub test_timeout: Duration,
+    /// Channel traffic of each test's last attempt, in the order they ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exchanges: Vec<Exchange>,
+}
+
+fn default_test_timeout() -> Duration {
+    DEFAULT_TEST_TIMEOUT
+}
+
+impl Transcript {
+    /// Read a transcript written by [`save`](Self::save).
+    pub fn load(path: &Path) -> Result<Self, TranscriptError> {
+        let content = std::fs::read_to_string(path).map_err(|source| TranscriptError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        serde_json::from_str(&content).map_err(|e| TranscriptError::Invalid {
+            path: path.to_path_buf(),
+            line: e.line(),
+            column: e.column(),
+            message: without_location(&e.to_string()).to_string(),
+        })
+    }
+
+    /// Write the transcript as JSON, creating the parent directory if needed.
+    pub fn save(&self, path: &Path) -> Result<(), TranscriptError> {
+        let io_error = |source| TranscriptError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| io_error(e.into()))?;
+        std::fs::write(path, json).map_err(io_error)
+    }
+
+    /// The exchange recorded for the last attempt at `test`, if it was run.
+    pub fn exchange(&self, test: &str) -> Option<&Exchange> {
+        self.exchanges
+            .iter()
+            .rev()
+            .find(|exchange| exchange.test == test)
+    }
+}
+
+/// What one attempt at a test sent to the kernel and read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    /// Name of the test
+    pub test: String,
+    /// Session id the harness sent its messages under
+    pub session_id: String,
+    /// Sequence number of the last DAP request sent before the test started
+    #[serde(default)]
+    pub debug_seq: u64,
+    pub events: Vec<ChannelEvent>,
+}
+
+/// One message (or heartbeat) crossing a channel during an exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelEvent {
+    /// Microseconds since the attempt started
+    pub at_us: u64,
+    pub direction: Direction,
+    pub channel: Channel,
+    /// The message; None for heartbeats, which carry none
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_message"
+    )]
+    pub message: Option<JupyterMessage>,
+}
+
+/// Which way a [`ChannelEvent`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the harness to the kernel
+    Sent,
+    /// From the kernel to the harness
+    Received,
+}
+
+/// Message content is untagged, so each message has to be decoded by its
+/// header's msg_type rather than by serde's first structural match.
+pub(crate) fn message_from_value(mut value: Value) -> anyhow::Result<JupyterMessage> {
+    // A missing parent is written as `{}`, which from_value won't take
+    if value["parent_header"]
+        .as_object()
+        .is_some_and(|p| p.is_empty())
+    {
+        value["parent_header"] = Value::Null;
+    }
+    JupyterMessage::from_value(value)
+}
+
+fn deserialize_messages<'de, D>(deserializer: D) -> Result<Vec<JupyterMessage>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|value| message_from_value(value).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn deserialize_optional_message<'de, D>(deserializer: D) -> Result<Option<JupyterMessage>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Value>::deserialize(deserializer)?
+        .map(message_from_value)
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Collects the channel traffic of the test attempt in progress.
+///
+/// Clones share one recording, so every channel and the IOPub reader task
+/// can add to it. Nothing is kept while no recording is in progress.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Recorder {
+    recording: Arc<Mutex<Option<Recording>>>,
+}
+
+#[derive(Debug)]
+struct Recording {
+    started: Instant,
+    events: Vec<ChannelEvent>,
+}
+
+impl Recorder {
+    /// Start a new recording, dropping any unfinished one.
+    pub(crate) fn start(&self) {
+        if let Ok(mut recording) = self.recording.lock() {
+            *recording = Some(Recording {
+                started: Instant::now(),
+                events: Vec::new(),
+            });
+        }
+    }
+
+    /// End the recording, returning what it caught.
+    pub(crate) fn finish(&self) -> Vec<ChannelEvent> {
+        match self.recording.lock() {
+            Ok(mut recording) => recording.take().map_or_else(Vec::new, |r| r.events),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Record `message` (None for a heartbeat) going out on `channel`.
+    pub(crate) fn sent(&self, channel: Channel, message: Option<&JupyterMessage>) {
+        self.record(Direction::Sent, channel, message);
+    }
+
+    /// Record `message` (None for a heartbeat) coming in on `channel`.
+    pub(crate) fn received(&self, channel: Channel, message: Option<&JupyterMessage>) {
+        self.record(Direction::Received, channel, message);
+    }
+
+    fn record(&self, direction: Direction, channel: Channel, message: Option<&JupyterMessage>) {
+        let Ok(mut recording) = self.recording.lock() else {
+            return;
+        };
+        if let Some(recording) = recording.as_mut() {
+            recording.events.push(ChannelEvent {
+                at_us: recording.started.elapsed().as_micros() as u64,
+                direction,
+                channel,
+                message: message.cloned(),
+            });
+        }
+    }
+}
+
+/// A kernel stand-in that serves a recorded transcript instead of talking
+/// to a process.
+#[derive(Debug, Clone)]
+pub struct ReplayKernel {
+    transcript: Transcript,
+}
+
+impl ReplayKernel {
+    pub fn new(transcript: Transcript) -> Self {
+        Self { transcript }
+    }
+
+    /// Load the transcript at `path`.
+    pub fn load(path: &Path) -> Result<Self, TranscriptError> {
+        Transcript::load(path).map(Self::new)
+    }
+
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+}
+
+impl MessageSource for ReplayKernel {
+    fn observed_messages(&self) -> &[JupyterMessage] {
+        &self.transcript.messages
+    }
+}
+
+/// Serves a transcript's exchanges to tests re-running against it.
+pub(crate) struct ReplaySession {
+    transcript: Arc<Transcript>,
+    /// Why the exchange being served stopped matching what its test sent
+    diverged: Arc<Mutex<Option<String>>>,
+}
+
+impl ReplaySession {
+    pub(crate) fn new(transcript: Arc<Transcript>) -> Self {
+        Self {
+            transcript,
+            diverged: Arc::default(),
+        }
+    }
+
+    pub(crate) fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// Serve `exchange` (or nothing, for a test that wasn't recorded) on
+    /// fresh channels for `session_id`.
+    ///
+    /// Each message the test sends is matched against the next one
+    /// recorded, and what the kernel sent after that is delivered with its
+    /// recorded timing, rewritten to the ids the test generated this time.
+    pub(crate) fn serve(
+        &mut self,
+        exchange: Option<&Exchange>,
+        session_id: &str,
+    ) -> RelayedChannels {
+        self.diverged = Arc::default();
+        let diverged = self.diverged.clone();
+        let events = exchange.map_or_else(Vec::new, |exchange| exchange.events.clone());
+        channels::relay(session_id, Recorder::default(), move |relay| {
+            tokio::spawn(serve_events(events, relay, diverged))
+        })
+    }
+
+    /// Why the test being served stopped matching its recorded exchange,
+    /// if it did.
+    pub(crate) fn divergence(&self) -> Option<String> {
+        self.diverged.lock().ok()?.clone()
+    }
+}
+
+/// Play `events` to the harness at the other end of `relay`, in step with
+/// what it sends.
+///
+/// On the first send that doesn't match the recording, the reason is left
+/// in `diverged` and the relay is dropped, which closes every channel so
+/// the test fails fast instead of waiting out its timeouts.
+async fn serve_events(
+    events: Vec<ChannelEvent>,
+    mut relay: Relay,
+    diverged: Arc<Mutex<Option<String>>>,
+) {
+    let recorded_any = !events.is_empty();
+    let mut substitutions = Substitutions::default();
+    // When the last matched send went out, in this run and in the recording
+    let mut anchor = (Instant::now(), Duration::ZERO);
+    let mut heartbeats: VecDeque<oneshot::Sender<()>> = VecDeque::new();
+    let diverge = |reason: String| {
+        if let Ok(mut diverged) = diverged.lock() {
+            diverged.get_or_insert(reason);
+        }
+    };
+
+    for event in events {
+        let at = Duration::from_micros(event.at_us);
+        match event.direction {
+            Direction::Received => {
+                let due = anchor.0 + at.saturating_sub(anchor.1);
+                tokio::time::sleep_until(due.into()).await;
+                match event.message {
+                    Some(message) => {
+                        if !relay.deliver(event.channel, substitutions.apply(message)) {
+                            return;
+                        }
+                    }
+                    None => {
+                        if let Some(echo) = heartbeats.pop_front() {
+                            let _ = echo.send(());
+                        }
+                    }
+                }
+            }
+            Direction::Sent => {
+                let Some((sent_at, outgoing)) = relay.outgoing.recv().await else {
+                    return;
+                };
+                match (outgoing, &event.message) {
+                    (Outgoing::Message(channel, message), Some(recorded))
+                        if channel == event.channel
+                            && message.message_type() == recorded.message_type() =>
+                    {
+                        substitutions.learn(recorded, &message);
+                    }
+                    (Outgoing::Heartbeat(echo), None) if event.channel == Channel::Heartbeat => {
+                        heartbeats.push_back(echo);
+                    }
+                    (outgoing, recorded) => {
+                        diverge(format!(
+                            "the test sent {} where the transcript has {}",
+                            describe_outgoing(&outgoing),
+                            describe_event(event.channel, recorded.as_ref())
+                        ));
+                        return;
+                    }
+                }
+                anchor = (sent_at, at);
+            }
+        }
+    }
+
+    // Nothing recorded answers whatever the test sends from here on
+    if let Some((_, outgoing)) = relay.outgoing.recv().await {
+        diverge(if recorded_any {
+            format!(
+                "the test sent {} after everything the transcript recorded",
+                describe_outgoing(&outgoing)
+            )
+        } else {
+            "the transcript has no exchange recorded for this test".to_string()
+        });
+    }
+}
+
+fn describe_outgoing(outgoing: &Outgoing) -> String {
+    match outgoing {
+        Outgoing::Message(channel, message) => describe_event(*channel, Some(message)),
+        Outgoing::Heartbeat(_) => describe_event(Channel::Heartbeat, None),
+    }
+}
+
+fn describe_event(channel: Channel, message: Option<&JupyterMessage>) -> String {
+    match message {
+        Some(message) => format!("{} on {}", message.message_type(), channel),
+        None => format!("a {} ping", channel),
+    }
+}
+
+/// Text a re-run test generated afresh (msg_ids, comm ids, unique markers
+/// in code), paired with what it was when the transcript was recorded.
+#[derive(Debug, Default)]
+struct Substitutions {
+    pairs: Vec<(String, String)>,
+}
+
+impl Substitutions {
+    /// Learn what changed between the recorded request and the one the
+    /// test sent in its place.
+    fn learn(&mut self, recorded: &JupyterMessage, sent: &JupyterMessage) {
+        self.learn_text(&recorded.header.msg_id, &sent.header.msg_id);
+        if let (Ok(recorded), Ok(sent)) = (
+            serde_json::to_value(&recorded.content),
+            serde_json::to_value(&sent.content),
+        ) {
+            self.learn_value(&recorded, &sent);
+        }
+    }
+
+    fn learn_value(&mut self, recorded: &Value, sent: &Value) {
+        match (recorded, sent) {
+            (Value::String(recorded), Value::String(sent)) => self.learn_text(recorded, sent),
+            (Value::Array(recorded), Value::Array(sent)) => {
+                for (recorded, sent) in recorded.iter().zip(sent) {
+                    self.learn_value(recorded, sent);
+                }
+            }
+            (Value::Object(recorded), Value::Object(sent)) => {
+                for (key, recorded) in recorded {
+                    if let Some(sent) = sent.get(key) {
+                        self.learn_value(recorded, sent);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn learn_text(&mut self, recorded: &str, sent: &str) {
+        let (recorded, sent) = changed_parts(recorded, sent);
+        if recorded.len() >= MIN_SUBSTITUTION_LEN && !self.pairs.iter().any(|(r, _)| r == recorded)
+        {
+            self.pairs.push((recorded.to_string(), sent.to_string()));
+        }
+    }
+
+    /// `message` with everything learned so far rewritten to this run's text.
+    fn apply(&self, message: JupyterMessage) -> JupyterMessage {
+        if self.pairs.is_empty() {
+            return message;
+        }
+        let Ok(mut value) = serde_json::to_value(&message) else {
+            return message;
+        };
+        self.rewrite(&mut value);
+        message_from_value(value).unwrap_or(message)
+    }
+
+    fn rewrite(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                for (recorded, sent) in &self.pairs {
+                    if text.contains(recorded.as_str()) {
+                        *text = text.replace(recorded.as_str(), sent);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.rewrite(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.rewrite(field)),
+            _ => {}
+        }
+    }
+}
+
+/// What differs between two strings once their common prefix and suffix
+/// are set aside, e.g. the two uuids in `comm-<uuid>`.
+fn changed_parts<'a>(a: &'a str, b: &'a str) -> (&'a str, &'a str) {
+    let prefix: usize = a
+        .chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix: usize = a
+        .chars()
+        .rev()
+        .zip(b.chars().rev())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+    (&a[..a.len() - suffix], &b[..b.len() - suffix])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{replay_suite, TestRegistry};
+    use crate::tests::{all_finalizers, all_tests};
+    use crate::types::{TestCategory, TestResult};
+    use jupyter_protocol::{
+        CommId, CommOpen, ExecuteReply, ExecuteRequest, ExecutionState, JupyterMessageContent,
+        KernelInfoRequest, ShutdownReply, Status, StreamContent,
+    };
+
+    fn transcript() -> Transcript {
+        let request: JupyterMessage = ExecuteRequest::new("print('hi')".to_string()).into();
+        Transcript {
+            kernel_name: "python3".to_string(),
+            language: "python".to_string(),
+            implementation: "ipykernel".to_string(),
+            protocol_version: "5.3".to_string(),
+            run_id: "run-1".to_string(),
+            messages: vec![
+                JupyterMessage::new(StreamContent::stdout("hi\n"), Some(&request)),
+                JupyterMessage::new(ShutdownReply::default(), None),
+            ],
+            attached: false,
+            kernel_info: None,
+            snippets: None,
+            iopub_welcome_received: true,
+            test_timeout: Duration::from_secs(2),
+            exchanges: Vec::new(),
+        }
+    }
+
+    fn event(direction: Direction, channel: Channel, message: JupyterMessage) -> ChannelEvent {
+        ChannelEvent {
+            at_us: 0,
+            direction,
+            channel,
+            message: Some(message),
+        }
+    }
+
+    /// What executing print("hello") exchanged with a kernel that printed
+    /// `output`.
+    fn execute_exchange(test: &str, output: &str) -> Exchange {
+        let request: JupyterMessage = ExecuteRequest::new("print(\"hello\")".to_string()).into();
+        let status = |execution_state| {
+            let status = JupyterMessage::new(Status { execution_state }, Some(&request));
+            event(Direction::Received, Channel::IoPub, status)
+        };
+        let stream = JupyterMessage::new(StreamContent::stdout(output), Some(&request));
+        let reply = JupyterMessage::new(ExecuteReply::default(), Some(&request));
+        Exchange {
+            test: test.to_string(),
+            session_id: "session-1".to_string(),
+            debug_seq: 0,
+            events: vec![
+                event(Direction::Sent, Channel::Shell, request.clone()),
+                status(ExecutionState::Busy),
+                event(Direction::Received, Channel::IoPub, stream),
+                status(ExecutionState::Idle),
+                event(Direction::Received, Channel::Shell, reply),
+            ],
+        }
+    }
+
+    fn registry(names: &[&str]) -> TestRegistry {
+        let mut registry = TestRegistry::new();
+        registry.extend(
+            all_tests()
+                .into_iter()
+                .filter(|test| names.contains(&test.name.as_str())),
+        );
+        registry
+    }
+
+    #[test]
+    fn test_transcript_round_trip_keeps_message_types() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "kernel-testbed-transcript-{}",
+                uuid::Uuid::new_v4()
+            ))
+            .join("python3.transcript.json");
+        let mut transcript = transcript();
+        transcript
+            .exchanges
+            .push(execute_exchange("execute_stdout", "hello\n"));
+        transcript.save(&path).unwrap();
+
+        let replay = ReplayKernel::load(&path).unwrap();
+        assert_eq!(replay.transcript().kernel_name, "python3");
+        assert_eq!(replay.transcript().run_id, "run-1");
+        let messages = replay.observed_messages();
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(
+            &messages[0].content,
+            JupyterMessageContent::StreamContent(stream) if stream.text == "hi\n"
+        ));
+        assert!(messages[0].parent_header.is_some());
+        assert!(matches!(
+            messages[1].content,
+            JupyterMessageContent::ShutdownReply(ShutdownReply { restart: false, .. })
+        ));
+        let exchange = replay.transcript().exchange("execute_stdout").unwrap();
+        assert_eq!(exchange.events.len(), 5);
+        assert!(matches!(
+            exchange.events[0].message.as_ref().unwrap().content,
+            JupyterMessageContent::ExecuteRequest(_)
+        ));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_replay_runs_finalizers_for_selected_tiers() {
+        let replay = ReplayKernel::new(transcript());
+        let report = replay_suite(
+            &replay,
+            &[TestCategory::Tier1Basic],
+            &TestRegistry::new(),
+            &all_finalizers(),
+            &[],
+            "run-2",
+        )
+        .await;
+        assert_eq!(report.kernel_name, "python3");
+        assert_eq!(report.kernel_launches, 0);
+        let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["shutdown_reply_restart"]);
+        assert!(matches!(report.results[0].result, TestResult::Pass));
+    }
+
+    #[tokio::test]
+    async fn test_replay_reevaluates_test_assertions() {
+        let heartbeat = Exchange {
+            test: "heartbeat_responds".to_string(),
+            session_id: "session-1".to_string(),
+            debug_seq: 0,
+            events: [Direction::Sent, Direction::Received]
+                .into_iter()
+                .map(|direction| ChannelEvent {
+                    at_us: 0,
+                    direction,
+                    channel: Channel::Heartbeat,
+                    message: None,
+                })
+                .collect(),
+        };
+        let replay = |output: &str| {
+            let mut transcript = transcript();
+            transcript.exchanges = vec![
+                heartbeat.clone(),
+                execute_exchange("execute_stdout", output),
+            ];
+            ReplayKernel::new(transcript)
+        };
+        let registry = registry(&["heartbeat_responds", "execute_stdout"]);
+        let tiers = [TestCategory::Tier1Basic];
+
+        let report = replay_suite(&replay("hello\n"), &tiers, &registry, &[], &[], "run-2").await;
+        let results: Vec<_> = report.results.iter().map(|r| &r.result).collect();
+        assert!(
+            matches!(results[..], [TestResult::Pass, TestResult::Pass]),
+            "{:?}",
+            results
+        );
+
+        // The same exchange with other output fails the same check
+        let report = replay_suite(&replay("goodbye\n"), &tiers, &registry, &[], &[], "run-2").await;
+        assert!(
+            report.results[1].result.is_failure(),
+            "{:?}",
+            report.results[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_skips_tests_that_diverge() {
+        let mut transcript = transcript();
+        let mut exchange = execute_exchange("execute_stdout", "hello\n");
+        exchange.events[0].message = Some(KernelInfoRequest {}.into());
+        transcript.exchanges.push(exchange);
+        let replay = ReplayKernel::new(transcript);
+
+        let started = Instant::now();
+        let report = replay_suite(
+            &replay,
+            &[TestCategory::Tier1Basic],
+            &registry(&["execute_stdout", "execute_reply_ok"]),
+            &[],
+            &[],
+            "run-2",
+        )
+        .await;
+        // Closed channels end both tests well before their timeouts
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let reason = |name: &str| match &report
+            .results
+            .iter()
+            .find(|r| r.name == name)
+            .unwrap()
+            .result
+        {
+            TestResult::Skipped { reason } => reason.clone(),
+            other => panic!("{} wasn't skipped: {:?}", name, other),
+        };
+        let diverged = reason("execute_stdout");
+        assert!(
+            diverged.contains("execute_request on shell"),
+            "{}",
+            diverged
+        );
+        assert!(
+            diverged.contains("kernel_info_request on shell"),
+            "{}",
+            diverged
+        );
+        let missing = reason("execute_reply_ok");
+        assert!(missing.contains("no exchange recorded"), "{}", missing);
+    }
+
+    #[test]
+    fn test_substitutions_follow_fresh_ids() {
+        let comm = |id: &str| -> JupyterMessage {
+            CommOpen {
+                comm_id: CommId(format!("test-comm-{}", id)),
+                target_name: "jupyter.kernel_testbed.test".to_string(),
+                data: Default::default(),
+                target_module: None,
+            }
+            .into()
+        };
+        let recorded = comm("9f3a1c2e-old");
+        let sent = comm("47b0d6aa-new");
+        let mut substitutions = Substitutions::default();
+        substitutions.learn(&recorded, &sent);
+
+        let close = JupyterMessage::new(
+            jupyter_protocol::CommClose {
+                comm_id: CommId("test-comm-9f3a1c2e-old".to_string()),
+                data: Default::default(),
+            },
+            Some(&recorded),
+        );
+        let close = substitutions.apply(close);
+        assert_eq!(close.parent_header.unwrap().msg_id, sent.header.msg_id);
+        match close.content {
+            JupyterMessageContent::CommClose(close) => {
+                assert_eq!(close.comm_id.0, "test-comm-47b0d6aa-new")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_changed_parts() {
+        assert_eq!(changed_parts("final_abc1", "final_xyz1"), ("abc", "xyz"));
+        assert_eq!(changed_parts("same", "same"), ("", ""));
+        assert_eq!(changed_parts("é1", "é2"), ("1", "2"));
+    }
+
+    #[test]
+    fn test_invalid_transcript_reports_location() {
+        let path = std::env::temp_dir().join(format!(
+            "kernel-testbed-transcript-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, "{\"kernel_name\": 3}").unwrap();
+
+        let err = ReplayKernel::load(&path).unwrap_err();
+        assert!(
+            matches!(err, TranscriptError::Invalid { line: 1, .. }),
+            "{}",
+            err
+        );
+        assert!(!err.to_string().contains("at line"), "{}", err);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
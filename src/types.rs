@@ -2,6 +2,10 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
 /// Classification of why a test failed, to help identify root cause.
@@ -20,6 +24,8 @@ pub enum FailureKind {
     KernelError,
     /// Test harness or setup issue
     HarnessError,
+    /// The kernel process exited mid-suite, with this exit status or signal
+    KernelDied { status: String },
 }
 
 impl FailureKind {
@@ -31,6 +37,7 @@ impl FailureKind {
             FailureKind::UnexpectedContent => "Response format differs from spec. Check kernel implementation.",
             FailureKind::KernelError => "Kernel reported an error. Check kernel logs for details.",
             FailureKind::HarnessError => "Test harness issue. Check test setup and dependencies.",
+            FailureKind::KernelDied { .. } => "Kernel process exited. Check its stderr.",
         }
     }
 
@@ -42,6 +49,7 @@ impl FailureKind {
             FailureKind::UnexpectedContent => "kernel",
             FailureKind::KernelError => "kernel",
             FailureKind::HarnessError => "testbed",
+            FailureKind::KernelDied { .. } => "kernel",
         }
     }
 }
@@ -61,15 +69,31 @@ pub enum TestCategory {
     /// Advanced features: stdin, comms, interrupt, debug
     #[serde(rename = "tier4_advanced")]
     Tier4Advanced,
+    /// Opt-in performance measurements, not part of any tier
+    #[serde(rename = "benchmark")]
+    Benchmark,
 }
 
 impl TestCategory {
+    /// Parse a tier number (1-4) as used on the command line and in spec files.
+    pub fn from_tier_number(n: u8) -> Option<Self> {
+        match n {
+            1 => Some(TestCategory::Tier1Basic),
+            2 => Some(TestCategory::Tier2Interactive),
+            3 => Some(TestCategory::Tier3RichOutput),
+            4 => Some(TestCategory::Tier4Advanced),
+            _ => None,
+        }
+    }
+
+    /// Tier number for sorting; benchmarks sort after every tier.
     pub fn tier_number(&self) -> u8 {
         match self {
             TestCategory::Tier1Basic => 1,
             TestCategory::Tier2Interactive => 2,
             TestCategory::Tier3RichOutput => 3,
             TestCategory::Tier4Advanced => 4,
+            TestCategory::Benchmark => 5,
         }
     }
 
@@ -79,10 +103,69 @@ impl TestCategory {
             TestCategory::Tier2Interactive => "Interactive Features",
             TestCategory::Tier3RichOutput => "Rich Output",
             TestCategory::Tier4Advanced => "Advanced Features",
+            TestCategory::Benchmark => "Benchmarks",
         }
     }
 }
 
+/// A minimum score for one tier, written as `tier1=100` (or `1=100`).
+///
+/// Scores are percentages where PartialPass counts for its fractional score
+/// and Unsupported tests are left out entirely.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TierRequirement {
+    /// Tier the requirement applies to
+    pub tier: TestCategory,
+    /// Minimum score, 0-100
+    pub min_percent: f32,
+}
+
+impl FromStr for TierRequirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected tierN=PERCENT, got {:?}", s))?;
+        let tier = key
+            .trim()
+            .trim_start_matches("tier")
+            .parse()
+            .ok()
+            .and_then(TestCategory::from_tier_number)
+            .ok_or_else(|| format!("unknown tier {:?}, expected tier1-tier4", key))?;
+        let min_percent: f32 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid percentage {:?}", value))?;
+        if !(0.0..=100.0).contains(&min_percent) {
+            return Err(format!("percentage {} is outside 0-100", min_percent));
+        }
+        Ok(Self { tier, min_percent })
+    }
+}
+
+impl TryFrom<String> for TierRequirement {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for TierRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tier{}={}", self.tier.tier_number(), self.min_percent)
+    }
+}
+
+impl From<TierRequirement> for String {
+    fn from(requirement: TierRequirement) -> Self {
+        requirement.to_string()
+    }
+}
+
 /// Result of a single test execution.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "status", rename_all = "snake_case")]
@@ -97,6 +180,8 @@ pub enum TestResult {
     },
     /// Kernel explicitly doesn't support this feature
     Unsupported,
+    /// The harness never ran the test (e.g. the run was interrupted)
+    Skipped { reason: String },
     /// Kernel didn't respond within timeout
     Timeout,
     /// Partial success with notes
@@ -129,6 +214,16 @@ impl TestResult {
         }
     }
 
+    /// Whether this is a failure or timeout, i.e. worth retrying.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, TestResult::Fail { .. } | TestResult::Timeout)
+    }
+
+    /// Whether the kernel process exited before or during the test.
+    pub fn is_kernel_died(&self) -> bool {
+        matches!(self.failure_kind(), Some(FailureKind::KernelDied { .. }))
+    }
+
     pub fn is_pass(&self) -> bool {
         matches!(self, TestResult::Pass | TestResult::PartialPass { .. })
     }
@@ -138,6 +233,7 @@ impl TestResult {
             TestResult::Pass => "PASS",
             TestResult::Fail { .. } => "FAIL",
             TestResult::Unsupported => "SKIP",
+            TestResult::Skipped { .. } => "SKIP",
             TestResult::Timeout => "TIME",
             TestResult::PartialPass { .. } => "PART",
         }
@@ -148,6 +244,7 @@ impl TestResult {
             TestResult::Pass => "✅",
             TestResult::Fail { .. } => "❌",
             TestResult::Unsupported => "⏭️",
+            TestResult::Skipped { .. } => "⏭️",
             TestResult::Timeout => "⏱️",
             TestResult::PartialPass { .. } => "⚠️",
         }
@@ -170,6 +267,56 @@ pub struct TestRecord {
     /// How long the test took
     #[serde(with = "duration_millis")]
     pub duration: Duration,
+    /// Whether this test came from a user-supplied spec rather than the built-in suite
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub custom: bool,
+    /// Whether this record only reports a measurement, such as startup
+    /// time, and so never counts toward scores or requirements
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub informational: bool,
+    /// Measurements recorded by benchmark tests (e.g., "p95_ms")
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    /// Why a benchmark that still passed was flagged as slow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<String>,
+    /// A shell reply without parent_header was accepted during this test
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub missing_parent_header: bool,
+    /// Last lines of kernel stderr, attached to timeouts and kernel errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_log_excerpt: Option<String>,
+    /// Outcome of every attempt, in order, when the test was retried; empty
+    /// if it ran once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<TestResult>,
+}
+
+impl TestRecord {
+    /// How many times the test ran.
+    pub fn attempt_count(&self) -> usize {
+        self.attempts.len().max(1)
+    }
+}
+
+/// When the suite relaunches the kernel under test.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsolationMode {
+    /// One kernel for the whole run
+    #[default]
+    Shared,
+    /// A fresh kernel for each tier
+    PerTier,
+}
+
+impl fmt::Display for IsolationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsolationMode::Shared => write!(f, "shared"),
+            IsolationMode::PerTier => write!(f, "per-tier"),
+        }
+    }
 }
 
 /// Report for a single kernel's conformance test run.
@@ -185,25 +332,154 @@ pub struct KernelReport {
     pub protocol_version: String,
     /// Individual test results
     pub results: Vec<TestRecord>,
+    /// Identifier shared by every report produced in one invocation
+    #[serde(default)]
+    pub run_id: String,
     /// When the test run started
+    #[serde(with = "utc_rfc3339")]
     pub timestamp: DateTime<Utc>,
     /// Total duration of test run
     #[serde(with = "duration_millis")]
     pub total_duration: Duration,
+    /// Time from spawning the kernel to its first kernel_info_reply
+    /// (zero if it never replied)
+    #[serde(default, with = "duration_millis")]
+    pub startup_duration: Duration,
+    /// How long each warm-up execution took, cold first, before any test ran
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "durations_millis"
+    )]
+    pub warmup_durations: Vec<Duration>,
+    /// The suite attached to a kernel it didn't start, so shutdown and
+    /// relaunch tests may have been skipped
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub attached: bool,
+    /// When the kernel was relaunched during the run
+    #[serde(default)]
+    pub isolation_mode: IsolationMode,
+    /// How many times the kernel was launched (or attached to)
+    #[serde(default = "one")]
+    pub kernel_launches: u32,
+    /// Environment overrides the kernel was launched with (empty = unset)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Working directory the kernel was launched in (the first launch's, if
+    /// it was relaunched)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Snippet fields replaced by a snippet overrides file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snippet_overrides: Vec<String>,
     /// Error that prevented tests from running (e.g., kernel startup failed)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub startup_error: Option<String>,
+    /// Per-tier requirements this report was judged against
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<TierRequirement>,
+    /// State-leakage diagnostics, present when isolation checking was enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<IsolationReport>,
+    /// Memory and CPU the kernel process used, present when resources were measured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+    /// The run was interrupted (e.g. Ctrl-C); tests it never got to are skipped
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    /// The suite timeout ran out; tests it never got to are skipped
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// The kernel process exited mid-suite; tests it never got to are skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_crash: Option<KernelCrash>,
+    /// How the kernel process ended when the suite shut it down; None for an
+    /// attached kernel or one that was never started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_exit: Option<KernelExitStatus>,
+}
+
+/// How the kernel process ended at the end of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelExitStatus {
+    /// The process exited on its own with this code
+    CleanExit(i32),
+    /// The process was terminated by this signal, not by the harness
+    Signaled(i32),
+    /// The harness had to kill the process
+    ForceKilled,
+    /// The process was still running when the harness last checked
+    StillRunning,
+    /// The process is gone, but how it ended couldn't be determined
+    Unknown,
+}
+
+impl KernelExitStatus {
+    /// Whether the process ended the way a healthy kernel should: exiting
+    /// with code 0 on its own.
+    pub fn is_clean(&self) -> bool {
+        *self == KernelExitStatus::CleanExit(0)
+    }
+}
+
+impl fmt::Display for KernelExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelExitStatus::CleanExit(code) => write!(f, "exited with code {}", code),
+            KernelExitStatus::Signaled(signal) => write!(f, "terminated by signal {}", signal),
+            KernelExitStatus::ForceKilled => write!(f, "killed by the harness"),
+            KernelExitStatus::StillRunning => write!(f, "still running after shutdown"),
+            KernelExitStatus::Unknown => write!(f, "exit status unknown"),
+        }
+    }
+}
+
+/// When and how the kernel process exited during a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KernelCrash {
+    /// Tests recorded before the one that found the kernel gone
+    pub after_tests: usize,
+    /// Exit status or signal, as the OS describes it
+    pub status: String,
+}
+
+/// Memory and CPU used by the kernel process, sampled during the run.
+///
+/// Only the kernel's own process is counted, not any children it spawns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Highest resident set size seen, in bytes
+    pub peak_rss_bytes: u64,
+    /// Resident set size at the last sample, in bytes
+    pub final_rss_bytes: u64,
+    /// User plus system CPU time, summed over every launch, in seconds
+    pub cpu_seconds: f64,
+}
+
+/// Which tests changed kernel state, as seen by the state fingerprint snippet.
+///
+/// Purely diagnostic: this never affects pass/fail.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IsolationReport {
+    /// Tests after which the fingerprint differed from before, in run order
+    pub state_mutating_tests: Vec<String>,
+    /// Why checking stopped early or never started, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 impl KernelReport {
     /// Create a report for a kernel that failed during startup.
     pub fn new_failed_at_startup(
+        run_id: String,
         kernel_name: String,
         language: String,
         error: String,
         total_duration: Duration,
     ) -> Self {
         Self {
+            run_id,
             kernel_name,
             language,
             implementation: "unknown".to_string(),
@@ -215,10 +491,32 @@ impl KernelReport {
                 message_type: "kernel_info_request".to_string(),
                 result: TestResult::fail(&error, FailureKind::ProtocolError),
                 duration: total_duration,
+                custom: false,
+                informational: false,
+                metrics: BTreeMap::new(),
+                flagged: None,
+                missing_parent_header: false,
+                kernel_log_excerpt: None,
+                attempts: Vec::new(),
             }],
             timestamp: Utc::now(),
             total_duration,
+            startup_duration: Duration::ZERO,
+            warmup_durations: Vec::new(),
+            env: BTreeMap::new(),
+            cwd: None,
+            snippet_overrides: Vec::new(),
+            attached: false,
+            isolation_mode: IsolationMode::Shared,
+            kernel_launches: 1,
             startup_error: Some(error),
+            requirements: Vec::new(),
+            isolation: None,
+            resources: None,
+            interrupted: false,
+            timed_out: false,
+            kernel_crash: None,
+            kernel_exit: None,
         }
     }
 
@@ -229,12 +527,17 @@ impl KernelReport {
 
     /// Count of passed tests
     pub fn passed(&self) -> usize {
-        self.results.iter().filter(|r| r.result.is_pass()).count()
+        self.scored_results().filter(|r| r.result.is_pass()).count()
     }
 
     /// Total number of tests run
     pub fn total(&self) -> usize {
-        self.results.len()
+        self.scored_results().count()
+    }
+
+    /// Results that count toward scores, i.e. all but informational ones
+    fn scored_results(&self) -> impl Iterator<Item = &TestRecord> {
+        self.results.iter().filter(|r| !r.informational)
     }
 
     /// Score as a fraction
@@ -246,14 +549,55 @@ impl KernelReport {
         }
     }
 
-    /// Get results for a specific tier
+    /// Get built-in results for a specific tier
     pub fn tier_results(&self, tier: TestCategory) -> Vec<&TestRecord> {
-        self.results.iter().filter(|r| r.category == tier).collect()
+        self.results
+            .iter()
+            .filter(|r| r.category == tier && !r.custom)
+            .collect()
+    }
+
+    /// Get results from user-supplied extra tests
+    pub fn custom_results(&self) -> Vec<&TestRecord> {
+        self.results.iter().filter(|r| r.custom).collect()
+    }
+
+    /// Tier score as a percentage for requirement gating.
+    ///
+    /// PartialPass counts for its fractional score and Unsupported tests are
+    /// excluded. Returns None when the tier has no scored tests.
+    pub fn tier_percent(&self, tier: TestCategory) -> Option<f32> {
+        let credits: Vec<f32> = self
+            .tier_results(tier)
+            .iter()
+            .filter(|r| !r.informational)
+            .filter_map(|r| match &r.result {
+                TestResult::Pass => Some(1.0),
+                TestResult::PartialPass { score, .. } => Some(*score),
+                TestResult::Fail { .. } | TestResult::Timeout => Some(0.0),
+                TestResult::Unsupported | TestResult::Skipped { .. } => None,
+            })
+            .collect();
+        if credits.is_empty() {
+            None
+        } else {
+            Some(credits.iter().sum::<f32>() * 100.0 / credits.len() as f32)
+        }
+    }
+
+    /// Requirements that were not met, with the tier's actual percentage.
+    pub fn unmet_requirements(&self) -> Vec<(&TierRequirement, Option<f32>)> {
+        self.requirements
+            .iter()
+            .map(|req| (req, self.tier_percent(req.tier)))
+            .filter(|(req, actual)| actual.is_none_or(|p| p < req.min_percent))
+            .collect()
     }
 
     /// Tier score as "passed/total"
     pub fn tier_score(&self, tier: TestCategory) -> (usize, usize) {
-        let tier_results = self.tier_results(tier);
+        let mut tier_results = self.tier_results(tier);
+        tier_results.retain(|r| !r.informational);
         let passed = tier_results.iter().filter(|r| r.result.is_pass()).count();
         (passed, tier_results.len())
     }
@@ -264,18 +608,36 @@ impl KernelReport {
 pub struct ConformanceMatrix {
     /// Reports from each kernel tested
     pub reports: Vec<KernelReport>,
+    /// Identifier of the run that produced this matrix
+    #[serde(default)]
+    pub run_id: String,
     /// When the matrix was generated
+    #[serde(with = "utc_rfc3339")]
     pub generated_at: DateTime<Utc>,
 }
 
 impl ConformanceMatrix {
-    pub fn new(reports: Vec<KernelReport>) -> Self {
+    pub fn new(run_id: String, reports: Vec<KernelReport>) -> Self {
         Self {
             reports,
+            run_id,
             generated_at: Utc::now(),
         }
     }
 
+    /// Get all unique benchmark metrics as (test name, metric name) pairs
+    pub fn all_benchmark_metrics(&self) -> Vec<(&str, &str)> {
+        let mut metrics: Vec<(&str, &str)> = self
+            .reports
+            .iter()
+            .flat_map(|r| r.tier_results(TestCategory::Benchmark))
+            .flat_map(|t| t.metrics.keys().map(|m| (t.name.as_str(), m.as_str())))
+            .collect();
+        metrics.sort();
+        metrics.dedup();
+        metrics
+    }
+
     /// Get all unique test names across all reports
     pub fn all_test_names(&self) -> Vec<&str> {
         let mut names: Vec<&str> = self
@@ -290,7 +652,7 @@ impl ConformanceMatrix {
 }
 
 /// Serde helper for Duration as milliseconds
-mod duration_millis {
+pub(crate) mod duration_millis {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use std::time::Duration;
 
@@ -309,3 +671,281 @@ mod duration_millis {
         Ok(Duration::from_millis(millis))
     }
 }
+
+/// Serde helper for a list of Durations as milliseconds
+mod durations_millis {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S>(durations: &[Duration], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        durations
+            .iter()
+            .map(Duration::as_millis)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let millis = Vec::<u64>::deserialize(deserializer)?;
+        Ok(millis.into_iter().map(Duration::from_millis).collect())
+    }
+}
+
+/// Default for counts that are at least one, e.g. kernel launches in
+/// reports written before they were recorded.
+fn one() -> u32 {
+    1
+}
+
+/// Serde helper for timestamps as UTC RFC3339 with second precision.
+///
+/// Deserialization accepts any RFC3339 offset and normalizes it to UTC.
+mod utc_rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(timestamp: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        timestamp
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&s)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Generate a fresh run identifier.
+pub fn new_run_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(category: TestCategory, result: TestResult) -> TestRecord {
+        TestRecord {
+            name: "t".to_string(),
+            category,
+            description: String::new(),
+            message_type: String::new(),
+            result,
+            duration: Duration::ZERO,
+            custom: false,
+            informational: false,
+            metrics: BTreeMap::new(),
+            flagged: None,
+            missing_parent_header: false,
+            kernel_log_excerpt: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    fn report(results: Vec<TestRecord>, requirements: &[&str]) -> KernelReport {
+        KernelReport {
+            run_id: String::new(),
+            kernel_name: "k".to_string(),
+            language: String::new(),
+            implementation: String::new(),
+            protocol_version: String::new(),
+            results,
+            timestamp: Utc::now(),
+            total_duration: Duration::ZERO,
+            startup_duration: Duration::ZERO,
+            warmup_durations: Vec::new(),
+            env: BTreeMap::new(),
+            cwd: None,
+            snippet_overrides: Vec::new(),
+            attached: false,
+            isolation_mode: IsolationMode::Shared,
+            kernel_launches: 1,
+            startup_error: None,
+            requirements: requirements.iter().map(|r| r.parse().unwrap()).collect(),
+            isolation: None,
+            resources: None,
+            interrupted: false,
+            timed_out: false,
+            kernel_crash: None,
+            kernel_exit: None,
+        }
+    }
+
+    #[test]
+    fn test_tier_percent_excludes_unsupported_and_weights_partial() {
+        let report = report(
+            vec![
+                record(TestCategory::Tier2Interactive, TestResult::Pass),
+                record(
+                    TestCategory::Tier2Interactive,
+                    TestResult::PartialPass { score: 0.5, notes: String::new() },
+                ),
+                record(TestCategory::Tier2Interactive, TestResult::Unsupported),
+                record(
+                    TestCategory::Tier2Interactive,
+                    TestResult::Skipped { reason: "interrupted".to_string() },
+                ),
+                record(TestCategory::Tier2Interactive, TestResult::fail("x", FailureKind::KernelError)),
+            ],
+            &[],
+        );
+        assert_eq!(report.tier_percent(TestCategory::Tier2Interactive), Some(50.0));
+        assert_eq!(report.tier_percent(TestCategory::Tier3RichOutput), None);
+    }
+
+    #[test]
+    fn test_unmet_requirements() {
+        let report = report(
+            vec![
+                record(TestCategory::Tier1Basic, TestResult::Pass),
+                record(TestCategory::Tier1Basic, TestResult::Unsupported),
+                record(
+                    TestCategory::Tier2Interactive,
+                    TestResult::PartialPass { score: 0.75, notes: String::new() },
+                ),
+            ],
+            &["tier1=100", "tier2=80", "tier3=10"],
+        );
+
+        let unmet: Vec<String> = report
+            .unmet_requirements()
+            .iter()
+            .map(|(req, actual)| format!("{} {:?}", req, actual))
+            .collect();
+        assert_eq!(unmet, vec!["tier2=80 Some(75.0)", "tier3=10 None"]);
+    }
+
+    #[test]
+    fn test_informational_records_are_not_scored() {
+        let mut startup = record(TestCategory::Tier1Basic, TestResult::Pass);
+        startup.informational = true;
+        let report = report(
+            vec![
+                startup,
+                record(
+                    TestCategory::Tier1Basic,
+                    TestResult::fail("x", FailureKind::KernelError),
+                ),
+            ],
+            &["tier1=50"],
+        );
+        assert_eq!(report.tier_percent(TestCategory::Tier1Basic), Some(0.0));
+        assert_eq!(report.tier_score(TestCategory::Tier1Basic), (0, 1));
+        assert_eq!((report.passed(), report.total()), (0, 1));
+        assert_eq!(report.unmet_requirements().len(), 1);
+        // Still listed with its tier
+        assert_eq!(report.tier_results(TestCategory::Tier1Basic).len(), 2);
+    }
+
+    #[test]
+    fn test_tier_requirement_parsing() {
+        let req: TierRequirement = "tier2=80".parse().unwrap();
+        assert_eq!(req.tier, TestCategory::Tier2Interactive);
+        assert_eq!(req.min_percent, 80.0);
+        assert_eq!("1=100".parse::<TierRequirement>().unwrap().tier, TestCategory::Tier1Basic);
+        assert!("tier5=10".parse::<TierRequirement>().is_err());
+        assert!("tier1=150".parse::<TierRequirement>().is_err());
+        assert!("tier1".parse::<TierRequirement>().is_err());
+    }
+
+    #[test]
+    fn test_isolation_only_serialized_when_checked() {
+        let mut report = report(vec![], &[]);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("isolation").is_none());
+
+        report.isolation = Some(IsolationReport {
+            state_mutating_tests: vec!["execute_stdout".to_string()],
+            error: None,
+        });
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["isolation"],
+            serde_json::json!({ "state_mutating_tests": ["execute_stdout"] })
+        );
+    }
+
+    #[test]
+    fn test_isolation_mode_defaults_for_older_reports() {
+        let mut report = report(vec![], &[]);
+        report.isolation_mode = IsolationMode::PerTier;
+        report.kernel_launches = 4;
+        let mut json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["isolation_mode"], "per-tier");
+        assert_eq!(json["kernel_launches"], 4);
+
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("isolation_mode");
+        fields.remove("kernel_launches");
+        let older: KernelReport = serde_json::from_value(json).unwrap();
+        assert_eq!(older.isolation_mode, IsolationMode::Shared);
+        assert_eq!(older.kernel_launches, 1);
+    }
+
+    #[test]
+    fn test_warmup_durations_serialized_as_millis() {
+        let mut report = report(vec![], &[]);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("warmup_durations").is_none());
+
+        report.warmup_durations = vec![Duration::from_millis(1520), Duration::from_millis(12)];
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["warmup_durations"], serde_json::json!([1520, 12]));
+        let read: KernelReport = serde_json::from_value(json).unwrap();
+        assert_eq!(read.warmup_durations, report.warmup_durations);
+    }
+
+    #[test]
+    fn test_kernel_died_keeps_exit_status() {
+        let result = TestResult::fail(
+            "Kernel process exited with signal: 11 (SIGSEGV)",
+            FailureKind::KernelDied {
+                status: "signal: 11 (SIGSEGV)".to_string(),
+            },
+        );
+        assert!(result.is_kernel_died());
+        assert!(!TestResult::Timeout.is_kernel_died());
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            json["kind"],
+            serde_json::json!({ "kernel_died": { "status": "signal: 11 (SIGSEGV)" } })
+        );
+        let read: TestResult = serde_json::from_value(json).unwrap();
+        assert!(read.is_kernel_died());
+    }
+
+    #[test]
+    fn test_kernel_exit_serialization() {
+        let mut report = report(vec![], &[]);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json.get("kernel_exit").is_none());
+
+        report.kernel_exit = Some(KernelExitStatus::CleanExit(1));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["kernel_exit"], serde_json::json!({ "clean_exit": 1 }));
+        let read: KernelReport = serde_json::from_value(json).unwrap();
+        assert_eq!(read.kernel_exit, report.kernel_exit);
+
+        let killed = serde_json::to_value(KernelExitStatus::ForceKilled).unwrap();
+        assert_eq!(killed, serde_json::json!("force_killed"));
+        assert!(KernelExitStatus::CleanExit(0).is_clean());
+        assert!(!KernelExitStatus::CleanExit(1).is_clean());
+        assert!(!KernelExitStatus::Signaled(11).is_clean());
+    }
+}
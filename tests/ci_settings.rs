@@ -0,0 +1,63 @@
+//! Precedence of the `--ci` preset against explicit flags.
+
+use jupyter_kernel_test::settings::{
+    EffectiveSettings, ExplicitSettings, OutputFormat, CI_REPORT_PATH,
+};
+use std::path::PathBuf;
+
+fn github_env(key: &str) -> Option<String> {
+    (key == "GITHUB_STEP_SUMMARY").then(|| "/tmp/step-summary.md".to_string())
+}
+
+#[test]
+fn ci_preset_defaults() {
+    let explicit = ExplicitSettings {
+        ci: true,
+        ..Default::default()
+    };
+    let settings = EffectiveSettings::resolve(&explicit, github_env);
+
+    assert_eq!(settings.format, OutputFormat::Json);
+    assert_eq!(settings.output, Some(PathBuf::from(CI_REPORT_PATH)));
+    assert_eq!(settings.step_summary, Some(PathBuf::from("/tmp/step-summary.md")));
+    assert!(settings.annotations);
+    assert!(settings.strict);
+}
+
+#[test]
+fn ci_with_explicit_terminal_format() {
+    let explicit = ExplicitSettings {
+        ci: true,
+        format: Some(OutputFormat::Terminal),
+        strict: Some(false),
+        ..Default::default()
+    };
+    let settings = EffectiveSettings::resolve(&explicit, github_env);
+
+    assert_eq!(settings.format, OutputFormat::Terminal);
+    // The JSON artifact path doesn't apply to terminal output
+    assert_eq!(settings.output, None);
+    assert!(!settings.strict);
+    assert!(settings.annotations);
+}
+
+#[test]
+fn no_ci_keeps_plain_defaults() {
+    let settings = EffectiveSettings::resolve(&ExplicitSettings::default(), github_env);
+
+    assert_eq!(settings.format, OutputFormat::Terminal);
+    assert_eq!(settings.output, None);
+    assert_eq!(settings.step_summary, None);
+    assert!(!settings.annotations);
+    assert!(!settings.strict);
+}
+
+#[test]
+fn step_summary_requires_env() {
+    let explicit = ExplicitSettings {
+        ci: true,
+        ..Default::default()
+    };
+    let settings = EffectiveSettings::resolve(&explicit, |_| None);
+    assert_eq!(settings.step_summary, None);
+}
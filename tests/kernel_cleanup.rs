@@ -0,0 +1,49 @@
+//! A launched kernel that is dropped without `shutdown()` must not outlive it.
+//!
+//! Needs an installed python3 kernelspec, so it only runs with
+//! `cargo test -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use jupyter_kernel_test::KernelUnderTest;
+use std::time::{Duration, Instant};
+
+/// Whether `pid` is still running; a zombie waiting to be reaped counts as gone.
+fn process_alive(pid: u32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat
+            .rsplit(')')
+            .next()
+            .is_some_and(|rest| rest.trim_start().starts_with('Z')),
+        Err(_) => false,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs an installed python3 kernel"]
+async fn dropped_kernel_is_killed_and_cleaned_up() {
+    let spec = runtimelib::find_kernelspec("python3")
+        .await
+        .expect("python3 kernelspec");
+    let kernel = KernelUnderTest::launch(spec, Duration::from_secs(10))
+        .await
+        .expect("kernel launches");
+    let pid = kernel.pid().expect("launched kernel has a pid");
+    let connection_path = kernel
+        .connection_path()
+        .expect("launched kernel has a connection file")
+        .to_path_buf();
+    assert!(process_alive(pid));
+
+    drop(kernel);
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while process_alive(pid) {
+        assert!(
+            Instant::now() < deadline,
+            "kernel {} still running 1s after drop",
+            pid
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(!connection_path.exists());
+}
@@ -0,0 +1,58 @@
+//! A kernel that dies while starting must fail the launch promptly, with its
+//! stderr, rather than leave the harness waiting on ports nobody will bind.
+#![cfg(unix)]
+
+use jupyter_kernel_test::harness::HarnessError;
+use jupyter_kernel_test::KernelUnderTest;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn kernel_exiting_at_startup_fails_the_launch() {
+    let builder = KernelUnderTest::command_builder(
+        "sh -c 'echo no such module >&2; exit 3' {connection_file}",
+        Some("exits-at-startup"),
+    )
+    .unwrap()
+    .startup_timeout(Duration::from_secs(60));
+
+    let started = Instant::now();
+    let err = match builder.launch().await {
+        Ok(_) => panic!("a kernel that exits can't launch"),
+        Err(e) => e,
+    };
+    assert!(started.elapsed() < Duration::from_secs(30), "{:?}", err);
+    match err {
+        HarnessError::ExitedDuringStartup(message) => {
+            assert!(message.contains("no such module"), "{}", message);
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn kernel_exiting_at_startup_is_relaunched_on_fresh_ports() {
+    // The kernel may have lost its ports to another process, so each launch
+    // gets new ones; every one of these fails, so all of them are tried
+    let launches =
+        std::env::temp_dir().join(format!("kernel-testbed-launches-{}", std::process::id()));
+    let command = format!(
+        "sh -c 'echo launched >> \"{}\"; exit 3' {{connection_file}}",
+        launches.display()
+    );
+    let builder = KernelUnderTest::command_builder(&command, Some("exits-at-startup"))
+        .unwrap()
+        .startup_timeout(Duration::from_secs(60));
+
+    let err = match builder.launch().await {
+        Ok(_) => panic!("a kernel that exits can't launch"),
+        Err(e) => e,
+    };
+    assert!(
+        matches!(err, HarnessError::ExitedDuringStartup(_)),
+        "{}",
+        err
+    );
+    let count = std::fs::read_to_string(&launches).unwrap().lines().count();
+    let _ = std::fs::remove_file(&launches);
+    assert!(count > 1, "launched {} time(s)", count);
+}
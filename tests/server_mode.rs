@@ -0,0 +1,267 @@
+//! Kernels started through a Jupyter Server: REST calls to start and delete
+//! them, and the channels websocket in between, against a stand-in server.
+
+use futures::{SinkExt, StreamExt};
+use jupyter_kernel_test::harness::HarnessError;
+use jupyter_kernel_test::{JupyterServer, KernelUnderTest};
+use jupyter_protocol::JupyterMessageContent;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+const TOKEN: &str = "secret";
+
+/// A Jupyter Server with one kernel, `fake`, that answers kernel_info and
+/// prints `hello` for any execute_request.
+struct FakeServer {
+    url: String,
+    /// Request lines of every request, in order
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeServer {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, log.clone()));
+            }
+        });
+        Self { url, requests }
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(mut stream: TcpStream, requests: Arc<Mutex<Vec<String>>>) {
+    // Peek so a websocket handshake is left for tungstenite to read
+    let mut peeked = vec![0; 4096];
+    let head = loop {
+        let n = stream.peek(&mut peeked).await.unwrap();
+        let text = String::from_utf8_lossy(&peeked[..n]).to_string();
+        if let Some(end) = text.find("\r\n\r\n") {
+            break text[..end + 4].to_string();
+        }
+        tokio::task::yield_now().await;
+    };
+    let request_line = head.lines().next().unwrap_or_default().to_string();
+    requests.lock().unwrap().push(request_line.clone());
+    let lower = head.to_ascii_lowercase();
+    let authorized = lower.contains(&format!("authorization: token {}", TOKEN));
+
+    if lower.contains("upgrade: websocket") && authorized {
+        let socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        kernel(socket).await;
+        return;
+    }
+
+    let mut discard = vec![0; head.len()];
+    stream.read_exact(&mut discard).await.unwrap();
+    let content_length = lower
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .map_or(0, |length| length.trim().parse().unwrap());
+    let mut body = vec![0; content_length];
+    stream.read_exact(&mut body).await.unwrap();
+
+    let mut words = request_line.split(' ');
+    let route = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let (status, body) = match route {
+        _ if !authorized => ("403 Forbidden", json!({"message": "Forbidden"})),
+        ("GET", "/api/kernelspecs") => ("200 OK", json!({"default": "fake", "kernelspecs": {}})),
+        ("POST", "/api/kernels") => {
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            if request["name"] == "fake" {
+                ("201 Created", json!({"id": "kernel-1", "name": "fake"}))
+            } else {
+                (
+                    "500 Internal Server Error",
+                    json!({"message": "No such kernel"}),
+                )
+            }
+        }
+        ("DELETE", "/api/kernels/kernel-1") => ("204 No Content", Value::Null),
+        _ => ("404 Not Found", json!({"message": "Not found"})),
+    };
+    let body = if body.is_null() {
+        String::new()
+    } else {
+        body.to_string()
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// Answer requests arriving on the channels websocket.
+async fn kernel(mut socket: WebSocketStream<TcpStream>) {
+    while let Some(Ok(frame)) = socket.next().await {
+        let Message::Text(text) = frame else {
+            continue;
+        };
+        let request: Value = serde_json::from_str(&text).unwrap();
+        let replies = match request["header"]["msg_type"].as_str() {
+            Some("kernel_info_request") => vec![reply(
+                &request,
+                "shell",
+                "kernel_info_reply",
+                json!({
+                    "status": "ok",
+                    "protocol_version": "5.3",
+                    "implementation": "fake",
+                    "implementation_version": "1.0",
+                    "language_info": {
+                        "name": "python",
+                        "version": "3",
+                        "mimetype": "text/x-python",
+                        "file_extension": ".py",
+                        "pygments_lexer": "python",
+                        "codemirror_mode": "python",
+                        "nbconvert_exporter": "python",
+                    },
+                    "banner": "",
+                    "help_links": [],
+                }),
+            )],
+            Some("execute_request") => vec![
+                reply(
+                    &request,
+                    "iopub",
+                    "status",
+                    json!({"execution_state": "busy"}),
+                ),
+                reply(
+                    &request,
+                    "iopub",
+                    "stream",
+                    json!({"name": "stdout", "text": "hello\n"}),
+                ),
+                reply(
+                    &request,
+                    "shell",
+                    "execute_reply",
+                    json!({"status": "ok", "execution_count": 1, "user_expressions": {}}),
+                ),
+                reply(
+                    &request,
+                    "iopub",
+                    "status",
+                    json!({"execution_state": "idle"}),
+                ),
+            ],
+            _ => Vec::new(),
+        };
+        for reply in replies {
+            socket.send(reply).await.unwrap();
+        }
+    }
+}
+
+fn reply(request: &Value, channel: &str, msg_type: &str, content: Value) -> Message {
+    let msg_id = format!(
+        "{}-{}",
+        request["header"]["msg_id"].as_str().unwrap(),
+        msg_type
+    );
+    let reply = json!({
+        "header": {
+            "msg_id": msg_id,
+            "session": "fake-kernel",
+            "username": "fake",
+            "date": "2026-01-01T00:00:00Z",
+            "msg_type": msg_type,
+            "version": "5.3",
+        },
+        "parent_header": request["header"],
+        "metadata": {},
+        "content": content,
+        "buffers": [],
+        "channel": channel,
+    });
+    Message::text(reply.to_string())
+}
+
+#[tokio::test]
+async fn kernel_runs_over_the_server_websocket() {
+    let fake = FakeServer::start().await;
+    let server = JupyterServer::new(&fake.url, Some(TOKEN)).unwrap();
+    assert_eq!(server.default_kernel_name().await.unwrap(), "fake");
+
+    let mut kernel = KernelUnderTest::server_builder(server, "fake")
+        .launch()
+        .await
+        .unwrap();
+    assert_eq!(kernel.kernel_info().unwrap().implementation, "fake");
+    assert!(!kernel.is_attached());
+
+    let (reply, iopub) = kernel.execute_and_collect("print('hello')").await.unwrap();
+    assert_eq!(reply.content.message_type(), "execute_reply");
+    let printed = iopub.iter().any(|msg| match &msg.content {
+        JupyterMessageContent::StreamContent(stream) => stream.text == "hello\n",
+        _ => false,
+    });
+    assert!(printed, "{:?}", iopub);
+
+    match kernel.heartbeat().await {
+        Err(HarnessError::NotAvailable(reason)) => {
+            assert!(reason.contains("Jupyter Server"), "{}", reason)
+        }
+        other => panic!("expected no heartbeat, got {:?}", other),
+    }
+
+    assert_eq!(kernel.shutdown().await.unwrap(), None);
+    let requests = fake.requests();
+    assert!(
+        requests
+            .iter()
+            .any(|line| line.starts_with("GET /api/kernels/kernel-1/channels?session_id=")),
+        "{:?}",
+        requests
+    );
+    assert_eq!(
+        requests.last().map(String::as_str),
+        Some("DELETE /api/kernels/kernel-1 HTTP/1.1")
+    );
+}
+
+#[tokio::test]
+async fn server_refusal_fails_the_launch() {
+    let fake = FakeServer::start().await;
+
+    let server = JupyterServer::new(&fake.url, Some("wrong")).unwrap();
+    let err = match KernelUnderTest::server_builder(server, "fake")
+        .launch()
+        .await
+    {
+        Ok(_) => panic!("a refused launch can't succeed"),
+        Err(e) => e,
+    };
+    match err {
+        HarnessError::LaunchFailed(message) => {
+            assert!(message.contains("403 Forbidden: Forbidden"), "{}", message)
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+
+    let server = JupyterServer::new(&fake.url, Some(TOKEN)).unwrap();
+    let err = match KernelUnderTest::server_builder(server, "missing")
+        .launch()
+        .await
+    {
+        Ok(_) => panic!("an unknown kernel can't launch"),
+        Err(e) => e,
+    };
+    assert!(err.to_string().contains("No such kernel"), "{}", err);
+}
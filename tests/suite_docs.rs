@@ -0,0 +1,32 @@
+//! Golden-file check for the generated suite reference.
+//!
+//! Run with `UPDATE_GOLDEN=1` to accept intentional metadata changes.
+
+use jupyter_kernel_test::{all_finalizers, all_tests, render_suite_docs};
+use std::path::Path;
+
+#[test]
+fn suite_docs_match_golden() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/suite_docs.md");
+    let rendered = render_suite_docs(&all_tests(), &all_finalizers(), &[]);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden, &rendered).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&golden).unwrap();
+    assert!(
+        rendered == expected,
+        "generated docs differ from {}; rerun with UPDATE_GOLDEN=1 and review the diff",
+        golden.display()
+    );
+}
+
+#[test]
+fn every_test_appears_in_docs() {
+    let rendered = render_suite_docs(&all_tests(), &all_finalizers(), &[]);
+    for test in all_tests() {
+        assert!(rendered.contains(&format!("### `{}`", test.name)), "{}", test.name);
+    }
+}